@@ -0,0 +1,117 @@
+use crate::error::*;
+use crate::hash::*;
+use crate::ignore::*;
+use crate::index::*;
+use crate::object::*;
+use crate::repository::*;
+use crate::status::*;
+
+use std::fs;
+
+use std::path::Path;
+use std::collections::BTreeSet;
+
+/* Stores the file as a blob, a symlink being stored as its target path */
+fn write_blob(repository: &Repository, file: &Path) -> Result<Hash, Error> {
+    let content = read_worktree_file(file)?;
+    repository.write_object(ObjectType::Blob, content)
+}
+
+/*
+ * The index entries for `name` (relative to the root of the working tree,
+ * "" being the root) and the files below it. Tracked files are added even
+ * when they match an ignore pattern.
+ */
+fn add_entries(
+    repository: &Repository,
+    name: &str,
+    ignore: &mut Ignore,
+    tracked: &BTreeSet<String>,
+) -> Result<Vec<IndexEntry>, Error> {
+    let mut entries = Vec::new();
+    let file = repository.worktree_path(name)?;
+    /* A symlink to a directory is added as a symlink, not descended into */
+    let stat = fs::symlink_metadata(&file)?;
+    let is_dir = stat.is_dir();
+    if !name.is_empty() && !tracked.contains(name) {
+        let dir_prefix = format!("{}/", name);
+        let tracked_inside = is_dir && tracked.iter().any(|file| file.starts_with(&dir_prefix));
+        if !tracked_inside && ignore.is_ignored(name, is_dir)? {
+            println!("[INFO] ignoring {}", name);
+            return Ok(entries)
+        }
+    }
+
+    if is_dir {
+        for entry in fs::read_dir(&file)? {
+            let entry = entry?;
+            if entry.file_name() == DOT_GIT {
+                continue
+            }
+
+            let file_name = entry.file_name();
+            let file_name = file_name.to_string_lossy();
+            let sub_name = match name {
+                "" => file_name.to_string(),
+                name => format!("{}/{}", name, file_name)
+            };
+            let sub_directory = add_entries(repository, &sub_name, ignore, tracked)?;
+            entries.extend(sub_directory);
+        }
+    } else {
+        let hash = write_blob(repository, &file)?;
+        let entry = IndexEntry::create(hash, worktree_mode(&stat), name, &file)?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/* Stages the file or directory at `path`, relative to the current directory */
+pub fn add(repository: &Repository, path: &Path) -> Result<(), Error> {
+    // 1. create objects
+    let name = repository.relative_path(path)?;
+    let index = repository.read_index()?;
+    let tracked = index.entries.iter().map(|entry| entry.name.clone()).collect();
+    let mut ignore = Ignore::load(repository)?;
+    let new_entries = add_entries(repository, &name, &mut ignore, &tracked)?;
+    let updated_index = index.extend(new_entries);
+
+    // 2. write to index
+    repository.write_index(&updated_index)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test_util::*;
+
+    use crate::tree::*;
+
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    #[test]
+    fn add_records_executables_and_symlinks() {
+        let repository = TempRepo::new("add");
+        let dir = repository.path();
+        fs::create_dir_all(dir.join("bin")).unwrap();
+        fs::write(dir.join("bin/run.sh"), "#!/bin/sh\n").unwrap();
+        fs::set_permissions(dir.join("bin/run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(dir.join("README"), "hello\n").unwrap();
+        symlink("bin", dir.join("tools")).unwrap();
+
+        let mut ignore = Ignore::new(dir.to_path_buf(), Vec::new());
+        let mut entries = add_entries(&repository, "", &mut ignore, &BTreeSet::new()).unwrap();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let modes: Vec<(&str, FileMode)> = entries.iter().map(|entry| (entry.name.as_str(), entry.mode())).collect();
+        assert_eq!(modes, [("README", FileMode::Regular), ("bin/run.sh", FileMode::Executable), ("tools", FileMode::Symlink)]);
+        assert_eq!(repository.read_object(&entries[2].key).unwrap().content, b"bin");
+
+        let tree = TreeCache::from_index(Index::new(entries)).write(&repository).unwrap();
+        let tree = Tree::read(&repository, &tree).unwrap();
+        let bin = Tree::read(&repository, &tree.entries[1].key).unwrap();
+        assert_eq!(bin.entries[0].mode, FileMode::Executable);
+        assert_eq!(tree.entries[2].mode, FileMode::Symlink);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::error::*;
+use crate::hash::*;
+use crate::command::*;
+use crate::log::*;
+use crate::refs::*;
+use crate::tag::*;
+use crate::repository::*;
+
+fn branch_ref(name: &str) -> Result<String, Error> {
+    let ref_name = format!("refs/heads/{}", name);
+    if !is_valid_ref_name(&ref_name) || name == "HEAD" {
+        return Err(Error::BadRef(format!("'{}' is not a valid branch name", name)))
+    }
+    Ok(ref_name)
+}
+
+fn short(hash: &Hash) -> String {
+    hash.to_string()[..7].to_string()
+}
+
+fn not_found(name: &str) -> Error {
+    Error::BadRef(format!("Branch '{}' not found", name))
+}
+
+pub fn list_branches(repository: &Repository) -> Result<(), Error> {
+    let current = repository.current_branch()?;
+    if let Head::Detached(hash) = repository.read_head()? {
+        println!("* (HEAD detached at {})", short(&hash));
+    }
+    for (name, _) in repository.list_refs("refs/heads")? {
+        let name = &name["refs/heads/".len()..];
+        let marker = if current.as_deref() == Some(name) { '*' } else { ' ' };
+        println!("{} {}", marker, name);
+    }
+    Ok(())
+}
+
+pub fn create_branch(repository: &Repository, name: &str, start: Option<&str>, force: bool) -> Result<(), Error> {
+    let ref_name = branch_ref(name)?;
+    if repository.read_ref(&ref_name)?.is_some() {
+        if !force {
+            return Err(Error::Conflict(format!("A branch named '{}' already exists", name)))
+        }
+        if repository.current_branch()?.as_deref() == Some(name) {
+            return Err(Error::InvalidArgument(format!("Cannot force update the current branch '{}'", name)))
+        }
+    }
+
+    let start = peel_to_commit(repository, repository.resolve_revision(start.unwrap_or("HEAD"))?)?;
+    repository.write_ref(&ref_name, &start)
+}
+
+pub fn rename_branch(repository: &Repository, old: Option<&str>, new: &str, force: bool) -> Result<(), Error> {
+    let current = repository.current_branch()?;
+    let old = match old.or(current.as_deref()) {
+        Some(old) => old.to_string(),
+        None => return Err(Error::InvalidArgument("HEAD is detached, no branch to rename".into()))
+    };
+    let old_ref = branch_ref(&old)?;
+    let new_ref = branch_ref(new)?;
+
+    let hash = repository.read_ref(&old_ref)?;
+    let is_current = current.as_deref() == Some(old.as_str());
+    /* The current branch may be unborn, then only HEAD changes */
+    if hash.is_none() && !is_current {
+        return Err(not_found(&old))
+    }
+    if old_ref != new_ref && repository.read_ref(&new_ref)?.is_some() && !force {
+        return Err(Error::Conflict(format!("A branch named '{}' already exists", new)))
+    }
+
+    if let Some(hash) = hash {
+        repository.delete_ref(&old_ref)?;
+        repository.write_ref(&new_ref, &hash)?;
+    }
+    if is_current {
+        repository.write_head(&Head::Branch(new_ref))?;
+    }
+    Ok(())
+}
+
+/*
+ * Deletes branches. Without `force` a branch must be merged into HEAD, so
+ * no commits are lost, and the checked-out branch can never be deleted.
+ */
+pub fn delete_branch(repository: &Repository, name: &str, force: bool) -> Result<(), Error> {
+    let ref_name = branch_ref(name)?;
+    let hash = repository.read_ref(&ref_name)?.ok_or_else(|| not_found(name))?;
+
+    if repository.current_branch()?.as_deref() == Some(name) {
+        return Err(Error::InvalidArgument(format!("Cannot delete branch '{}' checked out", name)))
+    }
+    if !force {
+        let merged = match repository.read_ref("HEAD")? {
+            Some(head) => is_ancestor(repository, &hash, &head)?,
+            None => false
+        };
+        if !merged {
+            return Err(Error::InvalidArgument(format!(
+                "The branch '{}' is not fully merged. If you are sure you want to delete it, run 'nit branch -D {}'",
+                name, name
+            )))
+        }
+    }
+
+    repository.delete_ref(&ref_name)?;
+    println!("Deleted branch {} (was {}).", name, short(&hash));
+    Ok(())
+}
+
+pub fn branch(repository: &Repository, action: BranchAction) -> Result<(), Error> {
+    match action {
+        BranchAction::List => list_branches(repository)?,
+        BranchAction::ShowCurrent => {
+            if let Some(name) = repository.current_branch()? {
+                println!("{}", name);
+            }
+        },
+        BranchAction::Create { name, start, force } => create_branch(repository, &name, start.as_deref(), force)?,
+        BranchAction::Rename { old, new, force } => rename_branch(repository, old.as_deref(), &new, force)?,
+        BranchAction::Delete { names, force } => {
+            for name in names {
+                delete_branch(repository, &name, force)?;
+            }
+        }
+    }
+    Ok(())
+}
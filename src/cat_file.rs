@@ -0,0 +1,42 @@
+use crate::error::*;
+use crate::command::*;
+use crate::object::*;
+use crate::repository::*;
+use crate::tree::*;
+
+use std::io;
+
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+
+/*
+ * Writes the object for people to read. Only trees are formatted, the
+ * other objects are written as they are stored, and names are written as
+ * their bytes so that tools can inspect exactly what was written.
+ */
+fn pretty_print(object: Object) -> Result<(), Error> {
+    let mut stdout = io::stdout().lock();
+    match object.kind {
+        ObjectType::Tree => {
+            for entry in Tree::parse(&object.content)?.entries {
+                write!(stdout, "{:06o} {} {}\t", entry.mode, entry.kind(), entry.key)?;
+                stdout.write_all(entry.name.as_os_str().as_bytes())?;
+                stdout.write_all(b"\n")?;
+            }
+        },
+        ObjectType::Blob | ObjectType::Commit | ObjectType::Tag => stdout.write_all(&object.content)?
+    }
+    Ok(())
+}
+
+pub fn cat_file(repository: &Repository, mode: CatFileMode, object: &str) -> Result<(), Error> {
+    let hash = repository.resolve_revision(object)?;
+    let object = repository.read_object(&hash)?;
+    match mode {
+        CatFileMode::Type => println!("{}", object.kind),
+        CatFileMode::Size => println!("{}", object.content.len()),
+        CatFileMode::Pretty => pretty_print(object)?,
+        CatFileMode::Exists => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,435 @@
+use crate::error::*;
+use crate::hash::*;
+use crate::branch::*;
+use crate::command::*;
+use crate::commit::*;
+use crate::diff::*;
+use crate::index::*;
+use crate::object::*;
+use crate::refs::*;
+use crate::status::*;
+use crate::tag::*;
+use crate::tree::*;
+use crate::repository::*;
+
+use std::fs;
+use std::io;
+
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::collections::{BTreeMap, BTreeSet};
+
+/* Removes a file and the directories below `root` it leaves empty */
+fn remove_worktree_file(root: &Path, path: &Path) -> Result<(), Error> {
+    match fs::remove_file(path) {
+        Ok(()) => {},
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {},
+        Err(err) => return Err(err.into())
+    }
+
+    let mut dir = path.parent();
+    while let Some(parent) = dir.filter(|parent| parent.starts_with(root) && *parent != root) {
+        if fs::remove_dir(parent).is_err() {
+            break
+        }
+        dir = parent.parent();
+    }
+    Ok(())
+}
+
+/* Writes a blob to the working tree as a file, executable or symlink */
+fn write_worktree_file(repository: &Repository, path: &Path, mode: FileMode, key: &Hash) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::symlink_metadata(path).is_ok_and(|stat| !stat.is_dir()) {
+        fs::remove_file(path)?;
+    }
+
+    /* A submodule is only an empty directory until it is cloned */
+    if mode == FileMode::Gitlink {
+        return Ok(fs::create_dir_all(path)?)
+    }
+
+    let content = repository.read_object_of_kind(key, ObjectType::Blob)?.content;
+    if mode == FileMode::Symlink {
+        return Ok(symlink(OsStr::from_bytes(&content), path)?)
+    }
+
+    fs::write(path, content)?;
+    let permissions = if mode == FileMode::Executable { 0o755 } else { 0o644 };
+    Ok(fs::set_permissions(path, fs::Permissions::from_mode(permissions))?)
+}
+
+/*
+ * Whether checking out `new` for the path would lose local changes, i.e.
+ * the index or working tree differs from HEAD (`old`) and from `new`.
+ * Files that are not tracked at all must not be overwritten either.
+ */
+fn would_overwrite(
+    repository: &Repository,
+    path: &str,
+    old: Option<&(FileMode, Hash)>,
+    new: Option<&(FileMode, Hash)>,
+    entries: &BTreeMap<String, IndexEntry>,
+    index_mtime: u32,
+) -> Result<bool, Error> {
+    let Some(entry) = entries.get(path) else {
+        /* An untracked file where the file or one of its directories goes */
+        let root = repository.work_tree()?;
+        let untracked_file = |path: &Path| !entries.contains_key(path.to_string_lossy().as_ref())
+            && fs::symlink_metadata(root.join(path)).is_ok_and(|stat| !stat.is_dir());
+        return Ok(new.is_some() && Path::new(path).ancestors().any(untracked_file))
+    };
+
+    let staged = Some((entry.mode(), entry.key.clone()));
+    let worktree = worktree_entry(repository, entry, index_mtime)?;
+    let clean = staged.as_ref() == old && worktree == staged;
+    let already_new = staged.as_ref() == new && worktree.as_ref() == new;
+    let already_deleted = new.is_none() && worktree.is_none();
+    Ok(!clean && !already_new && !already_deleted)
+}
+
+/*
+ * Replaces the HEAD tree with `new` in the index and working tree. Paths
+ * that are the same in both trees keep their local changes, like git's
+ * two-way merge. Without `force`, nothing is changed if a local change
+ * would be overwritten.
+ */
+fn switch_tree(
+    repository: &Repository,
+    old: &BTreeMap<String, (FileMode, Hash)>,
+    new: &BTreeMap<String, (FileMode, Hash)>,
+    force: bool,
+) -> Result<(), Error> {
+    let mut index = repository.read_index()?;
+    let index_mtime = repository.index_mtime()?;
+    let mut entries: BTreeMap<String, IndexEntry> = std::mem::take(&mut index.entries)
+        .into_iter()
+        .map(|entry| (entry.name.clone(), entry))
+        .collect();
+
+    /* Forcing resets everything to `new`, including staged files */
+    let mut paths: BTreeSet<String> = old.keys().chain(new.keys()).cloned().collect();
+    if force {
+        paths.extend(entries.keys().cloned());
+    } else {
+        paths.retain(|path| old.get(path) != new.get(path));
+
+        let mut conflicts = Vec::new();
+        for path in &paths {
+            if would_overwrite(repository, path, old.get(path), new.get(path), &entries, index_mtime)? {
+                conflicts.push(path.clone());
+            }
+        }
+        if !conflicts.is_empty() {
+            return Err(Error::Conflict(format!(
+                "Your local changes to the following files would be overwritten by checkout:\n\t{}\n\
+                Please commit your changes or stash them before you switch branches.",
+                conflicts.join("\n\t")
+            )))
+        }
+    }
+
+    /* Removals go first, so files and directories may swap places */
+    for path in &paths {
+        if !new.contains_key(path) {
+            remove_worktree_file(repository.work_tree()?, &repository.worktree_path(path)?)?;
+            entries.remove(path);
+            index.invalidate_cache_tree(path);
+        }
+    }
+    for path in &paths {
+        if let Some((mode, key)) = new.get(path) {
+            let unchanged = entries.get(path).is_some_and(|entry|
+                entry.mode() == *mode
+                    && entry.key == *key
+                    && worktree_entry(repository, entry, index_mtime).ok().flatten() == Some((*mode, key.clone()))
+            );
+            if !unchanged {
+                let file = repository.worktree_path(path)?;
+                write_worktree_file(repository, &file, *mode, key)?;
+                entries.insert(path.clone(), IndexEntry::create(key.clone(), *mode, path, &file)?);
+                index.invalidate_cache_tree(path);
+            }
+        }
+    }
+
+    /*
+     * The other extensions are kept, like git does. The untracked cache
+     * stays valid as every path added or removed here was also written or
+     * removed in the working tree, changing the directory stat data it
+     * checks.
+     */
+    index.entries = entries.into_values().collect();
+    index.header.num_entries = index.entries.len() as u32;
+    repository.write_index(&index)
+}
+
+fn tree_entries(repository: &Repository, commit: Option<&Hash>) -> Result<BTreeMap<String, (FileMode, Hash)>, Error> {
+    let Some(commit) = commit else {
+        return Ok(BTreeMap::new())
+    };
+    Ok(Tree::read(repository, &Commit::read(repository, commit)?.tree)?
+        .flatten(repository, "")?
+        .into_iter()
+        .map(|(path, entry)| (path, (entry.mode, entry.key)))
+        .collect())
+}
+
+/*
+ * Restores paths (relative to the current directory) from a revision into
+ * the index and working tree, or from the index into the working tree when
+ * no revision is given.
+ */
+pub fn checkout_paths(repository: &Repository, revision: Option<&str>, paths: &[PathBuf]) -> Result<(), Error> {
+    let pathspecs = repository.relative_paths(paths)?;
+    let mut index = repository.read_index()?;
+    let source = match revision {
+        Some(revision) => Side::tree(repository, revision)?.entries,
+        None => Side::index(&index).entries
+    };
+
+    for (path, pathspec) in paths.iter().zip(&pathspecs) {
+        if !source.keys().any(|file| matches_paths(file, std::slice::from_ref(pathspec))) {
+            return Err(Error::InvalidArgument(format!(
+                "pathspec '{}' did not match any file(s) known to nit",
+                path.display()
+            )))
+        }
+    }
+
+    let mut restored = Vec::new();
+    for (path, (mode, key)) in source.iter().filter(|(file, _)| matches_paths(file, &pathspecs)) {
+        let file = repository.worktree_path(path)?;
+        write_worktree_file(repository, &file, *mode, key)?;
+        restored.push(IndexEntry::create(key.clone(), *mode, path, &file)?);
+    }
+
+    /* Restoring from the index only refreshes the stat data of its entries */
+    index = index.extend(restored);
+    repository.write_index(&index)
+}
+
+/*
+ * Checks out a branch (HEAD follows it) or any other commit (HEAD is
+ * detached), optionally creating a new branch at `target` first.
+ */
+pub fn checkout_revision(
+    repository: &Repository,
+    target: Option<&str>,
+    new_branch: Option<&str>,
+    detach: bool,
+    force: bool,
+) -> Result<(), Error> {
+    let current = repository.read_ref("HEAD")?;
+    let target_commit = match target {
+        Some(target) => Some(peel_to_commit(repository, repository.resolve_revision(target)?)?),
+        None => current.clone()
+    };
+
+    let branch = match (new_branch, target) {
+        (Some(name), _) => Some(name.to_string()),
+        (None, Some(target)) if !detach && repository.read_ref(&format!("refs/heads/{}", target))?.is_some() => {
+            Some(target.to_string())
+        },
+        _ => None
+    };
+
+    if let Some(name) = new_branch {
+        let ref_name = format!("refs/heads/{}", name);
+        if !is_valid_ref_name(&ref_name) {
+            return Err(Error::BadRef(format!("'{}' is not a valid branch name", name)))
+        }
+        if repository.read_ref(&ref_name)?.is_some() {
+            return Err(Error::Conflict(format!("A branch named '{}' already exists", name)))
+        }
+    }
+
+    let old = tree_entries(repository, current.as_ref())?;
+    let new = tree_entries(repository, target_commit.as_ref())?;
+    switch_tree(repository, &old, &new, force)?;
+
+    /* On an unborn branch the new branch is unborn as well */
+    if let (Some(name), Some(_)) = (new_branch, &target_commit) {
+        create_branch(repository, name, target, false)?;
+    }
+
+    match (branch, target_commit) {
+        (Some(name), _) => {
+            let previous = repository.current_branch()?;
+            repository.write_head(&Head::Branch(format!("refs/heads/{}", name)))?;
+            if new_branch.is_some() {
+                println!("Switched to a new branch '{}'", name);
+            } else if previous.as_deref() == Some(name.as_str()) {
+                println!("Already on '{}'", name);
+            } else {
+                println!("Switched to branch '{}'", name);
+            }
+        },
+        (None, Some(commit)) => {
+            repository.write_head(&Head::Detached(commit.clone()))?;
+            let subject = Commit::read(repository, &commit)?.subject().to_string();
+            println!("HEAD is now at {} {}", &commit.to_string()[..7], subject);
+        },
+        (None, None) => {
+            return Err(Error::InvalidArgument("You are on a branch yet to be born".into()))
+        }
+    }
+    Ok(())
+}
+
+pub fn checkout(repository: &Repository, options: CheckoutOptions) -> Result<(), Error> {
+    let CheckoutOptions { target, new_branch, detach, force, paths } = options;
+
+    if !paths.is_empty() {
+        return checkout_paths(repository, target.as_deref(), &paths)
+    }
+
+    /* Like git, a single argument that is no revision is a path */
+    if let Some(target) = target.as_deref()
+        && new_branch.is_none()
+        && repository.resolve_revision(target).is_err()
+        && Path::new(target).exists() {
+        return checkout_paths(repository, None, &[PathBuf::from(target)])
+    }
+
+    checkout_revision(repository, target.as_deref(), new_branch.as_deref(), detach, force)
+}
+
+/* Like checkout, but only switches branches unless asked to detach HEAD */
+pub fn switch(repository: &Repository, options: CheckoutOptions) -> Result<(), Error> {
+    let CheckoutOptions { target, new_branch, detach, force, .. } = options;
+
+    if let Some(target) = target.as_deref()
+        && new_branch.is_none()
+        && !detach
+        && repository.read_ref(&format!("refs/heads/{}", target))?.is_none() {
+        return Err(Error::InvalidArgument(format!(
+            "'{}' is not a branch, use --detach to switch to a commit",
+            target
+        )))
+    }
+
+    checkout_revision(repository, target.as_deref(), new_branch.as_deref(), detach, force)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::index_extension::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn remove_file_prunes_empty_directories() {
+        let dir = TempDir::new("checkout");
+        fs::create_dir_all(dir.join("src/nested")).unwrap();
+        fs::write(dir.join("keep"), "kept").unwrap();
+        fs::write(dir.join("src/nested/file"), "removed").unwrap();
+
+        remove_worktree_file(dir.path(), &dir.join("src/nested/file")).unwrap();
+
+        assert!(!dir.join("src").exists());
+        assert!(dir.join("keep").exists());
+    }
+
+    #[test]
+    fn write_executable_and_symlink() {
+        let repository = TempRepo::new("checkout-modes");
+        let dir = repository.path();
+        let script = repository.write_object(ObjectType::Blob, b"#!/bin/sh\n".to_vec()).unwrap();
+        let target = repository.write_object(ObjectType::Blob, b"bin/run.sh".to_vec()).unwrap();
+
+        write_worktree_file(&repository, &dir.join("bin/run.sh"), FileMode::Executable, &script).unwrap();
+        write_worktree_file(&repository, &dir.join("run"), FileMode::Symlink, &target).unwrap();
+
+        let stat = fs::symlink_metadata(dir.join("bin/run.sh")).unwrap();
+        assert_eq!(stat.permissions().mode() & 0o777, 0o755);
+        assert_eq!(fs::read_link(dir.join("run")).unwrap(), Path::new("bin/run.sh"));
+        assert_eq!(fs::read(dir.join("run")).unwrap(), b"#!/bin/sh\n");
+
+        let latin1 = repository.write_object(ObjectType::Blob, b"caf\xe9".to_vec()).unwrap();
+        write_worktree_file(&repository, &dir.join("latin1"), FileMode::Symlink, &latin1).unwrap();
+        assert_eq!(fs::read_link(dir.join("latin1")).unwrap().as_os_str().as_bytes(), b"caf\xe9");
+    }
+
+    #[test]
+    fn switching_branches_keeps_index_extensions_and_version() {
+        let repository = TempRepo::new("checkout-extensions");
+        let dir = repository.path();
+        fs::write(repository.git_path("config"), "[user]\n\tname = Nit\n\temail = nit@example.com\n").unwrap();
+        let commit = |message: &str| {
+            let options = CommitOptions { message: message.to_string(), author: None, date: None };
+            crate::commit::commit(&repository, options).unwrap()
+        };
+
+        fs::write(dir.join("README"), "hello\n").unwrap();
+        crate::add::add(&repository, &dir.join("README")).unwrap();
+        let main = commit("Initial commit\n");
+        checkout_revision(&repository, None, Some("topic"), false, false).unwrap();
+        fs::create_dir(dir.join("src")).unwrap();
+        fs::write(dir.join("src/topic.rs"), "fn main() {}\n").unwrap();
+        crate::add::add(&repository, &dir.join("src")).unwrap();
+        commit("Add topic\n");
+
+        let mut index = repository.read_index().unwrap();
+        let resolved = ResolveUndo { path: "README".into(), stages: [Some((0o100644, main.clone())), None, None] };
+        index.extensions.push(Extension::ResolveUndo(vec![resolved]));
+        index.extensions.push(Extension::Untracked(b"untracked cache".to_vec()));
+        index.header.version = 4;
+        repository.write_index(&index).unwrap();
+
+        checkout_revision(&repository, Some("main"), None, false, false).unwrap();
+
+        let index = repository.read_index().unwrap();
+        let names: Vec<&str> = index.entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["README"]);
+        assert!(!dir.join("src").exists());
+        assert!(index.extensions.iter().any(|extension| matches!(extension, Extension::ResolveUndo(_))));
+        assert!(index.extensions.contains(&Extension::Untracked(b"untracked cache".to_vec())));
+        assert!(!index.cache_tree().unwrap().is_valid());
+        assert_eq!(index.header.version, 4);
+
+        fs::write(dir.join("README"), "changed\n").unwrap();
+        checkout_paths(&repository, None, &[dir.join("README")]).unwrap();
+        assert_eq!(fs::read(dir.join("README")).unwrap(), b"hello\n");
+        assert_eq!(repository.read_index().unwrap().header.version, 4);
+    }
+
+    #[test]
+    fn commit_after_switch_leaves_out_removed_files() {
+        let repository = TempRepo::new("checkout-cache-tree");
+        let dir = repository.path();
+        fs::write(repository.git_path("config"), "[user]\n\tname = Nit\n\temail = nit@example.com\n").unwrap();
+        let commit = |message: &str| {
+            let options = CommitOptions { message: message.to_string(), author: None, date: None };
+            crate::commit::commit(&repository, options).unwrap()
+        };
+
+        fs::create_dir(dir.join("src")).unwrap();
+        fs::write(dir.join("src/a.rs"), "a\n").unwrap();
+        crate::add::add(&repository, &dir.join("src")).unwrap();
+        let main = commit("Add a\n");
+
+        /* The same number of entries below src, so only invalidation shows the change */
+        checkout_revision(&repository, None, Some("topic"), false, false).unwrap();
+        fs::remove_file(dir.join("src/a.rs")).unwrap();
+        fs::write(dir.join("src/b.rs"), "b\n").unwrap();
+        let b = repository.write_object(ObjectType::Blob, b"b\n".to_vec()).unwrap();
+        let mut index = repository.read_index().unwrap();
+        index.entries.clear();
+        let index = index.extend(vec![IndexEntry::create(b, FileMode::Regular, "src/b.rs", &dir.join("src/b.rs")).unwrap()]);
+        repository.write_index(&index).unwrap();
+        commit("Replace a with b\n");
+
+        checkout_revision(&repository, Some("main"), None, false, false).unwrap();
+        let again = commit("Same tree\n");
+
+        assert!(dir.join("src/a.rs").exists() && !dir.join("src/b.rs").exists());
+        let tree = |hash: &Hash| Commit::read(&repository, hash).unwrap().tree;
+        assert_eq!(tree(&again), tree(&main));
+    }
+}
@@ -1,31 +1,896 @@
+use crate::pack::{DEFAULT_DEPTH, DEFAULT_WINDOW};
+
 use std::path::PathBuf;
 
+#[derive(Debug)]
+pub enum CatFileMode {
+    Type,
+    Size,
+    Pretty,
+    Exists
+}
+
+#[derive(Debug)]
+pub struct CommitOptions {
+    pub message: String,
+    /* "Name <email>" overriding the configured author */
+    pub author: Option<String>,
+    pub date: Option<String>
+}
+
+#[derive(Debug)]
+pub enum TagAction {
+    /* The tags matching any of the patterns, or all tags */
+    List(Vec<String>),
+    Create {
+        name: String,
+        target: Option<String>,
+        message: Option<String>,
+        force: bool
+    },
+    Delete(Vec<String>)
+}
+
+#[derive(Debug)]
+pub enum BranchAction {
+    List,
+    ShowCurrent,
+    Create {
+        name: String,
+        start: Option<String>,
+        force: bool
+    },
+    /* Renames the current branch when `old` is None */
+    Rename {
+        old: Option<String>,
+        new: String,
+        force: bool
+    },
+    Delete {
+        names: Vec<String>,
+        force: bool
+    }
+}
+
+#[derive(Debug)]
+pub struct CheckoutOptions {
+    /* Branch or commit to check out, or the source of `paths` */
+    pub target: Option<String>,
+    pub new_branch: Option<String>,
+    pub detach: bool,
+    /* Throw away local changes */
+    pub force: bool,
+    pub paths: Vec<PathBuf>
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LogFormat {
+    Medium,
+    Oneline,
+    /* Format string with placeholders, e.g. "%h %s" */
+    Custom(String)
+}
+
+/* How dates are shown, as selected by `--date` */
+#[derive(Debug, PartialEq)]
+pub enum DateFormat {
+    Default,
+    /* The default format in the local timezone */
+    Local,
+    Iso,
+    IsoStrict,
+    Rfc,
+    Short,
+    Relative,
+    /* "<timestamp> <offset>" as stored in the object */
+    Raw,
+    Unix
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LogOrder {
+    /* Newest commit first as the graph is walked */
+    Date,
+    /* Children before parents, newest first otherwise */
+    DateOrder,
+    /* Children before parents, keeping lines of history together */
+    Topo
+}
+
+#[derive(Debug)]
+pub struct LogOptions {
+    pub revisions: Vec<String>,
+    pub paths: Vec<PathBuf>,
+    pub format: LogFormat,
+    pub date: DateFormat,
+    pub order: LogOrder,
+    pub max_count: Option<usize>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub author: Option<String>
+}
+
+#[derive(Debug)]
+pub enum StatusFormat {
+    Long,
+    Short,
+    PorcelainV1,
+    PorcelainV2
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DiffOutput {
+    Patch,
+    Stat,
+    Numstat,
+    NameOnly,
+    NameStatus
+}
+
+#[derive(Debug)]
+pub struct DiffOptions {
+    /* Compare against the index instead of the working tree */
+    pub cached: bool,
+    /* Number of unchanged lines around each hunk */
+    pub context: usize,
+    pub output: DiffOutput,
+    pub revisions: Vec<String>,
+    pub paths: Vec<PathBuf>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigScope {
+    System,
+    Global,
+    Local,
+    Worktree,
+    File(PathBuf)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigType {
+    Bool,
+    Int
+}
+
+#[derive(Debug)]
+pub enum ConfigAction {
+    Get {
+        key: String,
+        all: bool
+    },
+    Set {
+        key: String,
+        value: String,
+        /* Add another value instead of replacing */
+        add: bool
+    },
+    Unset {
+        key: String,
+        all: bool
+    },
+    List
+}
+
+#[derive(Debug)]
+pub struct ConfigOptions {
+    pub action: ConfigAction,
+    /* The file to read or write, all scopes are read when None */
+    pub scope: Option<ConfigScope>,
+    pub value_type: Option<ConfigType>
+}
+
+#[derive(Debug)]
+pub struct RepackOptions {
+    /* Pack every reachable object instead of only the unpacked ones */
+    pub all: bool,
+    /* Keep the unreachable objects of removed packs as loose objects */
+    pub unpack_unreachable: bool,
+    /* Remove the packs made redundant and the packed loose objects */
+    pub delete: bool,
+    /* How many objects to try as a delta base, and how long delta chains get */
+    pub window: usize,
+    pub depth: usize
+}
+
+/* Options given before the command, e.g. `nit -C src status` */
+#[derive(Debug, Default)]
+pub struct GlobalOptions {
+    /* Directories to change to in order, each relative to the previous one */
+    pub directories: Vec<PathBuf>,
+    pub git_dir: Option<PathBuf>,
+    pub work_tree: Option<PathBuf>
+}
+
+impl GlobalOptions {
+    /* Splits off the global options, leaving the program name, command and its arguments */
+    pub fn parse(args: Vec<String>) -> Result<(Self, Vec<String>), String> {
+        let mut options = GlobalOptions::default();
+        let mut args = args.into_iter();
+        let mut rest: Vec<String> = args.next().into_iter().collect();
+
+        while let Some(argument) = args.next() {
+            let mut value = |name: &str| args.next().ok_or(format!("Option '{}' requires a value", name));
+            match argument.as_str() {
+                "-C" => {
+                    let directory = value("-C")?;
+                    /* Like git, an empty directory is ignored */
+                    if !directory.is_empty() {
+                        options.directories.push(PathBuf::from(directory));
+                    }
+                },
+                "--git-dir" => options.git_dir = Some(PathBuf::from(value("--git-dir")?)),
+                "--work-tree" => options.work_tree = Some(PathBuf::from(value("--work-tree")?)),
+                option if option.starts_with("--git-dir=") => {
+                    options.git_dir = Some(PathBuf::from(&option["--git-dir=".len()..]));
+                },
+                option if option.starts_with("--work-tree=") => {
+                    options.work_tree = Some(PathBuf::from(&option["--work-tree=".len()..]));
+                },
+                option if option.starts_with('-') => {
+                    return Err(format!("Unknown option {}", option))
+                },
+                _ => {
+                    rest.push(argument);
+                    rest.extend(args);
+                    break
+                }
+            }
+        }
+
+        Ok((options, rest))
+    }
+}
+
 #[derive(Debug)]
 pub enum Command {
+    Init {
+        directory: PathBuf,
+        initial_branch: String,
+        bare: bool
+    },
     Add(PathBuf),
-    Commit(String)
+    Commit(CommitOptions),
+    CatFile {
+        mode: CatFileMode,
+        object: String
+    },
+    Tag(TagAction),
+    Branch(BranchAction),
+    Checkout(CheckoutOptions),
+    Switch(CheckoutOptions),
+    Log(LogOptions),
+    Status {
+        format: StatusFormat,
+        branch: bool
+    },
+    Diff(DiffOptions),
+    Config(ConfigOptions),
+    CheckIgnore {
+        verbose: bool,
+        non_matching: bool,
+        paths: Vec<PathBuf>
+    },
+    Gc,
+    Repack(RepackOptions),
+    VerifyPack {
+        verbose: bool,
+        paths: Vec<PathBuf>
+    },
+    Fsck,
+    LsFiles {
+        stage: bool,
+        debug: bool
+    },
+    UpdateIndex {
+        index_version: Option<u32>
+    }
 }
 
 impl Command {
     pub fn parse(args: Vec<String>) -> Result<Self, String> {
-        if args.len() != 3 {
+        if args.len() < 2 {
             return Err("Missing arguments".into())
         };
 
         let command = args[1].to_lowercase();
-        let arguments = args[2].clone();
+        let arguments = &args[2..];
 
         match command.as_str() {
+            "init" => Self::parse_init(arguments),
             "add" => {
-                let path = PathBuf::from(&arguments);
+                let path = PathBuf::from(Self::single_argument(arguments)?);
                 Ok(Command::Add(path))
             },
-            "commit" => {
-                Ok(Command::Commit(arguments))
+            "commit" => Self::parse_commit(arguments),
+            "cat-file" => Self::parse_cat_file(arguments),
+            "tag" => Self::parse_tag(arguments),
+            "branch" => Self::parse_branch(arguments),
+            "checkout" => Ok(Command::Checkout(Self::parse_checkout(arguments, "-b")?)),
+            "switch" => {
+                let options = Self::parse_checkout(arguments, "-c")?;
+                if !options.paths.is_empty() {
+                    return Err("switch does not take paths, use checkout".into())
+                }
+                Ok(Command::Switch(options))
+            },
+            "log" => Self::parse_log(arguments),
+            "status" => Self::parse_status(arguments),
+            "diff" => Self::parse_diff(arguments),
+            "config" => Self::parse_config(arguments),
+            "check-ignore" => Self::parse_check_ignore(arguments),
+            "gc" => match arguments {
+                [] => Ok(Command::Gc),
+                _ => Err("Too many arguments".into())
+            },
+            "repack" => Self::parse_repack(arguments),
+            "verify-pack" => Self::parse_verify_pack(arguments),
+            "fsck" => match arguments {
+                [] => Ok(Command::Fsck),
+                _ => Err("Too many arguments".into())
             },
+            "ls-files" => Self::parse_ls_files(arguments),
+            "update-index" => Self::parse_update_index(arguments),
             unknown => {
                 Err(format!("Unexpected command {}", unknown))
             }
         }
     }
+
+    fn single_argument(arguments: &[String]) -> Result<String, String> {
+        match arguments {
+            [argument] => Ok(argument.clone()),
+            [] => Err("Missing arguments".into()),
+            _ => Err("Too many arguments".into())
+        }
+    }
+
+    fn parse_init(arguments: &[String]) -> Result<Self, String> {
+        let mut directory = None;
+        let mut initial_branch = String::from("master");
+        let mut bare = false;
+
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            match argument.as_str() {
+                "--bare" => bare = true,
+                "-b" | "--initial-branch" => {
+                    initial_branch = arguments.next()
+                        .ok_or(format!("Option '{}' requires a value", argument))?
+                        .clone();
+                },
+                option if option.starts_with("--initial-branch=") => {
+                    initial_branch = option["--initial-branch=".len()..].to_string();
+                },
+                option if option.starts_with('-') => {
+                    return Err(format!("Unknown option {}", option))
+                },
+                path => {
+                    if directory.is_some() {
+                        return Err("Too many arguments".into())
+                    }
+                    directory = Some(PathBuf::from(path));
+                }
+            }
+        }
+
+        if initial_branch.is_empty() {
+            return Err("Initial branch name cannot be empty".into())
+        }
+
+        let directory = directory.unwrap_or(PathBuf::from("."));
+        Ok(Command::Init { directory, initial_branch, bare })
+    }
+
+    fn parse_commit(arguments: &[String]) -> Result<Self, String> {
+        let mut message = None;
+        let mut author = None;
+        let mut date = None;
+
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            let (option, value) = match argument.split_once('=') {
+                Some((option, value)) if option.starts_with("--") => (option, Some(value.to_string())),
+                _ => (argument.as_str(), None)
+            };
+            let mut value = || value.clone()
+                .or_else(|| arguments.next().cloned())
+                .ok_or(format!("Option '{}' requires a value", option));
+
+            match option {
+                "-m" | "--message" => message = Some(value()?),
+                "--author" => author = Some(value()?),
+                "--date" => date = Some(value()?),
+                option if option.starts_with('-') => {
+                    return Err(format!("Unknown option {}", option))
+                },
+                positional => {
+                    if message.is_some() {
+                        return Err("Too many arguments".into())
+                    }
+                    message = Some(positional.to_string());
+                }
+            }
+        }
+
+        let message = message.ok_or("Missing commit message")?;
+        Ok(Command::Commit(CommitOptions { message, author, date }))
+    }
+
+    fn parse_cat_file(arguments: &[String]) -> Result<Self, String> {
+        let [mode, object] = arguments else {
+            return Err("Expected cat-file (-t | -s | -p | -e) <object>".into())
+        };
+
+        let mode = match mode.as_str() {
+            "-t" => CatFileMode::Type,
+            "-s" => CatFileMode::Size,
+            "-p" => CatFileMode::Pretty,
+            "-e" => CatFileMode::Exists,
+            unknown => return Err(format!("Unknown option {}", unknown))
+        };
+
+        Ok(Command::CatFile { mode, object: object.clone() })
+    }
+
+    fn parse_tag(arguments: &[String]) -> Result<Self, String> {
+        let mut list = false;
+        let mut delete = false;
+        let mut annotate = false;
+        let mut force = false;
+        let mut message = None;
+        let mut names = Vec::new();
+
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            match argument.as_str() {
+                "-l" | "--list" => list = true,
+                "-d" | "--delete" => delete = true,
+                "-a" | "--annotate" => annotate = true,
+                "-f" | "--force" => force = true,
+                "-m" | "--message" => {
+                    message = Some(arguments.next()
+                        .ok_or(format!("Option '{}' requires a value", argument))?
+                        .clone());
+                },
+                option if option.starts_with("--message=") => {
+                    message = Some(option["--message=".len()..].to_string());
+                },
+                option if option.starts_with('-') => {
+                    return Err(format!("Unknown option {}", option))
+                },
+                name => names.push(name.to_string())
+            }
+        }
+
+        if delete {
+            if names.is_empty() {
+                return Err("Missing tag name to delete".into())
+            }
+            return Ok(Command::Tag(TagAction::Delete(names)))
+        }
+
+        if list || names.is_empty() {
+            return Ok(Command::Tag(TagAction::List(names)))
+        }
+
+        if annotate && message.is_none() {
+            return Err("Annotated tags require a message (-m <message>)".into())
+        }
+
+        let mut names = names.into_iter();
+        let name = names.next().unwrap_or_default();
+        let target = names.next();
+        if names.next().is_some() {
+            return Err("Too many arguments".into())
+        }
+
+        Ok(Command::Tag(TagAction::Create { name, target, message, force }))
+    }
+
+    fn parse_branch(arguments: &[String]) -> Result<Self, String> {
+        let mut force = false;
+        let mut mode = None;
+        let mut names = Vec::new();
+
+        for argument in arguments {
+            match argument.as_str() {
+                "-f" | "--force" => force = true,
+                "-l" | "--list" | "--show-current" | "-d" | "--delete" | "-m" | "--move" => {
+                    mode = Some(argument.as_str());
+                },
+                "-D" => {
+                    force = true;
+                    mode = Some("-d");
+                },
+                "-M" => {
+                    force = true;
+                    mode = Some("-m");
+                },
+                option if option.starts_with('-') => {
+                    return Err(format!("Unknown option {}", option))
+                },
+                name => names.push(name.to_string())
+            }
+        }
+
+        let action = match (mode, names.as_slice()) {
+            (None | Some("-l" | "--list"), []) => BranchAction::List,
+            (Some("--show-current"), []) => BranchAction::ShowCurrent,
+            (None, [name]) => BranchAction::Create { name: name.clone(), start: None, force },
+            (None, [name, start]) => BranchAction::Create {
+                name: name.clone(),
+                start: Some(start.clone()),
+                force
+            },
+            (Some("-m" | "--move"), [new]) => BranchAction::Rename { old: None, new: new.clone(), force },
+            (Some("-m" | "--move"), [old, new]) => BranchAction::Rename {
+                old: Some(old.clone()),
+                new: new.clone(),
+                force
+            },
+            (Some("-d" | "--delete"), []) => return Err("Branch name required".into()),
+            (Some("-d" | "--delete"), names) => BranchAction::Delete { names: names.to_vec(), force },
+            _ => return Err("Too many arguments".into())
+        };
+
+        Ok(Command::Branch(action))
+    }
+
+    /* checkout and switch differ only in the option creating a branch */
+    fn parse_checkout(arguments: &[String], create: &str) -> Result<CheckoutOptions, String> {
+        let mut options = CheckoutOptions {
+            target: None,
+            new_branch: None,
+            detach: false,
+            force: false,
+            paths: Vec::new()
+        };
+
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            match argument.as_str() {
+                "--" => {
+                    options.paths.extend(arguments.by_ref().map(PathBuf::from));
+                },
+                "-f" | "--force" => options.force = true,
+                "-d" | "--detach" => options.detach = true,
+                option if option == create => {
+                    let name = arguments.next()
+                        .ok_or(format!("Option '{}' requires a value", option))?;
+                    options.new_branch = Some(name.clone());
+                },
+                option if option.starts_with('-') => {
+                    return Err(format!("Unknown option {}", option))
+                },
+                target => {
+                    if options.target.is_some() {
+                        return Err("Too many arguments".into())
+                    }
+                    options.target = Some(target.to_string());
+                }
+            }
+        }
+
+        if options.target.is_none() && options.new_branch.is_none() && options.paths.is_empty() {
+            return Err("Missing branch or commit to check out".into())
+        }
+        Ok(options)
+    }
+
+    fn parse_format(format: &str) -> LogFormat {
+        match format {
+            "oneline" => LogFormat::Oneline,
+            "medium" => LogFormat::Medium,
+            format => {
+                let format = format.strip_prefix("format:")
+                    .or(format.strip_prefix("tformat:"))
+                    .unwrap_or(format);
+                LogFormat::Custom(format.to_string())
+            }
+        }
+    }
+
+    fn parse_date_format(format: &str) -> Result<DateFormat, String> {
+        Ok(match format {
+            "default" => DateFormat::Default,
+            "local" | "default-local" => DateFormat::Local,
+            "iso" | "iso8601" => DateFormat::Iso,
+            "iso-strict" | "iso8601-strict" => DateFormat::IsoStrict,
+            "rfc" | "rfc2822" => DateFormat::Rfc,
+            "short" => DateFormat::Short,
+            "relative" => DateFormat::Relative,
+            "raw" => DateFormat::Raw,
+            "unix" => DateFormat::Unix,
+            unknown => return Err(format!("Unknown date format '{}'", unknown))
+        })
+    }
+
+    fn parse_log(arguments: &[String]) -> Result<Self, String> {
+        let mut options = LogOptions {
+            revisions: Vec::new(),
+            paths: Vec::new(),
+            format: LogFormat::Medium,
+            date: DateFormat::Default,
+            order: LogOrder::Date,
+            max_count: None,
+            since: None,
+            until: None,
+            author: None
+        };
+
+        let parse_count = |count: &str| count.parse::<usize>()
+            .map_err(|_| format!("Invalid count '{}'", count));
+
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            let (option, value) = match argument.split_once('=') {
+                Some((option, value)) if option.starts_with("--") => (option, Some(value.to_string())),
+                _ => (argument.as_str(), None)
+            };
+            let mut value = || value.clone()
+                .or_else(|| arguments.next().cloned())
+                .ok_or(format!("Option '{}' requires a value", option));
+
+            match option {
+                "--" => {
+                    options.paths.extend(arguments.by_ref().map(PathBuf::from));
+                },
+                "--oneline" => options.format = LogFormat::Oneline,
+                "--format" | "--pretty" => options.format = Self::parse_format(&value()?),
+                "--date" => options.date = Self::parse_date_format(&value()?)?,
+                "--date-order" => options.order = LogOrder::DateOrder,
+                "--topo-order" => options.order = LogOrder::Topo,
+                "-n" | "--max-count" => options.max_count = Some(parse_count(&value()?)?),
+                "--since" | "--after" => options.since = Some(value()?),
+                "--until" | "--before" => options.until = Some(value()?),
+                "--author" => options.author = Some(value()?),
+                option if option.starts_with("-n") => {
+                    options.max_count = Some(parse_count(&option[2..])?);
+                },
+                option if option.starts_with('-') && option[1..].chars().all(|c| c.is_ascii_digit()) => {
+                    options.max_count = Some(parse_count(&option[1..])?);
+                },
+                option if option.starts_with('-') => {
+                    return Err(format!("Unknown option {}", option))
+                },
+                revision => options.revisions.push(revision.to_string())
+            }
+        }
+
+        Ok(Command::Log(options))
+    }
+
+    fn parse_status(arguments: &[String]) -> Result<Self, String> {
+        let mut format = StatusFormat::Long;
+        let mut branch = false;
+
+        for argument in arguments {
+            match argument.as_str() {
+                "-s" | "--short" => format = StatusFormat::Short,
+                "--long" => format = StatusFormat::Long,
+                "--porcelain" | "--porcelain=v1" => format = StatusFormat::PorcelainV1,
+                "--porcelain=v2" => format = StatusFormat::PorcelainV2,
+                "-b" | "--branch" => branch = true,
+                unknown => return Err(format!("Unknown option {}", unknown))
+            }
+        }
+
+        Ok(Command::Status { format, branch })
+    }
+
+    fn parse_check_ignore(arguments: &[String]) -> Result<Self, String> {
+        let mut verbose = false;
+        let mut non_matching = false;
+        let mut paths = Vec::new();
+
+        for argument in arguments {
+            match argument.as_str() {
+                "-v" | "--verbose" => verbose = true,
+                "-n" | "--non-matching" => non_matching = true,
+                "--" => {},
+                unknown if unknown.starts_with('-') => return Err(format!("Unknown option {}", unknown)),
+                path => paths.push(PathBuf::from(path))
+            }
+        }
+
+        if paths.is_empty() {
+            return Err("no path specified".into())
+        }
+        if non_matching && !verbose {
+            return Err("--non-matching is only valid with --verbose".into())
+        }
+        Ok(Command::CheckIgnore { verbose, non_matching, paths })
+    }
+
+    fn parse_repack(arguments: &[String]) -> Result<Self, String> {
+        let mut options = RepackOptions {
+            all: false,
+            unpack_unreachable: false,
+            delete: false,
+            window: DEFAULT_WINDOW,
+            depth: DEFAULT_DEPTH
+        };
+        let parse_number = |option: &str, value: &str| value.parse::<usize>()
+            .map_err(|_| format!("Invalid value '{}' for {}", value, option));
+
+        for argument in arguments {
+            match argument.as_str() {
+                "-a" => options.all = true,
+                "-A" => {
+                    options.all = true;
+                    options.unpack_unreachable = true;
+                },
+                "-d" => options.delete = true,
+                option if option.starts_with("--window=") => {
+                    options.window = parse_number("--window", &option["--window=".len()..])?;
+                },
+                option if option.starts_with("--depth=") => {
+                    options.depth = parse_number("--depth", &option["--depth=".len()..])?;
+                },
+                unknown => return Err(format!("Unknown option {}", unknown))
+            }
+        }
+
+        Ok(Command::Repack(options))
+    }
+
+    fn parse_verify_pack(arguments: &[String]) -> Result<Self, String> {
+        let mut verbose = false;
+        let mut paths = Vec::new();
+
+        for argument in arguments {
+            match argument.as_str() {
+                "-v" | "--verbose" => verbose = true,
+                unknown if unknown.starts_with('-') => return Err(format!("Unknown option {}", unknown)),
+                path => paths.push(PathBuf::from(path))
+            }
+        }
+
+        if paths.is_empty() {
+            return Err("Missing pack index to verify".into())
+        }
+        Ok(Command::VerifyPack { verbose, paths })
+    }
+
+    fn parse_ls_files(arguments: &[String]) -> Result<Self, String> {
+        let mut stage = false;
+        let mut debug = false;
+
+        for argument in arguments {
+            match argument.as_str() {
+                "-s" | "--stage" => stage = true,
+                "--debug" => debug = true,
+                unknown => return Err(format!("Unknown option {}", unknown))
+            }
+        }
+
+        Ok(Command::LsFiles { stage, debug })
+    }
+
+    fn parse_update_index(arguments: &[String]) -> Result<Self, String> {
+        let mut index_version = None;
+
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            let value = match argument.split_once('=') {
+                Some(("--index-version", value)) => value.to_string(),
+                None if argument == "--index-version" => arguments.next()
+                    .cloned()
+                    .ok_or("Option '--index-version' requires a value")?,
+                _ => return Err(format!("Unknown option {}", argument))
+            };
+            let version = value.parse::<u32>()
+                .ok()
+                .filter(|version| (2..=4).contains(version))
+                .ok_or(format!("index-version {} not in range: 2..4", value))?;
+            index_version = Some(version);
+        }
+
+        Ok(Command::UpdateIndex { index_version })
+    }
+
+    fn parse_diff(arguments: &[String]) -> Result<Self, String> {
+        let mut options = DiffOptions {
+            cached: false,
+            context: 3,
+            output: DiffOutput::Patch,
+            revisions: Vec::new(),
+            paths: Vec::new()
+        };
+
+        let parse_context = |context: &str| context.parse::<usize>()
+            .map_err(|_| format!("Invalid number of context lines '{}'", context));
+
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            match argument.as_str() {
+                "--" => {
+                    options.paths.extend(arguments.by_ref().map(PathBuf::from));
+                },
+                "--cached" | "--staged" => options.cached = true,
+                "-p" | "--patch" => options.output = DiffOutput::Patch,
+                "--stat" => options.output = DiffOutput::Stat,
+                "--numstat" => options.output = DiffOutput::Numstat,
+                "--name-only" => options.output = DiffOutput::NameOnly,
+                "--name-status" => options.output = DiffOutput::NameStatus,
+                "-U" | "--unified" => {
+                    let context = arguments.next()
+                        .ok_or(format!("Option '{}' requires a value", argument))?;
+                    options.context = parse_context(context)?;
+                },
+                option if option.starts_with("--unified=") => {
+                    options.context = parse_context(&option["--unified=".len()..])?;
+                },
+                option if option.starts_with("-U") => {
+                    options.context = parse_context(&option[2..])?;
+                },
+                option if option.starts_with('-') => {
+                    return Err(format!("Unknown option {}", option))
+                },
+                revision => options.revisions.push(revision.to_string())
+            }
+        }
+
+        Ok(Command::Diff(options))
+    }
+
+    fn parse_config(arguments: &[String]) -> Result<Self, String> {
+        let mut mode = None;
+        let mut scope = None;
+        let mut value_type = None;
+        let mut names = Vec::new();
+
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            match argument.as_str() {
+                "--system" => scope = Some(ConfigScope::System),
+                "--global" => scope = Some(ConfigScope::Global),
+                "--local" => scope = Some(ConfigScope::Local),
+                "--worktree" => scope = Some(ConfigScope::Worktree),
+                "-f" | "--file" => {
+                    let file = arguments.next()
+                        .ok_or(format!("Option '{}' requires a value", argument))?;
+                    scope = Some(ConfigScope::File(PathBuf::from(file)));
+                },
+                "--bool" | "--type=bool" => value_type = Some(ConfigType::Bool),
+                "--int" | "--type=int" => value_type = Some(ConfigType::Int),
+                "--get" | "--get-all" | "--set" | "--add" | "--unset" | "--unset-all" | "-l" | "--list" => {
+                    if mode.is_some() {
+                        return Err("Only one action at a time".into())
+                    }
+                    mode = Some(argument.as_str());
+                },
+                option if option.starts_with("--file=") => {
+                    scope = Some(ConfigScope::File(PathBuf::from(&option["--file=".len()..])));
+                },
+                option if option.starts_with('-') => {
+                    return Err(format!("Unknown option {}", option))
+                },
+                name => names.push(name.to_string())
+            }
+        }
+
+        let action = match (mode, names.as_slice()) {
+            (Some("-l" | "--list"), []) => ConfigAction::List,
+            (Some("--get") | None, [key]) => ConfigAction::Get { key: key.clone(), all: false },
+            (Some("--get-all"), [key]) => ConfigAction::Get { key: key.clone(), all: true },
+            (Some("--set") | None, [key, value]) => ConfigAction::Set {
+                key: key.clone(),
+                value: value.clone(),
+                add: false
+            },
+            (Some("--add"), [key, value]) => ConfigAction::Set {
+                key: key.clone(),
+                value: value.clone(),
+                add: true
+            },
+            (Some("--unset"), [key]) => ConfigAction::Unset { key: key.clone(), all: false },
+            (Some("--unset-all"), [key]) => ConfigAction::Unset { key: key.clone(), all: true },
+            (_, []) => return Err("Missing arguments".into()),
+            _ => return Err("Wrong number of arguments".into())
+        };
+
+        Ok(Command::Config(ConfigOptions { action, scope, value_type }))
+    }
 }
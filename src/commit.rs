@@ -1,175 +1,281 @@
+use crate::error::*;
 use crate::hash::*;
+use crate::object::*;
+use crate::util::*;
+use crate::command::*;
+use crate::ident::*;
+use crate::repository::*;
+use crate::tree::*;
 
 use std::fmt;
+use std::fs;
+use std::io;
 
+/* An identity with a date; the name and email are kept as the bytes written */
+#[derive(Debug, Clone)]
 pub struct Stamp {
-    pub name: String,
-    pub email: String,
-    pub timestamp: u32,
+    pub name: Vec<u8>,
+    pub email: Vec<u8>,
+    pub timestamp: i64,
+    /* UTC offset exactly as written in the object, e.g. "+0100" */
+    pub timezone: String,
+}
+
+impl Stamp {
+    /* Parses "<name> <<email>> <timestamp> <timezone>" */
+    pub fn parse(stamp: &[u8]) -> Result<Stamp, Error> {
+        let invalid = || corrupt(format!("Invalid identity '{}'", String::from_utf8_lossy(stamp)));
+
+        let open = stamp.iter().position(|&byte| byte == b'<').ok_or_else(invalid)?;
+        let close = stamp.iter().rposition(|&byte| byte == b'>').filter(|&close| close > open).ok_or_else(invalid)?;
+        let date = str::from_utf8(&stamp[close + 1..]).map_err(|_| invalid())?;
+        let (timestamp, timezone) = date.trim_start()
+            .split_once(' ')
+            .ok_or_else(invalid)?;
+
+        Ok(Stamp {
+            name: stamp[..open].trim_ascii_end().to_vec(),
+            email: stamp[open + 1..close].to_vec(),
+            timestamp: timestamp.parse().map_err(|_| invalid())?,
+            timezone: timezone.to_string(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.name.clone();
+        bytes.extend_from_slice(b" <");
+        bytes.extend_from_slice(&self.email);
+        bytes.extend(format!("> {} {}", self.timestamp, self.timezone).into_bytes());
+        bytes
+    }
 }
 
 impl fmt::Display for Stamp {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} <{}> {} +0100", self.name, self.email, self.timestamp)
+        write!(f, "{}", String::from_utf8_lossy(&self.to_bytes()))
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct Commit {
-    tree: Hash,
-    parent: Option<Hash>,
-    author: Stamp,
-    committer: Stamp,
-    message: String,
+    pub tree: Hash,
+    pub parents: Vec<Hash>,
+    pub author: Stamp,
+    pub committer: Stamp,
+    /* Any other headers in order, e.g. `encoding`, `mergetag` and `gpgsig` */
+    pub headers: Headers,
+    /*
+     * The message as written, in the `encoding` header's encoding if any.
+     * None for a commit without the empty line that starts it.
+     */
+    pub message: Option<Vec<u8>>,
 }
 
 impl Commit {
     pub fn create(
         tree: Hash, 
-        parent: Option<Hash>, 
+        parents: Vec<Hash>, 
         author: Stamp, 
         committer: Stamp, 
-        message: String
+        mut message: String
     ) -> Self {
+        if !message.ends_with('\n') {
+            message.push('\n');
+        }
+
         Self {
             tree,
-            parent,
+            parents,
             author,
             committer,
-            message
+            headers: Vec::new(),
+            message: Some(message.into_bytes())
         }
     }
-}
 
-#[cfg(test)]
-impl Commit {
-    pub fn read(bytes: &mut &[u8]) -> Option<Commit> {
-        Commit::read_header(bytes);
-        let commit = str::from_utf8(bytes).ok()?;
-        let mut lines = commit.lines().peekable();
-
-        let tree = Commit::read_tree(lines.next()?);
-        let parent = if lines.peek()?.starts_with("parent ") {
-            Some(Hash::from_hex(&lines.next()?[7..47]))
-        } else { None } ;
-        let author = Commit::read_author(lines.next()?);
-        let committer = Commit::read_committer(lines.next()?);
-
-        lines.next()?; // Empty line
-        let mut message = lines.collect::<Vec<&str>>().join("\n");
-        message.push_str("\n");
-
-        Some(
-            Commit {
-                tree,
-                parent,
-                author,
-                committer,
-                message
-            }
-        )
+    pub fn read(repository: &Repository, hash: &Hash) -> Result<Commit, Error> {
+        let object = repository.read_object_of_kind(hash, ObjectType::Commit)?;
+        Commit::parse(&object.content)
     }
 
-    fn read_header(bytes: &mut &[u8]) {
-        if let Some(pos) = bytes.iter().position(|&x| x == 0) {
-            crate::take_n_bytes(bytes, pos + 1);
+    /* Parses the content of a commit object, i.e. without the object header */
+    pub fn parse(content: &[u8]) -> Result<Commit, Error> {
+        let (headers, message) = split_headers(content);
+        let mut headers = headers.into_iter().peekable();
+
+        let tree = match headers.next() {
+            Some((key, Some(value))) if key == "tree" => Hash::from_hex(&String::from_utf8_lossy(&value))?,
+            _ => return Err(corrupt("Commit is missing tree".into()))
+        };
+
+        let mut parents = Vec::new();
+        while let Some((_, value)) = headers.next_if(|(key, _)| key == "parent") {
+            parents.push(Hash::from_hex(&String::from_utf8_lossy(&value.unwrap_or_default()))?);
         }
-    }
 
-    fn read_tree(tree: &str) -> Hash{
-        let hash = &tree["tree ".len()..];
-        Hash::from_hex(&hash[..40])
-    }
+        let author = match headers.next() {
+            Some((key, Some(value))) if key == "author" => Stamp::parse(&value)?,
+            _ => return Err(corrupt("Commit is missing author".into()))
+        };
 
-    fn read_parent(parent: &str) -> Hash{
-        let hash = &parent["parent ".len()..];
-        Hash::from_hex(&hash[..40])
+        let committer = match headers.next() {
+            Some((key, Some(value))) if key == "committer" => Stamp::parse(&value)?,
+            _ => return Err(corrupt("Commit is missing committer".into()))
+        };
+
+        Ok(Commit {
+            tree,
+            parents,
+            author,
+            committer,
+            headers: headers.collect(),
+            message
+        })
     }
 
-    fn read_author(author: &str) -> Stamp {
-        Commit::take_stamp(&author["author ".len()..])
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = format!("tree {}\n", self.tree).into_bytes();
+        for parent in &self.parents {
+            bytes.extend(format!("parent {}\n", parent).into_bytes());
+        }
+        write_header(&mut bytes, "author", &self.author.to_bytes());
+        write_header(&mut bytes, "committer", &self.committer.to_bytes());
+        write_headers_and_message(&mut bytes, &self.headers, self.message.as_deref());
+        bytes
     }
 
-    fn read_committer(committer: &str) -> Stamp {
-        Commit::take_stamp(&committer["committer ".len()..])
+    /* The message for display, invalid UTF-8 being replaced */
+    pub fn message_text(&self) -> String {
+        String::from_utf8_lossy(self.message.as_deref().unwrap_or_default()).to_string()
     }
 
-    fn take_stamp(stamp: &str) -> Stamp {
-        let (name, stamp) = stamp.split_once(" <").unwrap();
-        let (email, stamp) = stamp.split_once("> ").unwrap();
-        let (timestamp, _timezone) = stamp.split_once(" ").unwrap();
-        Stamp {
-            name: name.to_string(),
-            email: email.to_string(),
-            timestamp: timestamp.parse().unwrap(),
+    /* The first paragraph of the message, joined into a single line */
+    pub fn subject(&self) -> String {
+        self.message_text()
+            .split("\n\n")
+            .next()
+            .unwrap_or_default()
+            .lines()
+            .map(str::trim)
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
 
-        }
+    /* The message after the subject paragraph */
+    pub fn body(&self) -> String {
+        self.message_text()
+            .split_once("\n\n")
+            .map(|(_, body)| body.trim_start_matches('\n').to_string())
+            .unwrap_or_default()
     }
 }
 
 impl fmt::Display for Commit {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "tree {}", self.tree)?;
-        if let Some(parent) = &self.parent  {
-            writeln!(f, "parent {}", parent)?;
-        }
-        writeln!(f, "author {}", self.author)?;
-        writeln!(f, "committer {}", self.committer)?;
-        writeln!(f, "\n{}", self.message)
+        write!(f, "{}", String::from_utf8_lossy(&self.to_bytes()))
     }
 }
 
-#[cfg(test)]
-use chrono::DateTime;
+/*
+ * The parents of the next commit: the commit HEAD points to, if the branch
+ * is born, followed by the commits being merged listed in MERGE_HEAD.
+ */
+fn parents(repository: &Repository) -> Result<Vec<Hash>, Error> {
+    let mut parents: Vec<Hash> = repository.read_ref("HEAD")?.into_iter().collect();
 
-#[cfg(test)]
-pub fn timestamp_to_date(seconds: u32, nanoseconds: u32) -> String {
-    let seconds: i64 = i64::from(seconds);
-    let dt = DateTime::from_timestamp(seconds, nanoseconds);
-    match dt {
-        Some(date) => format!("{}", date),
-        None => String::from("")
+    match fs::read_to_string(repository.git_path("MERGE_HEAD")) {
+        Ok(content) => {
+            for line in content.lines().filter(|line| !line.trim().is_empty()) {
+                let parent = Hash::from_hex(line.trim())
+                    .map_err(|_| Error::BadRef(String::from("MERGE_HEAD is corrupt")))?;
+                if !parents.contains(&parent) {
+                    parents.push(parent);
+                }
+            }
+        },
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {},
+        Err(err) => return Err(err.into())
+    }
+
+    Ok(parents)
+}
+
+fn update_refs(repository: &Repository, commit: &Hash) -> Result<(), Error> {
+    repository.update_head(commit)?;
+
+    /* The merge is concluded by the commit */
+    match fs::remove_file(repository.git_path("MERGE_HEAD")) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(())
+    }
+}
+
+/* Records the index as a new commit on top of HEAD */
+pub fn commit(repository: &Repository, options: CommitOptions) -> Result<Hash, Error> {
+    // 0. read staging area (index)
+    let mut index = repository.read_index()?;
+
+    // 1. write-tree, keeping the trees written in the index for the next commit
+    let cached = index.cache_tree().cloned();
+    let tree = write_tree(repository, &mut index)?;
+    if index.cache_tree() != cached.as_ref() {
+        repository.write_index(&index)?;
     }
+
+    // 2. write to commit
+    let parents = parents(repository)?;
+    let author = author(repository, options.author.as_deref(), options.date.as_deref())?;
+    let committer = committer(repository)?;
+    let commit = Commit::create(tree, parents, author, committer, options.message);
+    let hash = repository.write_object(ObjectType::Commit, commit.to_bytes())?;
+
+    // 3. update refs
+    update_refs(repository, &hash)?;
+    Ok(hash)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use std::fs;
+
+    use crate::test_util::*;
     use crate::compress::*;
 
     #[test]
     fn read_commit_object() {
         let filename = String::from("examples/commit");
         let content = fs::read(&filename).unwrap();
-        let mut commit_file = &_decompress(content).unwrap()[..];
+        let object = parse_object(&decompress(content).unwrap()).unwrap();
 
-        let commit = Commit::read(&mut commit_file).unwrap();
-        assert_eq!(commit.author.name, "Nicolay Roness");
-        assert_eq!(commit.parent.unwrap().to_string(), "c631313b6cc3a747eac28cdb26802678a96b870b");
-        assert_eq!(commit.message, "create blob from file\n");
+        let commit = Commit::parse(&object.content).unwrap();
+        assert_eq!(commit.author.name, b"Nicolay Roness");
+        assert_eq!(commit.parents[0].to_string(), "c631313b6cc3a747eac28cdb26802678a96b870b");
+        assert_eq!(commit.message.as_deref().unwrap(), b"create blob from file\n");
     }
 
     #[test]
     fn create_commit_from_tree() {
         let filename = String::from("examples/commit_tree");
         let content = fs::read(&filename).unwrap();
-        let decoded = &_decompress(content).unwrap()[..];
+        let decoded = &decompress(content).unwrap()[..];
 
-        let key = Hash::from_bytes(String::from(""), decoded.to_vec());
-        let parent = Some(Hash::from_hex("f60b322c7351b08514fceed6f69102138ab420e7"));
+        let key = Hash::from_bytes(b"", decoded);
+        let parents = vec![Hash::from_hex("f60b322c7351b08514fceed6f69102138ab420e7").unwrap()];
         let author = Stamp {
-            name: "Nicolay Roness".to_string(),
-            email: "nicolay.caspersen.roness@sparebank1.no".to_string(),
-            timestamp: 1764365370 
+            name: b"Nicolay Roness".to_vec(),
+            email: b"nicolay.caspersen.roness@sparebank1.no".to_vec(),
+            timestamp: 1764365370,
+            timezone: "+0100".to_string()
         };
 
         let committer = Stamp {
-            name: "Nicolay Roness".to_string(),
-            email: "nicolay.caspersen.roness@sparebank1.no".to_string(),
-            timestamp: 1764365370 
+            name: b"Nicolay Roness".to_vec(),
+            email: b"nicolay.caspersen.roness@sparebank1.no".to_vec(),
+            timestamp: 1764365370,
+            timezone: "+0100".to_string()
         };
         let message = String::from("det virker!");
-        let commit = Commit::create(key, parent, author, committer, message)
+        let commit = Commit::create(key, parents, author, committer, message)
             .to_string();
 
         let expected =
@@ -181,5 +287,113 @@ committer Nicolay Roness <nicolay.caspersen.roness@sparebank1.no> 1764365370 +01
 det virker!\n";
         assert_eq!(commit, expected);
     }
-}
 
+    #[test]
+    fn create_root_and_merge_commits() {
+        let key = Hash::from_hex("b03318345a1f9d098d0bfa44d6111818ab701fbe").unwrap();
+        let stamp = Stamp::parse(b"Nicolay Roness <nicolay.caspersen.roness@sparebank1.no> 1764365370 +0100").unwrap();
+        let parents = vec![
+            Hash::from_hex("f60b322c7351b08514fceed6f69102138ab420e7").unwrap(),
+            Hash::from_hex("c631313b6cc3a747eac28cdb26802678a96b870b").unwrap(),
+        ];
+
+        let root = Commit::create(key.clone(), Vec::new(), stamp.clone(), stamp.clone(), "root".into()).to_string();
+        let merge = Commit::create(key, parents, stamp.clone(), stamp, "merge".into()).to_string();
+
+        assert!(root.starts_with("tree b03318345a1f9d098d0bfa44d6111818ab701fbe\nauthor "));
+        assert!(merge.contains("\nparent f60b322c7351b08514fceed6f69102138ab420e7\n\
+            parent c631313b6cc3a747eac28cdb26802678a96b870b\n"));
+    }
+
+    #[test]
+    fn commit_round_trip() {
+        let filename = String::from("examples/commit");
+        let content = fs::read(&filename).unwrap();
+        let object = parse_object(&decompress(content).unwrap()).unwrap();
+
+        let commit = Commit::parse(&object.content).unwrap();
+
+        assert_eq!(commit.to_bytes(), object.content);
+    }
+
+    #[test]
+    fn commit_with_merge_parents_and_signature() {
+        let content = "tree b03318345a1f9d098d0bfa44d6111818ab701fbe
+parent f60b322c7351b08514fceed6f69102138ab420e7
+parent c631313b6cc3a747eac28cdb26802678a96b870b
+author A U Thor <author@example.com> 1764365370 -0530
+committer C O Mitter <committer@example.com> 1764365371 +0000
+encoding ISO-8859-1
+gpgsig -----BEGIN PGP SIGNATURE-----
+ 
+ iQEzBAABCAAdFiEE
+ -----END PGP SIGNATURE-----
+
+Merge branch 'feature'
+
+Details.
+";
+
+        let commit = Commit::parse(content.as_bytes()).unwrap();
+
+        assert_eq!(commit.parents.len(), 2);
+        assert_eq!(commit.author.timezone, "-0530");
+        assert_eq!(commit.headers[0], ("encoding".into(), Some(b"ISO-8859-1".to_vec())));
+        assert_eq!(commit.headers[1].1.as_deref().unwrap(), b"-----BEGIN PGP SIGNATURE-----\n\niQEzBAABCAAdFiEE\n-----END PGP SIGNATURE-----");
+        assert_eq!(commit.message.as_deref().unwrap(), b"Merge branch 'feature'\n\nDetails.\n");
+        assert_eq!(commit.to_string(), content);
+    }
+
+    #[test]
+    fn latin1_commit_round_trips() {
+        let mut content = b"tree b03318345a1f9d098d0bfa44d6111818ab701fbe
+author Ren\xe9 Fran\xe7ois <rene@example.com> 1764365370 +0100
+committer Ren\xe9 Fran\xe7ois <rene@example.com> 1764365370 +0100
+encoding ISO-8859-1
+
+".to_vec();
+        content.extend_from_slice(b"Caf\xe9 cr\xe8me\n\nD\xe9tails.\n");
+
+        let commit = Commit::parse(&content).unwrap();
+
+        assert_eq!(commit.author.name, b"Ren\xe9 Fran\xe7ois");
+        assert_eq!(commit.message.as_deref().unwrap(), b"Caf\xe9 cr\xe8me\n\nD\xe9tails.\n");
+        assert_eq!(commit.subject(), "Caf\u{fffd} cr\u{fffd}me");
+        assert_eq!(commit.to_bytes(), content);
+    }
+
+    #[test]
+    fn headers_without_value_or_message_round_trip() {
+        let headers = "tree b03318345a1f9d098d0bfa44d6111818ab701fbe
+author A <a@b> 1764365370 +0100
+committer A <a@b> 1764365370 +0100
+novalue
+empty \n";
+
+        let commit = Commit::parse(headers.as_bytes()).unwrap();
+        assert_eq!(commit.headers, [("novalue".to_string(), None), ("empty".to_string(), Some(Vec::new()))]);
+        assert_eq!(commit.message, None);
+        assert_eq!(commit.to_bytes(), headers.as_bytes());
+
+        let content = format!("{}\nmessage\n", headers);
+        assert_eq!(Commit::parse(content.as_bytes()).unwrap().to_bytes(), content.as_bytes());
+    }
+
+    #[test]
+    fn commit_staged_files_through_repository() {
+        let repository = TempRepo::new("commit");
+        let dir = repository.path();
+        fs::write(repository.git_path("config"), "[user]\n\tname = Nit\n\temail = nit@example.com\n").unwrap();
+        fs::write(dir.join("README"), "hello\n").unwrap();
+
+        crate::add::add(&repository, &dir.join("README")).unwrap();
+        let options = CommitOptions { message: String::from("Initial commit\n"), author: None, date: None };
+        let hash = commit(&repository, options).unwrap();
+
+        let commit = Commit::read(&repository, &hash).unwrap();
+        assert_eq!(repository.read_ref("refs/heads/main").unwrap(), Some(hash));
+        assert!(commit.parents.is_empty());
+        let cached = repository.read_index().unwrap().cache_tree().cloned().unwrap();
+        assert_eq!(cached.hash, Some(commit.tree));
+    }
+}
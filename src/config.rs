@@ -0,0 +1,654 @@
+use crate::error::*;
+use crate::command::*;
+use crate::repository::*;
+use crate::util::*;
+
+use std::env;
+use std::fs;
+use std::io;
+
+use std::path::{Path, PathBuf};
+
+/* Includes nested deeper than this are most likely a cycle */
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+/*
+ * The variables of one or more git-config files, in the order they were
+ * read. Keys are normalized to "section.subsection.name" where section and
+ * name are lowercase and the subsection keeps its case.
+ */
+#[derive(Debug, Default)]
+pub struct Config {
+    pub entries: Vec<(String, String)>
+}
+
+/* A line of a config file, or several when a value is continued */
+#[derive(Debug)]
+struct ConfigLine {
+    first: usize,
+    last: usize,
+    section: Option<String>,
+    /* Whether the line starts with a section header */
+    header: bool,
+    variable: Option<(String, String)>,
+}
+
+/* How `edit_config` changes a variable */
+#[derive(Debug, Clone, Copy)]
+pub enum ConfigEdit<'a> {
+    /* Replaces the value, which must not be multi-valued */
+    Set(&'a str),
+    /* Adds another value, even if the variable exists */
+    Add(&'a str),
+    /* Removes the variable, which must not be multi-valued */
+    Unset,
+    UnsetAll,
+}
+
+fn unquote(value: &str, line_number: usize) -> Result<String, Error> {
+    let mut result = String::new();
+    let mut quoted = false;
+    let mut chars = value.chars();
+    /* Trailing whitespace outside quotes is dropped, inside it is kept */
+    let mut pending_space = String::new();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                result.push_str(&pending_space);
+                pending_space.clear();
+                quoted = !quoted;
+            },
+            '\\' => {
+                result.push_str(&pending_space);
+                pending_space.clear();
+                match chars.next() {
+                    Some('n') => result.push('\n'),
+                    Some('t') => result.push('\t'),
+                    Some('b') => { result.pop(); },
+                    Some(c @ ('"' | '\\')) => result.push(c),
+                    _ => return Err(Error::InvalidConfig(format!("Bad escape in config line {}", line_number)))
+                }
+            },
+            '#' | ';' if !quoted => break,
+            c if c.is_whitespace() && !quoted => {
+                if !result.is_empty() {
+                    pending_space.push(c);
+                }
+            },
+            c => {
+                result.push_str(&pending_space);
+                pending_space.clear();
+                result.push(c);
+            }
+        }
+    }
+
+    if quoted {
+        return Err(Error::InvalidConfig(format!("Unterminated quote in config line {}", line_number)))
+    }
+    Ok(result)
+}
+
+/* Quotes a value if reading it back would otherwise change it */
+fn quote(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    let needs_quotes = value.starts_with(char::is_whitespace)
+        || value.ends_with(char::is_whitespace)
+        || value.contains(['#', ';']);
+    if needs_quotes {
+        format!("\"{}\"", escaped)
+    } else {
+        escaped
+    }
+}
+
+/* Parses "[section]", "[section \"subsection\"]" or the legacy "[section.subsection]" */
+fn parse_section(header: &str, line_number: usize) -> Result<String, Error> {
+    let invalid = || Error::InvalidConfig(format!("Bad section header in config line {}", line_number));
+
+    match header.split_once(|c: char| c.is_whitespace()) {
+        Some((section, subsection)) => {
+            let subsection = subsection.trim()
+                .strip_prefix('"')
+                .and_then(|subsection| subsection.strip_suffix('"'))
+                .ok_or_else(invalid)?
+                .replace("\\\"", "\"")
+                .replace("\\\\", "\\");
+            Ok(format!("{}.{}", section.to_lowercase(), subsection))
+        },
+        None => match header.split_once('.') {
+            Some((section, subsection)) => Ok(format!("{}.{}", section.to_lowercase(), subsection.to_lowercase())),
+            None => Ok(header.to_lowercase())
+        }
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/* Splits the lines of a config file into section headers and variables */
+fn scan(content: &str) -> Result<Vec<ConfigLine>, Error> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut scanned = Vec::new();
+    let mut section: Option<String> = None;
+
+    let mut index = 0;
+    while index < lines.len() {
+        let first = index;
+        let line_number = index + 1;
+        let mut line = lines[index].trim().to_string();
+        index += 1;
+
+        let header = line.starts_with('[');
+        if header {
+            let end = line.find(']')
+                .ok_or_else(|| Error::InvalidConfig(format!("Bad section header in config line {}", line_number)))?;
+            section = Some(parse_section(&line[1..end], line_number)?);
+            line = line[end + 1..].trim().to_string();
+        }
+
+        let mut variable = None;
+        if !line.is_empty() && !line.starts_with('#') && !line.starts_with(';') {
+            /* A backslash at the end of the line continues the value */
+            while line.ends_with('\\') && !line.ends_with("\\\\") && index < lines.len() {
+                line.pop();
+                line.push_str(lines[index]);
+                index += 1;
+            }
+
+            if section.is_none() {
+                return Err(Error::InvalidConfig(format!("Variable outside of a section in config line {}", line_number)))
+            }
+            let (name, value) = match line.split_once('=') {
+                Some((name, value)) => (name.trim(), unquote(value, line_number)?),
+                /* A variable without a value is a boolean true */
+                None => (line.trim(), String::from("true"))
+            };
+            if !is_valid_name(name) {
+                return Err(Error::InvalidConfig(format!("Bad variable name in config line {}", line_number)))
+            }
+            variable = Some((name.to_lowercase(), value));
+        }
+
+        scanned.push(ConfigLine { first, last: index - 1, section: section.clone(), header, variable });
+    }
+
+    Ok(scanned)
+}
+
+/* Splits a key into its normalized section (with subsection) and name */
+fn split_key(key: &str) -> Result<(String, String), Error> {
+    let invalid = || Error::InvalidArgument(format!("Invalid key '{}'", key));
+
+    let (section, name) = normalize_key(key).rsplit_once('.').map(|(section, name)| {
+        (section.to_string(), name.to_string())
+    }).ok_or_else(invalid)?;
+    if section.is_empty() || !is_valid_name(&name) {
+        return Err(invalid())
+    }
+    Ok((section, name))
+}
+
+fn format_header(section: &str) -> String {
+    match section.split_once('.') {
+        Some((section, subsection)) => {
+            format!("[{} \"{}\"]", section, subsection.replace('\\', "\\\\").replace('"', "\\\""))
+        },
+        None => format!("[{}]", section)
+    }
+}
+
+/*
+ * Changes a variable in the text of a config file. Everything else,
+ * including comments and formatting, is kept as it was. New variables go
+ * after the last line of their section, which is appended if missing.
+ */
+pub fn edit_config(content: &str, key: &str, edit: ConfigEdit) -> Result<String, Error> {
+    let (section, name) = split_key(key)?;
+    let scanned = scan(content)?;
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+
+    let matches: Vec<&ConfigLine> = scanned
+        .iter()
+        .filter(|line| line.section.as_ref() == Some(&section)
+            && line.variable.as_ref().is_some_and(|(variable, _)| *variable == name))
+        .collect();
+
+    let multiple = || Error::InvalidArgument(format!("{} has multiple values", key));
+    let missing = || Error::InvalidArgument(format!("{} is not set", key));
+
+    match edit {
+        ConfigEdit::Set(value) if matches.len() == 1 => {
+            let line = matches[0];
+            let mut replacement = format!("\t{} = {}", name, quote(value));
+            if line.header {
+                replacement = format_header(&section) + "\n" + &replacement;
+            }
+            lines.splice(line.first..=line.last, [replacement]);
+        },
+        ConfigEdit::Set(_) if matches.len() > 1 => return Err(multiple()),
+        ConfigEdit::Set(value) | ConfigEdit::Add(value) => {
+            let variable = format!("\t{} = {}", name, quote(value));
+            let last = scanned.iter().rev().find(|line| line.section.as_ref() == Some(&section)
+                && (line.header || line.variable.is_some()));
+            match last {
+                Some(line) => lines.insert(line.last + 1, variable),
+                None => {
+                    lines.push(format_header(&section));
+                    lines.push(variable);
+                }
+            }
+        },
+        ConfigEdit::Unset | ConfigEdit::UnsetAll if matches.is_empty() => return Err(missing()),
+        ConfigEdit::Unset if matches.len() > 1 => return Err(multiple()),
+        ConfigEdit::Unset | ConfigEdit::UnsetAll => {
+            /* Back to front, so the line numbers of earlier matches stay valid */
+            for line in matches.iter().rev() {
+                if line.header {
+                    lines.splice(line.first..=line.last, [format_header(&section)]);
+                } else {
+                    lines.drain(line.first..=line.last);
+                }
+            }
+        }
+    }
+
+    let mut content = lines.join("\n");
+    content.push('\n');
+    Ok(content)
+}
+
+/* Edits a config file in place, creating it if needed */
+pub fn edit_config_file(path: &Path, key: &str, edit: ConfigEdit) -> Result<(), Error> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err.into())
+    };
+    Ok(fs::write(path, edit_config(&content, key, edit)?)?)
+}
+
+pub fn home() -> Option<PathBuf> {
+    env::var_os("HOME").map(PathBuf::from)
+}
+
+/* A file in git's directory below $XDG_CONFIG_HOME, or ~/.config without it */
+pub fn xdg_config_path(name: &str) -> Option<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(xdg) if !xdg.is_empty() => Some(PathBuf::from(xdg).join("git").join(name)),
+        _ => home().map(|home| home.join(".config/git").join(name))
+    }
+}
+
+/* Expands a leading "~/" to the home directory */
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), home()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path)
+    }
+}
+
+/*
+ * The condition of an `includeIf` section: "gitdir:<pattern>" (or
+ * "gitdir/i:" ignoring case) matches the repository's .git directory,
+ * "onbranch:<pattern>" the checked-out branch. As in git, a pattern ending
+ * in '/' matches everything below it and a relative gitdir pattern may
+ * match at any depth.
+ */
+fn include_condition_holds(condition: &str, file: &Path, repository: Option<&Repository>) -> bool {
+    let (kind, pattern) = match condition.split_once(':') {
+        Some(split) => split,
+        None => return false
+    };
+
+    let mut pattern = pattern.to_string();
+    if pattern.ends_with('/') {
+        pattern.push_str("**");
+    }
+
+    match kind {
+        "gitdir" | "gitdir/i" => {
+            let Some(Ok(git_dir)) = repository.map(|repository| fs::canonicalize(repository.git_dir())) else {
+                return false
+            };
+            let pattern = if let Some(relative) = pattern.strip_prefix("./") {
+                let base = file.parent().unwrap_or(Path::new(""));
+                base.join(relative).to_string_lossy().to_string()
+            } else if pattern.starts_with("~/") {
+                expand_home(&pattern).to_string_lossy().to_string()
+            } else if !pattern.starts_with('/') {
+                format!("**/{}", pattern)
+            } else {
+                pattern
+            };
+            let git_dir = git_dir.to_string_lossy();
+            wildmatch(pattern.as_bytes(), git_dir.as_bytes(), kind == "gitdir/i")
+        },
+        "onbranch" => match repository.map(Repository::current_branch) {
+            Some(Ok(Some(branch))) => wildmatch(pattern.as_bytes(), branch.as_bytes(), false),
+            _ => false
+        },
+        _ => false
+    }
+}
+
+impl Config {
+    /* Parses the text of a config file, without following includes */
+    pub fn parse(content: &str) -> Result<Config, Error> {
+        let entries = scan(content)?
+            .into_iter()
+            .filter_map(|line| {
+                let (name, value) = line.variable?;
+                Some((format!("{}.{}", line.section?, name), value))
+            })
+            .collect();
+        Ok(Config { entries })
+    }
+
+    /*
+     * Reads a config file and the files it includes, a missing file is
+     * empty. Conditional includes need the repository to be evaluated.
+     */
+    pub fn read(path: &Path, repository: Option<&Repository>) -> Result<Config, Error> {
+        Self::read_included(path, repository, 0)
+    }
+
+    fn read_included(path: &Path, repository: Option<&Repository>, depth: usize) -> Result<Config, Error> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(Error::InvalidConfig(format!("Exceeded maximum include depth in {}", path.display())))
+        }
+
+        let parsed = match fs::read_to_string(path) {
+            Ok(content) => Config::parse(&content)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(err) => return Err(err.into())
+        };
+
+        /* Included variables are inserted where the include is */
+        let mut config = Config::default();
+        for (key, value) in parsed.entries {
+            let include = match key.strip_suffix(".path") {
+                Some("include") => true,
+                Some(section) => section.strip_prefix("includeif.")
+                    .is_some_and(|condition| include_condition_holds(condition, path, repository)),
+                None => false
+            };
+            config.entries.push((key, value.clone()));
+
+            if include {
+                let included = expand_home(&value);
+                let included = match path.parent() {
+                    Some(base) if included.is_relative() => base.join(included),
+                    _ => included
+                };
+                config.entries.extend(Self::read_included(&included, repository, depth + 1)?.entries);
+            }
+        }
+        Ok(config)
+    }
+
+    /*
+     * The system, global, repository and worktree config in that order, so
+     * later files override earlier ones. Outside of a repository only the
+     * system and global config are read.
+     */
+    pub fn load(repository: Option<&Repository>) -> Result<Config, Error> {
+        let mut config = Config::default();
+        for scope in [ConfigScope::System, ConfigScope::Global, ConfigScope::Local] {
+            for path in scope_paths(&scope, repository) {
+                config.entries.extend(Config::read(&path, repository)?.entries);
+            }
+        }
+
+        if config.get_bool("extensions.worktreeConfig")?.unwrap_or(false) {
+            for path in scope_paths(&ConfigScope::Worktree, repository) {
+                config.entries.extend(Config::read(&path, repository)?.entries);
+            }
+        }
+        Ok(config)
+    }
+
+    /* The last value of a variable, e.g. "user.name" */
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.get_all(key).pop()
+    }
+
+    /* All values of a multi-valued variable, in the order they were read */
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        let key = normalize_key(key);
+        self.entries
+            .iter()
+            .filter(|(name, _)| *name == key)
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>, Error> {
+        self.get(key).map(|value| parse_bool(value).ok_or_else(|| Error::InvalidConfig(
+            format!("Bad boolean config value '{}' for '{}'", value, key)
+        ))).transpose()
+    }
+}
+
+/* The files of a scope, in the order they are read. The last one is written */
+pub fn scope_paths(scope: &ConfigScope, repository: Option<&Repository>) -> Vec<PathBuf> {
+    match scope {
+        ConfigScope::System => {
+            if env::var_os("GIT_CONFIG_NOSYSTEM").is_some() {
+                return Vec::new()
+            }
+            vec![env::var_os("GIT_CONFIG_SYSTEM").map(PathBuf::from).unwrap_or(PathBuf::from("/etc/gitconfig"))]
+        },
+        /* Like git, the global config is $XDG_CONFIG_HOME/git/config and ~/.gitconfig */
+        ConfigScope::Global => {
+            if let Some(global) = env::var_os("GIT_CONFIG_GLOBAL") {
+                return vec![PathBuf::from(global)]
+            }
+            let mut paths: Vec<PathBuf> = xdg_config_path("config").into_iter().collect();
+            paths.extend(home().map(|home| home.join(".gitconfig")));
+            paths
+        },
+        ConfigScope::Local => repository.map(|repository| repository.git_path("config")).into_iter().collect(),
+        ConfigScope::Worktree => repository.map(|repository| repository.git_path("config.worktree")).into_iter().collect(),
+        ConfigScope::File(path) => vec![path.clone()],
+    }
+}
+
+/* Lowercases the section and the variable name of a key, but not the subsection */
+pub fn normalize_key(key: &str) -> String {
+    match (key.split_once('.'), key.rsplit_once('.')) {
+        (Some((section, _)), Some((rest, name))) if rest.len() > section.len() => {
+            format!("{}.{}.{}", section.to_lowercase(), &rest[section.len() + 1..], name.to_lowercase())
+        },
+        _ => key.to_lowercase()
+    }
+}
+
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" => Some(true),
+        "false" | "no" | "off" | "" => Some(false),
+        number => parse_int(number).map(|number| number != 0)
+    }
+}
+
+/* Parses an integer with an optional k, m or g suffix (powers of 1024) */
+pub fn parse_int(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (digits, factor) = match value.chars().last()?.to_ascii_lowercase() {
+        'k' => (&value[..value.len() - 1], 1 << 10),
+        'm' => (&value[..value.len() - 1], 1 << 20),
+        'g' => (&value[..value.len() - 1], 1 << 30),
+        _ => (value, 1)
+    };
+    digits.parse::<i64>().ok()?.checked_mul(factor)
+}
+
+fn typed(value: &str, key: &str, value_type: &Option<ConfigType>) -> Result<String, Error> {
+    let invalid = |kind: &str| Error::InvalidConfig(
+        format!("Bad {} config value '{}' for '{}'", kind, value, key)
+    );
+    match value_type {
+        Some(ConfigType::Bool) => parse_bool(value).map(|value| value.to_string()).ok_or_else(|| invalid("boolean")),
+        Some(ConfigType::Int) => parse_int(value).map(|value| value.to_string()).ok_or_else(|| invalid("numeric")),
+        None => Ok(value.to_string())
+    }
+}
+
+fn read_scope(scope: &Option<ConfigScope>, repository: Option<&Repository>) -> Result<Config, Error> {
+    match scope {
+        Some(scope) => {
+            let mut config = Config::default();
+            for path in scope_paths(scope, repository) {
+                config.entries.extend(Config::read(&path, repository)?.entries);
+            }
+            Ok(config)
+        },
+        None => Config::load(repository)
+    }
+}
+
+impl Repository {
+    /* The config of this repository, including the system and global config */
+    pub fn config(&self) -> Result<Config, Error> {
+        Config::load(Some(self))
+    }
+}
+
+/*
+ * Runs `nit config`, returning false when a variable to get is not set.
+ * Without a repository only the system and global config are available.
+ */
+pub fn config(repository: Option<&Repository>, options: ConfigOptions) -> Result<bool, Error> {
+    let ConfigOptions { action, scope, value_type } = options;
+    let write_path = || scope_paths(scope.as_ref().unwrap_or(&ConfigScope::Local), repository)
+        .pop()
+        .ok_or_else(|| Error::InvalidArgument("No config file to write to".into()));
+
+    match action {
+        ConfigAction::Get { key, all } => {
+            split_key(&key)?;
+            let config = read_scope(&scope, repository)?;
+            let mut values = config.get_all(&key);
+            if !all {
+                values = values.pop().into_iter().collect();
+            }
+            for value in &values {
+                println!("{}", typed(value, &key, &value_type)?);
+            }
+            return Ok(!values.is_empty())
+        },
+        ConfigAction::List => {
+            for (key, value) in read_scope(&scope, repository)?.entries {
+                println!("{}={}", key, value);
+            }
+        },
+        ConfigAction::Set { key, value, add } => {
+            /* Values are stored in canonical form when a type is given */
+            let value = typed(&value, &key, &value_type)?;
+            let edit = if add { ConfigEdit::Add(&value) } else { ConfigEdit::Set(&value) };
+            edit_config_file(&write_path()?, &key, edit)?;
+        },
+        ConfigAction::Unset { key, all } => {
+            let edit = if all { ConfigEdit::UnsetAll } else { ConfigEdit::Unset };
+            edit_config_file(&write_path()?, &key, edit)?;
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test_util::*;
+
+    const CONTENT: &str = r#"
+# comment
+[core]
+	bare = false
+	filemode
+[user]
+	name = "Ada  Lovelace" ; trailing comment
+	email = ada@example.com
+[remote "Origin"]
+	url = https://example.com/\
+repo.git
+	fetch = +refs/heads/*:refs/remotes/origin/*
+	fetch = +refs/tags/*:refs/tags/*
+[branch.main]
+	remote = origin
+"#;
+
+    #[test]
+    fn parse_sections_and_values() {
+        let config = Config::parse(CONTENT).unwrap();
+
+        assert_eq!(config.get("core.bare"), Some("false"));
+        assert_eq!(config.get("core.fileMode"), Some("true"));
+        assert_eq!(config.get("user.name"), Some("Ada  Lovelace"));
+        assert_eq!(config.get("remote.Origin.url"), Some("https://example.com/repo.git"));
+        assert_eq!(config.get("remote.origin.url"), None);
+        assert_eq!(config.get_all("remote.Origin.fetch").len(), 2);
+        assert_eq!(config.get("branch.main.remote"), Some("origin"));
+    }
+
+    #[test]
+    fn reject_variable_outside_section() {
+        assert!(Config::parse("name = value\n").is_err());
+    }
+
+    #[test]
+    fn parse_booleans_and_integers() {
+        assert_eq!(parse_bool("Yes"), Some(true));
+        assert_eq!(parse_bool("off"), Some(false));
+        assert_eq!(parse_bool("maybe"), None);
+        assert_eq!(parse_int("512"), Some(512));
+        assert_eq!(parse_int("2k"), Some(2048));
+        assert_eq!(parse_int("1G"), Some(1 << 30));
+        assert_eq!(parse_int("1x"), None);
+    }
+
+    #[test]
+    fn edit_keeps_comments_and_layout() {
+        let set = edit_config(CONTENT, "user.email", ConfigEdit::Set("ada@lovelace.org")).unwrap();
+        let added = edit_config(&set, "core.editor", ConfigEdit::Add("vim # or emacs")).unwrap();
+        let created = edit_config(&added, "alias.Lg", ConfigEdit::Set("log --oneline")).unwrap();
+        let unset = edit_config(&created, "remote.Origin.fetch", ConfigEdit::UnsetAll).unwrap();
+
+        let config = Config::parse(&unset).unwrap();
+        assert!(unset.contains("# comment\n"));
+        assert!(unset.contains("\tname = \"Ada  Lovelace\" ; trailing comment\n"));
+        assert_eq!(config.get("user.email"), Some("ada@lovelace.org"));
+        assert_eq!(config.get("core.editor"), Some("vim # or emacs"));
+        assert!(unset.ends_with("[alias]\n\tlg = log --oneline\n"));
+        assert!(config.get_all("remote.Origin.fetch").is_empty());
+        assert_eq!(config.get("remote.Origin.url"), Some("https://example.com/repo.git"));
+    }
+
+    #[test]
+    fn refuse_to_overwrite_multiple_values() {
+        assert!(edit_config(CONTENT, "remote.Origin.fetch", ConfigEdit::Set("x")).is_err());
+        assert!(edit_config(CONTENT, "remote.Origin.fetch", ConfigEdit::Unset).is_err());
+        assert!(edit_config(CONTENT, "user.missing", ConfigEdit::Unset).is_err());
+    }
+
+    #[test]
+    fn follow_includes() {
+        let dir = TempDir::new("config");
+        fs::write(dir.join("main"), "[user]\n\tname = Before\n[include]\n\tpath = extra\n[core]\n\tbare = true\n").unwrap();
+        fs::write(dir.join("extra"), "[user]\n\tname = Included\n").unwrap();
+
+        let config = Config::read(&dir.join("main"), None).unwrap();
+
+        assert_eq!(config.get("user.name"), Some("Included"));
+        assert_eq!(config.get_bool("core.bare").unwrap(), Some(true));
+    }
+}
@@ -0,0 +1,236 @@
+use crate::command::*;
+
+use std::time::SystemTime;
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, Offset, TimeZone};
+
+/* The current time in seconds since the epoch */
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
+
+/* Parses a timezone as written in commits, e.g. "+0100" or "-0530" */
+pub fn parse_offset(timezone: &str) -> Option<FixedOffset> {
+    let (sign, digits) = match timezone.split_at_checked(1)? {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return None
+    };
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None
+    }
+
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/* Formats an offset in seconds east of UTC as in commits, e.g. "-0530" */
+pub fn format_offset(seconds: i32) -> String {
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let minutes = seconds.abs() / 60;
+    format!("{}{:02}{:02}", sign, minutes / 60, minutes % 60)
+}
+
+/* The offset of the local timezone at `timestamp`, which varies with DST */
+pub fn local_offset(timestamp: i64) -> String {
+    let local = DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .with_timezone(&Local);
+    format_offset(local.offset().fix().local_minus_utc())
+}
+
+pub fn to_datetime(timestamp: i64, timezone: &str) -> DateTime<FixedOffset> {
+    let offset = parse_offset(timezone).unwrap_or(FixedOffset::east_opt(0).unwrap());
+    DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .with_timezone(&offset)
+}
+
+/* The default date format of git, e.g. "Sun Nov 2 18:05:53 2025 +0100" */
+pub fn format_default(timestamp: i64, timezone: &str) -> String {
+    to_datetime(timestamp, timezone)
+        .format("%a %b %-d %H:%M:%S %Y %z")
+        .to_string()
+}
+
+/*
+ * Formats a date as selected by `--date`. All formats but `local` show the
+ * time in the timezone it was recorded in.
+ */
+pub fn format_date(timestamp: i64, timezone: &str, format: &DateFormat, now: i64) -> String {
+    let date = to_datetime(timestamp, timezone);
+    match format {
+        DateFormat::Default => format_default(timestamp, timezone),
+        DateFormat::Local => DateTime::from_timestamp(timestamp, 0)
+            .unwrap_or_default()
+            .with_timezone(&Local)
+            .format("%a %b %-d %H:%M:%S %Y")
+            .to_string(),
+        DateFormat::Iso => date.format("%Y-%m-%d %H:%M:%S %z").to_string(),
+        DateFormat::IsoStrict => date.format("%Y-%m-%dT%H:%M:%S%:z").to_string(),
+        DateFormat::Rfc => date.format("%a, %-d %b %Y %H:%M:%S %z").to_string(),
+        DateFormat::Short => date.format("%Y-%m-%d").to_string(),
+        DateFormat::Relative => format_relative(timestamp, now),
+        DateFormat::Raw => format!("{} {}", timestamp, timezone),
+        DateFormat::Unix => timestamp.to_string(),
+    }
+}
+
+fn plural(count: i64, unit: &str) -> String {
+    if count == 1 {
+        format!("{} {}", count, unit)
+    } else {
+        format!("{} {}s", count, unit)
+    }
+}
+
+/* Describes the age of `timestamp` the way git does, e.g. "3 days ago" */
+pub fn format_relative(timestamp: i64, now: i64) -> String {
+    let seconds = now - timestamp;
+    if seconds < 0 {
+        return String::from("in the future")
+    }
+    if seconds < 90 {
+        return format!("{} ago", plural(seconds, "second"))
+    }
+
+    let minutes = (seconds + 30) / 60;
+    if minutes < 90 {
+        return format!("{} ago", plural(minutes, "minute"))
+    }
+
+    let hours = (minutes + 30) / 60;
+    if hours < 36 {
+        return format!("{} ago", plural(hours, "hour"))
+    }
+
+    let days = (hours + 12) / 24;
+    if days < 14 {
+        return format!("{} ago", plural(days, "day"))
+    }
+    if days < 70 {
+        return format!("{} ago", plural((days + 3) / 7, "week"))
+    }
+    if days < 365 {
+        return format!("{} ago", plural((days + 15) / 30, "month"))
+    }
+
+    let total_months = (days * 12 * 2 + 365) / (365 * 2);
+    let (years, months) = (total_months / 12, total_months % 12);
+    if years < 5 && months > 0 {
+        format!("{}, {} ago", plural(years, "year"), plural(months, "month"))
+    } else {
+        format!("{} ago", plural((days + 183) / 365, "year"))
+    }
+}
+
+fn parse_ago(date: &str, now: i64) -> Option<i64> {
+    let mut words = date.split_whitespace();
+    let count: i64 = words.next()?.parse().ok()?;
+    let unit = words.next()?;
+    if words.next()? != "ago" || words.next().is_some() {
+        return None
+    }
+
+    let seconds = match unit.trim_end_matches('s') {
+        "second" => 1,
+        "minute" => 60,
+        "hour" => 3600,
+        "day" => 86400,
+        "week" => 7 * 86400,
+        "month" => 30 * 86400,
+        "year" => 365 * 86400,
+        _ => return None
+    };
+    Some(now - count * seconds)
+}
+
+/*
+ * Parses the dates accepted by `--since` and `--until`: unix timestamps
+ * ("1764365370" or "@1764365370"), ISO dates with or without a time,
+ * RFC 2822 / RFC 3339 dates and relative dates such as "2 weeks ago".
+ * Dates without a timezone are interpreted in the local timezone.
+ */
+pub fn parse_date(date: &str, now: i64) -> Option<i64> {
+    let date = date.trim();
+    if date == "now" {
+        return Some(now)
+    }
+    if let Some(seconds) = date.strip_prefix('@') {
+        return seconds.parse().ok()
+    }
+    if !date.is_empty() && date.chars().all(|c| c.is_ascii_digit()) {
+        return date.parse().ok()
+    }
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return Some(date.timestamp())
+    }
+    if let Ok(date) = DateTime::parse_from_rfc2822(date) {
+        return Some(date.timestamp())
+    }
+    if let Ok(date) = DateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S %z") {
+        return Some(date.timestamp())
+    }
+
+    let local = |naive: NaiveDateTime| Local.from_local_datetime(&naive)
+        .earliest()
+        .map(|date| date.timestamp());
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(date, format) {
+            return local(naive)
+        }
+    }
+    if let Ok(day) = NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        return local(day.and_hms_opt(0, 0, 0)?)
+    }
+
+    parse_ago(date, now)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format_date_with_timezone() {
+        let date = format_default(1762103153, "+0100");
+
+        assert_eq!(date, "Sun Nov 2 18:05:53 2025 +0100");
+    }
+
+    #[test]
+    fn format_date_in_recorded_timezone() {
+        let (timestamp, now) = (1762103153, 1764365370);
+
+        assert_eq!(format_date(timestamp, "-0530", &DateFormat::Iso, now), "2025-11-02 11:35:53 -0530");
+        assert_eq!(format_date(timestamp, "+0100", &DateFormat::IsoStrict, now), "2025-11-02T18:05:53+01:00");
+        assert_eq!(format_date(timestamp, "+0100", &DateFormat::Rfc, now), "Sun, 2 Nov 2025 18:05:53 +0100");
+        assert_eq!(format_date(timestamp, "+0900", &DateFormat::Short, now), "2025-11-03");
+        assert_eq!(format_date(timestamp, "-0000", &DateFormat::Raw, now), "1762103153 -0000");
+        assert_eq!(format_date(timestamp, "+0100", &DateFormat::Unix, now), "1762103153");
+        assert_eq!(format_offset(-19800), "-0530");
+    }
+
+    #[test]
+    fn format_relative_dates() {
+        let now = 1764365370;
+
+        assert_eq!(format_relative(now - 30, now), "30 seconds ago");
+        assert_eq!(format_relative(now - 3 * 86400, now), "3 days ago");
+        assert_eq!(format_relative(now - 21 * 86400, now), "3 weeks ago");
+        assert_eq!(format_relative(now - 400 * 86400, now), "1 year, 1 month ago");
+    }
+
+    #[test]
+    fn parse_absolute_and_relative_dates() {
+        let now = 1764365370;
+
+        assert_eq!(parse_date("@1762103153", now), Some(1762103153));
+        assert_eq!(parse_date("2025-11-02T17:05:53+00:00", now), Some(1762103153));
+        assert_eq!(parse_date("2 days ago", now), Some(now - 2 * 86400));
+        assert_eq!(parse_date("yesterday-ish", now), None);
+    }
+}
@@ -0,0 +1,632 @@
+use crate::error::*;
+use crate::hash::*;
+use crate::command::*;
+use crate::index::*;
+use crate::log::*;
+use crate::object::*;
+use crate::status::*;
+use crate::tag::*;
+use crate::tree::*;
+use crate::repository::*;
+
+use std::io;
+
+use std::path::PathBuf;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edit {
+    /* Line `old` of the old side is line `new` of the new side */
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/*
+ * Myers' O((N+M)D) algorithm: finds the furthest reaching path on each
+ * diagonal k = x - y for an increasing number of edits d, keeping every
+ * round of furthest points so the shortest edit script can be traced back.
+ */
+fn myers<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Edit> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    let offset = max as usize;
+    let mut v = vec![0_isize; 2 * offset + 2];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=max {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let index = (k + max) as usize;
+            let mut x = if k == -d || (k != d && v[index - 1] < v[index + 1]) {
+                v[index + 1]
+            } else {
+                v[index - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index] = x;
+            if x >= n && y >= m {
+                break 'search
+            }
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let index = (k + max) as usize;
+        let previous_k = if k == -d || (k != d && v[index - 1] < v[index + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = v[(previous_k + max) as usize];
+        let previous_y = previous_x - previous_k;
+
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Equal(x as usize, y as usize));
+        }
+        if d > 0 {
+            if x == previous_x {
+                edits.push(Edit::Insert((y - 1) as usize));
+            } else {
+                edits.push(Edit::Delete((x - 1) as usize));
+            }
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+
+    edits.reverse();
+    edits
+}
+
+/* Runs Myers on the lines between the common prefix and suffix */
+pub fn diff_lines<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Edit> {
+    let prefix = a.iter().zip(b).take_while(|(a, b)| a == b).count();
+    let suffix = a[prefix..].iter().rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut edits: Vec<Edit> = (0..prefix).map(|i| Edit::Equal(i, i)).collect();
+    for edit in myers(&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]) {
+        edits.push(match edit {
+            Edit::Equal(old, new) => Edit::Equal(old + prefix, new + prefix),
+            Edit::Delete(old) => Edit::Delete(old + prefix),
+            Edit::Insert(new) => Edit::Insert(new + prefix),
+        });
+    }
+    for i in 0..suffix {
+        edits.push(Edit::Equal(a.len() - suffix + i, b.len() - suffix + i));
+    }
+    edits
+}
+
+/* Splits content into lines, keeping the line terminators */
+pub fn split_lines(content: &[u8]) -> Vec<&[u8]> {
+    content.split_inclusive(|&c| c == b'\n').collect()
+}
+
+/* Like git, content with a NUL byte in the first 8000 bytes is binary */
+pub fn is_binary(content: &[u8]) -> bool {
+    content.iter().take(8000).any(|&c| c == 0)
+}
+
+#[derive(Debug)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    pub edits: Vec<Edit>,
+}
+
+/*
+ * Groups the edits into hunks with `context` unchanged lines around each
+ * change. Changes closer than twice the context share a hunk.
+ */
+pub fn hunks(edits: &[Edit], context: usize) -> Vec<Hunk> {
+    let changes: Vec<usize> = edits.iter()
+        .enumerate()
+        .filter(|(_, edit)| !matches!(edit, Edit::Equal(..)))
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut groups: Vec<(usize, usize)> = Vec::new();
+    for &change in &changes {
+        match groups.last_mut() {
+            Some((_, last)) if change - *last <= 2 * context + 1 => *last = change,
+            _ => groups.push((change, change))
+        }
+    }
+
+    /* Line numbers (0-based) on each side before every edit */
+    let mut positions = Vec::with_capacity(edits.len() + 1);
+    let (mut old, mut new) = (0, 0);
+    for edit in edits {
+        positions.push((old, new));
+        match edit {
+            Edit::Equal(..) => { old += 1; new += 1 },
+            Edit::Delete(_) => old += 1,
+            Edit::Insert(_) => new += 1,
+        }
+    }
+    positions.push((old, new));
+
+    groups.into_iter().map(|(first, last)| {
+        let start = first.saturating_sub(context);
+        let end = (last + context + 1).min(edits.len());
+        let (old_start, new_start) = positions[start];
+        let (old_end, new_end) = positions[end];
+        let (old_len, new_len) = (old_end - old_start, new_end - new_start);
+
+        Hunk {
+            /* An empty range starts at the line before it */
+            old_start: if old_len == 0 { old_start } else { old_start + 1 },
+            old_len,
+            new_start: if new_len == 0 { new_start } else { new_start + 1 },
+            new_len,
+            edits: edits[start..end].to_vec(),
+        }
+    }).collect()
+}
+
+fn format_range(start: usize, len: usize) -> String {
+    if len == 1 {
+        start.to_string()
+    } else {
+        format!("{},{}", start, len)
+    }
+}
+
+fn push_line(output: &mut Vec<u8>, marker: u8, line: &[u8]) {
+    output.push(marker);
+    output.extend_from_slice(line);
+    if !line.ends_with(b"\n") {
+        output.extend_from_slice(b"\n\\ No newline at end of file\n");
+    }
+}
+
+/* The hunks of a unified diff between two contents, without file headers */
+pub fn unified_diff(old: &[u8], new: &[u8], context: usize) -> Vec<u8> {
+    let (old, new) = (split_lines(old), split_lines(new));
+    let edits = diff_lines(&old, &new);
+
+    let mut output = Vec::new();
+    for hunk in hunks(&edits, context) {
+        output.extend(format!("@@ -{} +{} @@\n",
+            format_range(hunk.old_start, hunk.old_len),
+            format_range(hunk.new_start, hunk.new_len)).into_bytes());
+        for edit in hunk.edits {
+            match edit {
+                Edit::Equal(i, _) => push_line(&mut output, b' ', old[i]),
+                Edit::Delete(i) => push_line(&mut output, b'-', old[i]),
+                Edit::Insert(i) => push_line(&mut output, b'+', new[i]),
+            }
+        }
+    }
+    output
+}
+
+/* Counts the inserted and deleted lines, or None for binary content */
+pub fn count_changes(old: &[u8], new: &[u8]) -> Option<(usize, usize)> {
+    if is_binary(old) || is_binary(new) {
+        return None
+    }
+
+    let (old, new) = (split_lines(old), split_lines(new));
+    let edits = diff_lines(&old, &new);
+    let insertions = edits.iter().filter(|edit| matches!(edit, Edit::Insert(_))).count();
+    let deletions = edits.iter().filter(|edit| matches!(edit, Edit::Delete(_))).count();
+    Some((insertions, deletions))
+}
+
+/* One side of a comparison: path -> (mode, object name) */
+pub struct Side {
+    pub entries: BTreeMap<String, (FileMode, Hash)>,
+    /* Content is read from the working tree rather than the object database */
+    pub worktree: bool,
+}
+
+impl Side {
+    pub fn tree(repository: &Repository, revision: &str) -> Result<Side, Error> {
+        let hash = repository.resolve_revision(&format!("{}^{{tree}}", revision))?;
+        let entries = Tree::read(repository, &hash)?
+            .flatten(repository, "")?
+            .into_iter()
+            .map(|(path, entry)| (path, (entry.mode, entry.key)))
+            .collect();
+        Ok(Side { entries, worktree: false })
+    }
+
+    /* HEAD's tree, empty on an unborn branch */
+    pub fn head(repository: &Repository) -> Result<Side, Error> {
+        match repository.read_ref("HEAD")? {
+            Some(_) => Side::tree(repository, "HEAD"),
+            None => Ok(Side { entries: BTreeMap::new(), worktree: false })
+        }
+    }
+
+    /* The staged files, leaving out those only intended to be added like git */
+    pub fn index(index: &Index) -> Side {
+        let entries = index.entries
+            .iter()
+            .filter(|entry| !entry.intent_to_add())
+            .map(|entry| (entry.name.clone(), (entry.mode(), entry.key.clone())))
+            .collect();
+        Side { entries, worktree: false }
+    }
+
+    /* The working tree files that are tracked in the index */
+    pub fn worktree(repository: &Repository, index: &Index) -> Result<Side, Error> {
+        let index_mtime = repository.index_mtime()?;
+        let mut entries = BTreeMap::new();
+        for entry in &index.entries {
+            if let Some(file) = worktree_entry(repository, entry, index_mtime)? {
+                entries.insert(entry.name.clone(), file);
+            }
+        }
+        Ok(Side { entries, worktree: true })
+    }
+
+    fn content(&self, repository: &Repository, path: &str, mode: FileMode, key: &Hash) -> Result<Vec<u8>, Error> {
+        if mode == FileMode::Gitlink {
+            return Ok(format!("Subproject commit {}\n", key).into_bytes())
+        }
+        if self.worktree {
+            return read_worktree_file(&repository.worktree_path(path)?)
+        }
+        Ok(repository.read_object_of_kind(key, ObjectType::Blob)?.content)
+    }
+}
+
+pub struct FilePair {
+    pub path: String,
+    pub old: Option<(FileMode, Hash)>,
+    pub new: Option<(FileMode, Hash)>,
+    pub old_content: Vec<u8>,
+    pub new_content: Vec<u8>,
+}
+
+pub fn matches_paths(path: &str, paths: &[PathBuf]) -> bool {
+    paths.is_empty() || paths.iter().any(|limit| {
+        let limit = limit.to_string_lossy();
+        let limit = limit.trim_end_matches('/');
+        limit.is_empty() || limit == "." || path == limit || path.starts_with(&format!("{}/", limit))
+    })
+}
+
+/* The files that differ between the two sides, sorted by path */
+pub fn compare(repository: &Repository, old: &Side, new: &Side, paths: &[PathBuf]) -> Result<Vec<FilePair>, Error> {
+    let all_paths: BTreeSet<&String> = old.entries.keys().chain(new.entries.keys()).collect();
+
+    let mut pairs = Vec::new();
+    for path in all_paths {
+        if !matches_paths(path, paths) {
+            continue
+        }
+        let old_entry = old.entries.get(path);
+        let new_entry = new.entries.get(path);
+        if old_entry == new_entry {
+            continue
+        }
+
+        let load = |side: &Side, entry: Option<&(FileMode, Hash)>| match entry {
+            Some((mode, key)) => side.content(repository, path, *mode, key),
+            None => Ok(Vec::new())
+        };
+        pairs.push(FilePair {
+            path: path.clone(),
+            old: old_entry.cloned(),
+            new: new_entry.cloned(),
+            old_content: load(old, old_entry)?,
+            new_content: load(new, new_entry)?,
+        });
+    }
+    Ok(pairs)
+}
+
+fn short(hash: &Hash) -> String {
+    hash.to_string()[..7].to_string()
+}
+
+pub fn format_patch(pair: &FilePair, context: usize) -> Vec<u8> {
+    let path = &pair.path;
+    let mut header = format!("diff --git a/{} b/{}\n", path, path);
+    let zero = Hash([0; 20]);
+    let bits = |side: &Option<(FileMode, Hash)>| side.clone().map(|(mode, key)| (mode.bits(), key));
+    let (old_mode, old_key) = bits(&pair.old).unwrap_or((0, zero.clone()));
+    let (new_mode, new_key) = bits(&pair.new).unwrap_or((0, zero));
+
+    match (&pair.old, &pair.new) {
+        (None, _) => header.push_str(&format!("new file mode {:06o}\n", new_mode)),
+        (_, None) => header.push_str(&format!("deleted file mode {:06o}\n", old_mode)),
+        _ if old_mode != new_mode => {
+            header.push_str(&format!("old mode {:06o}\nnew mode {:06o}\n", old_mode, new_mode));
+        },
+        _ => {}
+    }
+
+    if old_key != new_key {
+        header.push_str(&format!("index {}..{}", short(&old_key), short(&new_key)));
+        if pair.old.is_some() && pair.new.is_some() && old_mode == new_mode {
+            header.push_str(&format!(" {:06o}", old_mode));
+        }
+        header.push('\n');
+    }
+
+    let old_name = if pair.old.is_some() { format!("a/{}", path) } else { String::from("/dev/null") };
+    let new_name = if pair.new.is_some() { format!("b/{}", path) } else { String::from("/dev/null") };
+
+    let mut output = header.into_bytes();
+    if old_key == new_key {
+        return output
+    }
+    if is_binary(&pair.old_content) || is_binary(&pair.new_content) {
+        output.extend(format!("Binary files {} and {} differ\n", old_name, new_name).into_bytes());
+        return output
+    }
+
+    output.extend(format!("--- {}\n+++ {}\n", old_name, new_name).into_bytes());
+    output.extend(unified_diff(&pair.old_content, &pair.new_content, context));
+    output
+}
+
+pub fn format_numstat(pairs: &[FilePair]) -> String {
+    let mut output = String::new();
+    for pair in pairs {
+        match count_changes(&pair.old_content, &pair.new_content) {
+            Some((insertions, deletions)) => {
+                output.push_str(&format!("{}\t{}\t{}\n", insertions, deletions, pair.path));
+            },
+            None => output.push_str(&format!("-\t-\t{}\n", pair.path))
+        }
+    }
+    output
+}
+
+fn plural(count: usize, singular: &str, plural: &str) -> String {
+    format!("{} {}", count, if count == 1 { singular } else { plural })
+}
+
+/*
+ * The diffstat graph: one line per file with the number of changed lines
+ * and a bar of '+' and '-', scaled down to fit in 80 columns.
+ */
+pub fn format_stat(pairs: &[FilePair]) -> String {
+    const WIDTH: usize = 80;
+
+    let counts: Vec<Option<(usize, usize)>> = pairs
+        .iter()
+        .map(|pair| count_changes(&pair.old_content, &pair.new_content))
+        .collect();
+    let name_width = pairs.iter().map(|pair| pair.path.chars().count()).max().unwrap_or(0);
+    let max_change = counts.iter().flatten().map(|(i, d)| i + d).max().unwrap_or(0);
+    let count_width = if counts.iter().any(Option::is_none) {
+        max_change.to_string().len().max(3)
+    } else {
+        max_change.to_string().len()
+    };
+    /* " <name> | <count> " and the graph leave the last column free */
+    let graph_width = WIDTH.saturating_sub(name_width + count_width + 6).max(6);
+    let scale = |n: usize| if max_change <= graph_width || n == 0 {
+        n
+    } else {
+        1 + n * (graph_width - 1) / max_change
+    };
+
+    let mut output = String::new();
+    let (mut insertions, mut deletions) = (0, 0);
+    for (pair, count) in pairs.iter().zip(&counts) {
+        match count {
+            Some((inserted, deleted)) => {
+                insertions += inserted;
+                deletions += deleted;
+                /* Scale the total and the smaller side, so the bar keeps its length */
+                let mut total = scale(inserted + deleted);
+                if total < 2 && *inserted > 0 && *deleted > 0 {
+                    total = 2;
+                }
+                let (plus, minus) = if inserted < deleted {
+                    (scale(*inserted), total - scale(*inserted))
+                } else {
+                    (total - scale(*deleted), scale(*deleted))
+                };
+                let line = format!(" {:<name_width$} | {:>count_width$} {}{}",
+                    pair.path,
+                    inserted + deleted,
+                    "+".repeat(plus),
+                    "-".repeat(minus));
+                output.push_str(line.trim_end());
+                output.push('\n');
+            },
+            None => {
+                output.push_str(&format!(" {:<name_width$} | {:>count_width$} {} -> {} bytes\n",
+                    pair.path,
+                    "Bin",
+                    pair.old_content.len(),
+                    pair.new_content.len()));
+            }
+        }
+    }
+
+    if !pairs.is_empty() {
+        let mut summary = format!(" {}", plural(pairs.len(), "file changed", "files changed"));
+        if insertions > 0 || deletions == 0 {
+            summary.push_str(&format!(", {}", plural(insertions, "insertion(+)", "insertions(+)")));
+        }
+        if deletions > 0 || insertions == 0 {
+            summary.push_str(&format!(", {}", plural(deletions, "deletion(-)", "deletions(-)")));
+        }
+        output.push_str(&summary);
+        output.push('\n');
+    }
+    output
+}
+
+fn status_letter(pair: &FilePair) -> char {
+    match (&pair.old, &pair.new) {
+        (None, _) => 'A',
+        (_, None) => 'D',
+        _ => 'M'
+    }
+}
+
+pub fn diff(repository: &Repository, options: DiffOptions) -> Result<(), Error> {
+    let mut revisions = Vec::new();
+    for revision in &options.revisions {
+        /* "A...B" compares B with the merge base of A and B */
+        if let Some((from, to)) = revision.split_once("...") {
+            let commit = |name: &str| {
+                let name = if name.is_empty() { "HEAD" } else { name };
+                peel_to_commit(repository, repository.resolve_revision(name)?)
+            };
+            let base = merge_base(repository, &commit(from)?, &commit(to)?)?
+                .ok_or_else(|| Error::InvalidArgument(format!("{}: no merge base", revision)))?;
+            revisions.push(base.to_string());
+            revisions.push(if to.is_empty() { "HEAD" } else { to }.to_string());
+            continue
+        }
+        match revision.split_once("..") {
+            Some((from, to)) => {
+                revisions.push(if from.is_empty() { "HEAD" } else { from }.to_string());
+                revisions.push(if to.is_empty() { "HEAD" } else { to }.to_string());
+            },
+            None => revisions.push(revision.clone())
+        }
+    }
+
+    let index = repository.read_index()?;
+    let (old, new) = match (options.cached, revisions.as_slice()) {
+        (false, []) => (Side::index(&index), Side::worktree(repository, &index)?),
+        (true, []) => (Side::head(repository)?, Side::index(&index)),
+        (false, [revision]) => (Side::tree(repository, revision)?, Side::worktree(repository, &index)?),
+        (true, [revision]) => (Side::tree(repository, revision)?, Side::index(&index)),
+        (false, [from, to]) => (Side::tree(repository, from)?, Side::tree(repository, to)?),
+        _ => return Err(Error::InvalidArgument("Too many revisions to compare".into()))
+    };
+
+    let paths = repository.relative_paths(&options.paths)?;
+    let pairs = compare(repository, &old, &new, &paths)?;
+    let mut stdout = io::stdout();
+    match options.output {
+        DiffOutput::Patch => {
+            for pair in &pairs {
+                io::Write::write_all(&mut stdout, &format_patch(pair, options.context))?;
+            }
+        },
+        DiffOutput::Stat => print!("{}", format_stat(&pairs)),
+        DiffOutput::Numstat => print!("{}", format_numstat(&pairs)),
+        DiffOutput::NameOnly => pairs.iter().for_each(|pair| println!("{}", pair.path)),
+        DiffOutput::NameStatus => {
+            pairs.iter().for_each(|pair| println!("{}\t{}", status_letter(pair), pair.path))
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test_util::*;
+
+    use std::fs;
+
+    fn pair(path: &str, old: &str, new: &str) -> FilePair {
+        FilePair {
+            path: path.to_string(),
+            old: Some((FileMode::Regular, hash_object(ObjectType::Blob, old.as_bytes()))),
+            new: Some((FileMode::Regular, hash_object(ObjectType::Blob, new.as_bytes()))),
+            old_content: old.as_bytes().to_vec(),
+            new_content: new.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn myers_finds_shortest_edit_script() {
+        let a: Vec<char> = "ABCABBA".chars().collect();
+        let b: Vec<char> = "CBABAC".chars().collect();
+
+        let edits = diff_lines(&a, &b);
+
+        let changes = edits.iter().filter(|edit| !matches!(edit, Edit::Equal(..))).count();
+        assert_eq!(changes, 5);
+    }
+
+    #[test]
+    fn unified_diff_with_context() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+        let new = "1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\neleven\n";
+
+        let diff = String::from_utf8(unified_diff(old.as_bytes(), new.as_bytes(), 3)).unwrap();
+
+        let expected = "@@ -1,6 +1,6 @@\n 1\n 2\n-3\n+three\n 4\n 5\n 6\n\
+            @@ -8,3 +8,4 @@\n 8\n 9\n 10\n+eleven\n";
+        assert_eq!(diff, expected);
+    }
+
+    #[test]
+    fn unified_diff_without_newline_at_end() {
+        let diff = String::from_utf8(unified_diff(b"", b"new", 3)).unwrap();
+
+        assert_eq!(diff, "@@ -0,0 +1 @@\n+new\n\\ No newline at end of file\n");
+    }
+
+    #[test]
+    fn binary_files_differ() {
+        let mut binary = pair("image.png", "", "");
+        binary.new_content = vec![0x89, b'P', b'N', b'G', 0, 1];
+        binary.new = Some((FileMode::Regular, hash_object(ObjectType::Blob, &binary.new_content)));
+
+        let patch = String::from_utf8(format_patch(&binary, 3)).unwrap();
+
+        assert!(patch.ends_with("Binary files a/image.png and b/image.png differ\n"));
+        assert_eq!(format_numstat(&[binary]), "-\t-\timage.png\n");
+    }
+
+    #[test]
+    fn format_diffstat() {
+        let pairs = [
+            pair("src/main.rs", "a\nb\nc\n", "a\nB\nc\nd\n"),
+            pair("README.md", "x\n", "")
+        ];
+
+        let stat = format_stat(&pairs);
+
+        let expected = " src/main.rs | 3 ++-\n README.md   | 1 -\n 2 files changed, 2 insertions(+), 2 deletions(-)\n";
+        assert_eq!(stat, expected);
+    }
+
+    #[test]
+    fn intent_to_add_is_new_in_the_worktree_only() {
+        let repository = TempRepo::new("diff-intent");
+        let dir = repository.path();
+        fs::write(dir.join("n"), "new\n").unwrap();
+        let empty = hash_object(ObjectType::Blob, &[]);
+        let mut entry = IndexEntry::create(empty, FileMode::Regular, "n", &dir.join("n")).unwrap();
+        entry.set_intent_to_add(true);
+        let index = Index::new(vec![entry]);
+        repository.write_index(&index).unwrap();
+
+        let staged = compare(&repository, &Side::head(&repository).unwrap(), &Side::index(&index), &[]).unwrap();
+        assert!(staged.is_empty());
+
+        let worktree = Side::worktree(&repository, &index).unwrap();
+        let unstaged = compare(&repository, &Side::index(&index), &worktree, &[]).unwrap();
+        assert_eq!(unstaged.len(), 1);
+        assert_eq!(unstaged[0].old, None);
+        assert_eq!(unstaged[0].new, Some((FileMode::Regular, hash_object(ObjectType::Blob, b"new\n"))));
+        let patch = String::from_utf8(format_patch(&unstaged[0], 3)).unwrap();
+        assert!(patch.contains("new file mode 100644\nindex 0000000..3e75765\n"));
+    }
+}
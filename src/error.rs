@@ -0,0 +1,104 @@
+use crate::hash::*;
+
+use std::fmt;
+use std::io;
+
+use std::path::PathBuf;
+
+/* Every error nit reports, each with its own process exit code */
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /* An object that cannot be parsed, or is not of the expected type */
+    CorruptObject(String),
+    MissingObject(Hash),
+    /* An invalid ref name, or a revision that does not resolve */
+    BadRef(String),
+    InvalidIndex(String),
+    InvalidConfig(String),
+    NotARepository(PathBuf),
+    /* Local changes or existing files that an operation would overwrite */
+    Conflict(String),
+    /* Arguments that are well-formed but cannot be applied */
+    InvalidArgument(String),
+}
+
+impl Error {
+    /*
+     * Like git, 128 is used for a missing repository and 129 for bad
+     * usage. 1 is left for commands that report "not found" by exit code.
+     */
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io(_) => 2,
+            Error::CorruptObject(_) => 3,
+            Error::MissingObject(_) => 4,
+            Error::BadRef(_) => 5,
+            Error::InvalidIndex(_) => 6,
+            Error::InvalidConfig(_) => 7,
+            Error::Conflict(_) => 8,
+            Error::NotARepository(_) => 128,
+            Error::InvalidArgument(_) => 129,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::CorruptObject(message) => write!(f, "{}", message),
+            Error::MissingObject(hash) => write!(f, "Object {} not found", hash),
+            Error::BadRef(message) => write!(f, "{}", message),
+            Error::InvalidIndex(message) => write!(f, "Invalid index: {}", message),
+            Error::InvalidConfig(message) => write!(f, "{}", message),
+            Error::NotARepository(path) => {
+                write!(f, "not a git repository (or any of the parent directories): {}", path.display())
+            },
+            Error::Conflict(message) => write!(f, "{}", message),
+            Error::InvalidArgument(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exit_codes_are_distinct() {
+        let errors = [
+            Error::Io(io::Error::other("disk full")),
+            Error::CorruptObject(String::new()),
+            Error::MissingObject(Hash([0; 20])),
+            Error::BadRef(String::new()),
+            Error::InvalidIndex(String::new()),
+            Error::InvalidConfig(String::new()),
+            Error::NotARepository(PathBuf::from(".git")),
+            Error::Conflict(String::new()),
+            Error::InvalidArgument(String::new()),
+        ];
+
+        let mut codes: Vec<i32> = errors.iter().map(Error::exit_code).collect();
+        codes.sort();
+        codes.dedup();
+
+        assert_eq!(codes.len(), errors.len());
+        assert!(!codes.contains(&0) && !codes.contains(&1));
+    }
+}
@@ -0,0 +1,258 @@
+use crate::error::*;
+use crate::hash::*;
+use crate::commit::*;
+use crate::object::*;
+use crate::repository::*;
+use crate::tag::*;
+use crate::tree::*;
+
+use std::os::unix::ffi::OsStrExt;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/* The objects another object points to, with the type it expects them to have */
+type Links = Vec<(Hash, ObjectType)>;
+
+/* A timezone must be written as "+hhmm" or "-hhmm" */
+fn check_stamp(stamp: &Stamp) -> Result<(), Error> {
+    let timezone = stamp.timezone.as_bytes();
+    let valid = timezone.len() == 5
+        && matches!(timezone[0], b'+' | b'-')
+        && timezone[1..].iter().all(u8::is_ascii_digit);
+    if !valid {
+        return Err(corrupt(format!("invalid timezone '{}' for {}", stamp.timezone, String::from_utf8_lossy(&stamp.email))))
+    }
+    if stamp.email.iter().any(|byte| matches!(byte, b'<' | b'>' | b'\n')) {
+        return Err(corrupt(format!("invalid email '{}'", String::from_utf8_lossy(&stamp.email))))
+    }
+    Ok(())
+}
+
+/*
+ * Tree entries need a name that is neither empty, "." nor "..", has no '/'
+ * and is unique, their modes being checked when the tree is parsed. Like
+ * git they must be sorted with trees compared as if their name ended in '/'.
+ */
+fn check_tree(tree: &Tree) -> Result<(), Error> {
+    for entry in &tree.entries {
+        let name = entry.name.as_os_str().as_bytes();
+        if matches!(name, b"" | b"." | b"..") || name.contains(&b'/') {
+            return Err(corrupt(format!("invalid entry name '{}'", entry.name.display())))
+        }
+    }
+
+    for pair in tree.entries.windows(2) {
+        if pair[0].name == pair[1].name {
+            return Err(corrupt(format!("duplicate entry '{}'", pair[1].name.display())))
+        }
+        if pair[0].sort_key() > pair[1].sort_key() {
+            return Err(corrupt(format!("'{}' is not properly sorted", pair[1].name.display())))
+        }
+    }
+    Ok(())
+}
+
+/* Validates the syntax of an object, returning the objects it points to */
+pub fn check_object(object: &Object) -> Result<Links, Error> {
+    match object.kind {
+        ObjectType::Commit => {
+            let commit = Commit::parse(&object.content)?;
+            check_stamp(&commit.author)?;
+            check_stamp(&commit.committer)?;
+            let mut links = vec![(commit.tree, ObjectType::Tree)];
+            links.extend(commit.parents.into_iter().map(|parent| (parent, ObjectType::Commit)));
+            Ok(links)
+        },
+        ObjectType::Tree => {
+            let tree = Tree::parse(&object.content)?;
+            check_tree(&tree)?;
+            /* A submodule's commit lives in another repository */
+            Ok(tree.entries
+                .into_iter()
+                .filter(|entry| entry.mode != FileMode::Gitlink)
+                .map(|entry| {
+                    let kind = entry.kind();
+                    (entry.key, kind)
+                })
+                .collect())
+        },
+        ObjectType::Tag => {
+            let tag = Tag::parse(&object.content)?;
+            if let Some(tagger) = &tag.tagger {
+                check_stamp(tagger)?;
+            }
+            Ok(vec![(tag.object, tag.kind)])
+        },
+        ObjectType::Blob => Ok(Vec::new())
+    }
+}
+
+/*
+ * Checks the integrity of the repository like `git fsck`: every loose and
+ * packed object must inflate, hash to its name and be well-formed, and
+ * every object reachable from the refs, HEAD and the index must exist.
+ * Objects that are neither reachable nor pointed to by another object are
+ * reported as dangling. Returns whether no error was found.
+ */
+pub fn fsck(repository: &Repository) -> Result<bool, Error> {
+    let mut errors = 0;
+    let mut stored: BTreeMap<Hash, ObjectType> = BTreeMap::new();
+    let mut links: HashMap<Hash, Links> = HashMap::new();
+
+    /* Records a stored object and reports whether it is valid */
+    let mut check = |hash: &Hash, object: Result<Object, Error>| {
+        let object = match object {
+            Ok(object) => object,
+            Err(err) => {
+                println!("error: {}: {}", hash, err);
+                return false
+            }
+        };
+        stored.insert(hash.clone(), object.kind);
+        if hash_object(object.kind, &object.content) != *hash {
+            println!("error: {}: hash mismatch", hash);
+            return false
+        }
+        match check_object(&object) {
+            Ok(object_links) => {
+                links.insert(hash.clone(), object_links);
+                true
+            },
+            Err(err) => {
+                println!("error in {} {}: {}", object.kind, hash, err);
+                false
+            }
+        }
+    };
+
+    for hash in repository.loose_objects()? {
+        if !check(&hash, repository.read_object(&hash)) {
+            errors += 1;
+        }
+    }
+    for pack in repository.packs()?.iter() {
+        if let Err(err) = pack.verify() {
+            println!("error: {}", err);
+            errors += 1;
+        }
+        for hash in &pack.index.hashes {
+            let object = pack.read_object(hash).and_then(|object| object.ok_or(Error::MissingObject(hash.clone())));
+            if !check(hash, object) {
+                errors += 1;
+            }
+        }
+    }
+
+    /* Walk from the refs, HEAD and the index */
+    let mut stack: Vec<(Hash, Option<ObjectType>)> = Vec::new();
+    for (_, hash) in repository.list_refs("refs")? {
+        stack.push((hash, None));
+    }
+    stack.extend(repository.read_ref("HEAD")?.map(|hash| (hash, Some(ObjectType::Commit))));
+    if !repository.is_bare() {
+        for entry in repository.read_index()?.entries {
+            /* An intent-to-add entry has no blob yet */
+            if entry.mode() != FileMode::Gitlink && !entry.intent_to_add() {
+                stack.push((entry.key, Some(ObjectType::Blob)));
+            }
+        }
+    }
+
+    let mut reachable = HashSet::new();
+    while let Some((hash, kind)) = stack.pop() {
+        if !reachable.insert(hash.clone()) {
+            continue
+        }
+        match stored.get(&hash) {
+            None => {
+                let kind = kind.map_or(String::from("object"), |kind| kind.to_string());
+                println!("missing {} {}", kind, hash);
+                errors += 1;
+            },
+            Some(&stored_kind) => {
+                if let Some(expected) = kind && expected != stored_kind {
+                    println!("error: {} is a {}, not a {}", hash, stored_kind, expected);
+                    errors += 1;
+                    continue
+                }
+                let object_links = links.get(&hash).into_iter().flatten();
+                stack.extend(object_links.map(|(hash, kind)| (hash.clone(), Some(*kind))));
+            }
+        }
+    }
+
+    let referenced: HashSet<&Hash> = links.values().flatten().map(|(hash, _)| hash).collect();
+    for (hash, kind) in &stored {
+        if !reachable.contains(hash) && !referenced.contains(hash) {
+            println!("dangling {} {}", kind, hash);
+        }
+    }
+    Ok(errors == 0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::index::*;
+    use crate::test_util::*;
+
+    use std::fs;
+
+    fn tree_object(entries: &[(FileMode, &str)]) -> Object {
+        let key = Hash([1; 20]);
+        let entries = entries.iter().map(|&(mode, name)| TreeEntry::new(key.clone(), mode, name.into())).collect();
+        Object { kind: ObjectType::Tree, content: Tree::new(entries).to_bytes() }
+    }
+
+    #[test]
+    fn tree_entries_must_be_sorted_with_valid_modes() {
+        let (regular, tree) = (FileMode::Regular, FileMode::Tree);
+        assert!(check_object(&tree_object(&[(regular, "a.txt"), (tree, "a"), (FileMode::Symlink, "b")])).is_ok());
+        assert!(check_object(&tree_object(&[(regular, "b"), (regular, "a")])).is_err());
+        assert!(check_object(&tree_object(&[(tree, "a"), (regular, "a.txt")])).is_err());
+        assert!(check_object(&tree_object(&[(regular, "a"), (regular, "a")])).is_err());
+        assert!(check_object(&tree_object(&[(regular, "..")])).is_err());
+
+        let mut old_mode = b"100664 a\0".to_vec();
+        old_mode.extend_from_slice(&[1; 20]);
+        assert!(check_object(&Object { kind: ObjectType::Tree, content: old_mode }).is_err());
+    }
+
+    #[test]
+    fn commit_stamps_need_a_valid_timezone() {
+        let commit = |timezone: &str| Object {
+            kind: ObjectType::Commit,
+            content: format!(
+                "tree {}\nauthor A <a@b> 0 +0000\ncommitter A <a@b> 0 {}\n\nmessage\n",
+                Hash([1; 20]),
+                timezone
+            ).into_bytes()
+        };
+
+        assert_eq!(check_object(&commit("+0100")).unwrap().len(), 1);
+        assert!(check_object(&commit("0100")).is_err());
+        assert!(check_object(&commit("+01:00")).is_err());
+    }
+
+    #[test]
+    fn fsck_reports_missing_and_dangling_objects() {
+        let repository = TempRepo::new("fsck");
+
+        let blob = repository.write_object(ObjectType::Blob, b"content".to_vec()).unwrap();
+        let tree = Tree::new(vec![TreeEntry::new(blob.clone(), FileMode::Regular, "file".into())]);
+        let tree = repository.write_object(ObjectType::Tree, tree.to_bytes()).unwrap();
+        repository.write_object(ObjectType::Blob, b"dangling".to_vec()).unwrap();
+        assert!(fsck(&repository).unwrap());
+
+        fs::write(repository.path().join("new.txt"), "not added yet").unwrap();
+        let empty = hash_object(ObjectType::Blob, &[]);
+        let mut entry = IndexEntry::create(empty, FileMode::Regular, "new.txt", &repository.path().join("new.txt")).unwrap();
+        entry.set_intent_to_add(true);
+        repository.write_index(&Index::new(vec![entry])).unwrap();
+        assert!(fsck(&repository).unwrap());
+
+        repository.write_ref("refs/tags/tree", &tree).unwrap();
+        repository.remove_loose_object(&blob).unwrap();
+        assert!(!fsck(&repository).unwrap());
+    }
+}
@@ -0,0 +1,206 @@
+use crate::error::*;
+use crate::hash::*;
+use crate::command::*;
+use crate::commit::*;
+use crate::object::*;
+use crate::pack::*;
+use crate::repository::*;
+use crate::tag::*;
+use crate::tree::*;
+
+use std::fs;
+
+use std::collections::HashSet;
+
+/*
+ * Every object reachable from the references, HEAD and the index, with the
+ * hash of the path it was found at to choose delta bases by. Contents are
+ * only read to walk the objects and are not kept.
+ */
+pub fn reachable_objects(repository: &Repository) -> Result<Vec<PackObject>, Error> {
+    let mut stack: Vec<(Hash, String)> = Vec::new();
+    for (_, hash) in repository.list_refs("refs")? {
+        stack.push((hash, String::new()));
+    }
+    stack.extend(repository.read_ref("HEAD")?.map(|hash| (hash, String::new())));
+    for entry in repository.read_index()?.entries {
+        /*
+         * A submodule's commit is not part of this repository, and an
+         * intent-to-add entry has no blob yet
+         */
+        if entry.mode() != FileMode::Gitlink && !entry.intent_to_add() {
+            stack.push((entry.key.clone(), entry.name.clone()));
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut objects = Vec::new();
+    while let Some((hash, name)) = stack.pop() {
+        if !seen.insert(hash.clone()) {
+            continue
+        }
+
+        let object = repository.read_object(&hash)?;
+        match object.kind {
+            ObjectType::Commit => {
+                let commit = Commit::parse(&object.content)?;
+                stack.push((commit.tree, String::new()));
+                stack.extend(commit.parents.into_iter().map(|parent| (parent, String::new())));
+            },
+            ObjectType::Tree => {
+                for entry in Tree::parse(&object.content)?.entries {
+                    if entry.kind() == ObjectType::Commit {
+                        continue
+                    }
+                    let path = match name.as_str() {
+                        "" => entry.name.to_string_lossy().to_string(),
+                        name => format!("{}/{}", name, entry.name.to_string_lossy())
+                    };
+                    stack.push((entry.key, path));
+                }
+            },
+            ObjectType::Tag => stack.push((Tag::parse(&object.content)?.object, String::new())),
+            ObjectType::Blob => {}
+        }
+
+        objects.push(PackObject { hash, kind: object.kind, size: object.content.len(), name_hash: name_hash(&name) });
+    }
+    Ok(objects)
+}
+
+/* Removes the loose objects that are also in a pack, like `git prune-packed` */
+pub fn prune_packed(repository: &Repository) -> Result<usize, Error> {
+    let packs = repository.packs()?;
+    let mut removed = 0;
+    for hash in repository.loose_objects()? {
+        if packs.iter().any(|pack| pack.contains(&hash)) {
+            repository.remove_loose_object(&hash)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/*
+ * Packs the reachable objects that are not packed yet, or with `all` every
+ * reachable object into a single new pack. With `delete` the packs this
+ * makes redundant are removed, after turning their unreachable objects
+ * back into loose objects if asked to, and so are packed loose objects.
+ */
+pub fn repack(repository: &Repository, options: &RepackOptions) -> Result<(), Error> {
+    let old_packs = repository.packs()?;
+    let mut objects = reachable_objects(repository)?;
+    let reachable: HashSet<Hash> = objects.iter().map(|object| object.hash.clone()).collect();
+    if !options.all {
+        objects.retain(|object| !old_packs.iter().any(|pack| pack.contains(&object.hash)));
+    }
+
+    let mut new_pack = None;
+    if objects.is_empty() {
+        println!("Nothing new to pack.");
+    } else {
+        let (checksum, deltas) = write_pack(
+            &repository.git_path("objects/pack"),
+            &objects,
+            |hash| Ok(repository.read_object(hash)?.content),
+            options.window,
+            options.depth
+        )?;
+        println!("Total {} (delta {})", objects.len(), deltas);
+        println!("pack-{}", checksum);
+        new_pack = Some(checksum);
+    }
+    repository.reload_packs();
+
+    if !options.delete {
+        return Ok(())
+    }
+    if options.all {
+        for pack in old_packs.iter().filter(|pack| Some(&pack.index.pack_checksum) != new_pack.as_ref()) {
+            if options.unpack_unreachable {
+                for hash in pack.index.hashes.iter().filter(|hash| !reachable.contains(hash)) {
+                    if !repository.object_path(hash).exists() {
+                        let object = pack.read_object(hash)?.ok_or_else(|| Error::MissingObject(hash.clone()))?;
+                        repository.write_loose_object(hash, object.kind, object.content)?;
+                    }
+                }
+            }
+            fs::remove_file(pack.path.with_extension("idx"))?;
+            fs::remove_file(&pack.path)?;
+        }
+        repository.reload_packs();
+    }
+    prune_packed(repository)?;
+    Ok(())
+}
+
+/*
+ * Packs the references and all reachable objects into a single pack and
+ * removes the loose copies. Unreachable objects are kept as loose objects.
+ */
+pub fn gc(repository: &Repository) -> Result<(), Error> {
+    repository.pack_refs()?;
+    repack(repository, &RepackOptions {
+        all: true,
+        unpack_unreachable: true,
+        delete: true,
+        window: DEFAULT_WINDOW,
+        depth: DEFAULT_DEPTH,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test_util::*;
+
+    use crate::index::*;
+
+    #[test]
+    fn gc_packs_objects_and_refs() {
+        let repository = TempRepo::new("gc");
+        let dir = repository.path();
+
+        /* Two versions of a file, the second one a good delta candidate */
+        let lines: String = (0..100).map(|line| format!("line {} of a file worth packing\n", line)).collect();
+        let old = repository.write_object(ObjectType::Blob, lines.clone().into_bytes()).unwrap();
+        let new = repository.write_object(ObjectType::Blob, format!("{}one more\n", lines).into_bytes()).unwrap();
+        let unreachable = repository.write_object(ObjectType::Blob, b"dangling".to_vec()).unwrap();
+        let entry = |hash: &Hash, name: &str| {
+            fs::write(dir.join(name), "").unwrap();
+            IndexEntry::create(hash.clone(), FileMode::Regular, name, &dir.join(name)).unwrap()
+        };
+        repository.write_index(&Index::new(vec![entry(&old, "old.txt"), entry(&new, "new.txt")])).unwrap();
+        let tree = TreeCache::from_index(repository.read_index().unwrap()).write(&repository).unwrap();
+        repository.write_ref("refs/tags/tree", &tree).unwrap();
+
+        gc(&repository).unwrap();
+
+        let packs = repository.packs().unwrap();
+        assert_eq!(packs.len(), 1);
+        assert_eq!(packs[0].index.len(), 3);
+        assert_eq!(repository.loose_objects().unwrap(), vec![unreachable]);
+        assert_eq!(repository.read_object(&old).unwrap().content, lines.as_bytes());
+        assert!(!repository.git_path("refs/tags/tree").exists());
+        assert_eq!(repository.read_ref("refs/tags/tree").unwrap(), Some(tree));
+    }
+
+    #[test]
+    fn gc_skips_intent_to_add_entries() {
+        let repository = TempRepo::new("gc-intent");
+        let dir = repository.path();
+
+        /* Like `git add -N`, the entry names the empty blob without writing it */
+        fs::write(dir.join("new.txt"), "not added yet").unwrap();
+        let empty = hash_object(ObjectType::Blob, &[]);
+        let mut entry = IndexEntry::create(empty.clone(), FileMode::Regular, "new.txt", &dir.join("new.txt")).unwrap();
+        entry.set_intent_to_add(true);
+        repository.write_index(&Index::new(vec![entry])).unwrap();
+
+        gc(&repository).unwrap();
+
+        assert!(!repository.has_object(&empty).unwrap());
+        assert!(repository.read_index().unwrap().entries[0].intent_to_add());
+    }
+}
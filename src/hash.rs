@@ -1,25 +1,23 @@
+use crate::error::*;
+
 use sha1::{Sha1, Digest};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Hash(pub [u8; 20]);
 
 impl Hash {
-    pub fn from_hex(hash: &str) -> Self {
+    /* Parses a full, 40 character hex encoded object name */
+    pub fn from_hex(hash: &str) -> Result<Self, Error> {
+        let invalid = || Error::CorruptObject(format!("Invalid object name '{}'", hash));
         if hash.len() != 40 {
-            panic!("[ERROR] Hex encoded hash must be 40 characters, received '{}': {}",
-                hash.len(),
-                hash
-            )
+            return Err(invalid())
         }
-        let bytes = match hex::decode(hash) {
-            Ok(b) => b,
-            Err(err) => panic!("[ERROR] Invalid hash '{}': {}", hash, err)
-        };
+        let bytes = hex::decode(hash).map_err(|_| invalid())?;
 
-        Hash(bytes.try_into().unwrap())
+        Ok(Hash(bytes.try_into().map_err(|_| invalid())?))
     }
 
-    pub fn from_bytes(header: String, content: Vec<u8>) -> Self {
+    pub fn from_bytes(header: &[u8], content: &[u8]) -> Self {
         let mut hasher = Sha1::new();
         hasher.update(header);
         hasher.update(content);
@@ -47,7 +45,7 @@ mod test {
     fn sha1_hash() {
         let input = String::from("The quick brown fox jumps over the lazy dog");
 
-        let hashed = Hash::from_bytes(String::from(""), input.into_bytes()).to_string();
+        let hashed = Hash::from_bytes(b"", input.as_bytes()).to_string();
 
         let expected = String::from("2fd4e1c67a2d28fced849ee1bb76e7391b93eb12");
         assert_eq!(hashed, expected);
@@ -57,9 +55,15 @@ mod test {
     fn hash_from_hex() {
         let input = "2fd4e1c67a2d28fced849ee1bb76e7391b93eb12";
 
-        let hash = Hash::from_hex(input).to_string();
+        let hash = Hash::from_hex(input).unwrap().to_string();
 
         let expected = String::from("2fd4e1c67a2d28fced849ee1bb76e7391b93eb12");
         assert_eq!(hash, expected);
     }
+
+    #[test]
+    fn reject_invalid_hex() {
+        assert!(Hash::from_hex("2fd4e1c6").is_err());
+        assert!(Hash::from_hex("zfd4e1c67a2d28fced849ee1bb76e7391b93eb12").is_err());
+    }
 }
@@ -0,0 +1,167 @@
+use crate::error::*;
+use crate::commit::*;
+use crate::config::*;
+use crate::date::*;
+use crate::repository::*;
+
+use std::env;
+
+use chrono::DateTime;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    Author,
+    Committer
+}
+
+impl Role {
+    fn env_prefix(&self) -> &'static str {
+        match self {
+            Role::Author => "GIT_AUTHOR",
+            Role::Committer => "GIT_COMMITTER",
+        }
+    }
+
+    fn config_section(&self) -> &'static str {
+        match self {
+            Role::Author => "author",
+            Role::Committer => "committer",
+        }
+    }
+}
+
+/* Splits "Name <email>" as given to --author */
+pub fn parse_name_email(ident: &str) -> Option<(String, String)> {
+    let (name, rest) = ident.split_once('<')?;
+    let email = rest.strip_suffix('>')?;
+    Some((name.trim().to_string(), email.trim().to_string()))
+}
+
+/*
+ * Parses a date for a commit: git's internal format "<timestamp> <offset>"
+ * (optionally with a leading '@'), RFC 2822 or RFC 3339 dates keep their
+ * offset, everything else accepted by `parse_date` uses the local timezone.
+ */
+pub fn parse_ident_date(date: &str, now: i64) -> Option<(i64, String)> {
+    let date = date.trim();
+    if let Some((timestamp, timezone)) = date.split_once(' ') {
+        let timestamp = timestamp.strip_prefix('@').unwrap_or(timestamp);
+        if let (Ok(timestamp), Some(_)) = (timestamp.parse::<i64>(), parse_offset(timezone)) {
+            return Some((timestamp, timezone.to_string()))
+        }
+    }
+    let parsed = DateTime::parse_from_rfc2822(date).or_else(|_| DateTime::parse_from_rfc3339(date));
+    if let Ok(parsed) = parsed {
+        return Some((parsed.timestamp(), format_offset(parsed.offset().local_minus_utc())))
+    }
+
+    let timestamp = parse_date(date, now)?;
+    Some((timestamp, local_offset(timestamp)))
+}
+
+/*
+ * Resolves the identity for a role the way git does: the environment
+ * (GIT_AUTHOR_NAME, GIT_AUTHOR_EMAIL, GIT_AUTHOR_DATE and the GIT_COMMITTER_*
+ * counterparts) wins over `author.*`/`committer.*` config, which wins over
+ * `user.name` and `user.email`. The date defaults to `now`.
+ */
+pub fn resolve_ident(
+    role: Role,
+    config: &Config,
+    env: impl Fn(&str) -> Option<String>,
+    now: i64,
+) -> Result<Stamp, Error> {
+    let lookup = |field: &str| env(&format!("{}_{}", role.env_prefix(), field.to_uppercase()))
+        .or_else(|| config.get(&format!("{}.{}", role.config_section(), field)).map(str::to_string))
+        .or_else(|| config.get(&format!("user.{}", field)).map(str::to_string))
+        .filter(|value| !value.trim().is_empty());
+
+    let (Some(name), Some(email)) = (lookup("name"), lookup("email")) else {
+        return Err(Error::InvalidConfig(format!(
+            "{} identity unknown, please set user.name and user.email in ~/.gitconfig \
+            or in the repository config",
+            match role { Role::Author => "Author", Role::Committer => "Committer" }
+        )))
+    };
+
+    let (timestamp, timezone) = match env(&format!("{}_DATE", role.env_prefix())) {
+        Some(date) => parse_ident_date(&date, now)
+            .ok_or_else(|| Error::InvalidArgument(format!("Invalid date format: {}", date)))?,
+        None => (now, local_offset(now))
+    };
+
+    Ok(Stamp { name: name.into_bytes(), email: email.into_bytes(), timestamp, timezone })
+}
+
+/* The identity of `role` from the process environment and the loaded config */
+pub fn ident(role: Role, config: &Config, now: i64) -> Result<Stamp, Error> {
+    resolve_ident(role, config, |name| env::var(name).ok(), now)
+}
+
+/* The configured author, with `--author` and `--date` taking precedence */
+pub fn author(repository: &Repository, author: Option<&str>, date: Option<&str>) -> Result<Stamp, Error> {
+    let (name, email) = match author {
+        Some(author) => parse_name_email(author)
+            .map(|(name, email)| (Some(name), Some(email)))
+            .ok_or_else(|| Error::InvalidArgument(format!("--author '{}' is not 'Name <email>'", author)))?,
+        None => (None, None)
+    };
+
+    let environment = |variable: &str| match variable {
+        "GIT_AUTHOR_NAME" => name.clone(),
+        "GIT_AUTHOR_EMAIL" => email.clone(),
+        "GIT_AUTHOR_DATE" => date.map(str::to_string),
+        _ => None
+    }.or_else(|| env::var(variable).ok());
+
+    resolve_ident(Role::Author, &repository.config()?, environment, now())
+}
+
+pub fn committer(repository: &Repository) -> Result<Stamp, Error> {
+    ident(Role::Committer, &repository.config()?, now())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn environment(name: &str) -> Option<String> {
+        match name {
+            "GIT_AUTHOR_NAME" => Some(String::from("Env Author")),
+            "GIT_AUTHOR_DATE" => Some(String::from("1762103153 +0100")),
+            _ => None
+        }
+    }
+
+    #[test]
+    fn environment_overrides_config() {
+        let config = Config::parse("[user]\n\tname = Config User\n\temail = user@example.com\n").unwrap();
+
+        let author = resolve_ident(Role::Author, &config, environment, 0).unwrap();
+        let committer = resolve_ident(Role::Committer, &config, environment, 1764365370).unwrap();
+
+        assert_eq!(author.to_string(), "Env Author <user@example.com> 1762103153 +0100");
+        assert_eq!(committer.name, b"Config User");
+        assert_eq!(committer.timestamp, 1764365370);
+    }
+
+    #[test]
+    fn missing_identity_is_an_error() {
+        let config = Config::default();
+
+        assert!(resolve_ident(Role::Committer, &config, |_| None, 0).is_err());
+    }
+
+    #[test]
+    fn parse_author_and_dates() {
+        assert_eq!(
+            parse_name_email("Ada Lovelace <ada@example.com>"),
+            Some((String::from("Ada Lovelace"), String::from("ada@example.com")))
+        );
+        assert_eq!(parse_ident_date("@1762103153 -0530", 0), Some((1762103153, String::from("-0530"))));
+        assert_eq!(
+            parse_ident_date("Sun, 2 Nov 2025 18:05:53 +0100", 0),
+            Some((1762103153, String::from("+0100")))
+        );
+    }
+}
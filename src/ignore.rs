@@ -0,0 +1,275 @@
+use crate::error::*;
+use crate::config::*;
+use crate::repository::*;
+use crate::util::*;
+
+use std::fs;
+use std::io;
+
+use std::path::{Path, PathBuf};
+use std::collections::{BTreeSet, HashMap};
+
+const GITIGNORE: &str = ".gitignore";
+
+/* One line of a .gitignore or exclude file */
+#[derive(Debug, Clone)]
+pub struct IgnorePattern {
+    /* The line as written, shown by check-ignore -v */
+    pub original: String,
+    pub source: PathBuf,
+    pub line: usize,
+    pattern: String,
+    /* The directory of the .gitignore file, e.g. "src/" or "" */
+    base: String,
+    pub negated: bool,
+    directory_only: bool,
+    /* A pattern with a slash matches the path below `base`, others the name */
+    anchored: bool,
+}
+
+impl IgnorePattern {
+    pub fn parse(line: &str, base: &str, source: &Path, line_number: usize) -> Option<IgnorePattern> {
+        let original = line.trim_end_matches('\r');
+        if original.is_empty() || original.starts_with('#') {
+            return None
+        }
+
+        /* Trailing spaces are ignored unless escaped with a backslash */
+        let mut pattern = original.to_string();
+        while pattern.ends_with(' ') && !pattern.ends_with("\\ ") {
+            pattern.pop();
+        }
+
+        let negated = pattern.starts_with('!');
+        if negated {
+            pattern.remove(0);
+        }
+        let directory_only = pattern.ends_with('/');
+        if directory_only {
+            pattern.pop();
+        }
+        let anchored = pattern.contains('/');
+        if let Some(rest) = pattern.strip_prefix('/') {
+            pattern = rest.to_string();
+        }
+        if pattern.is_empty() {
+            return None
+        }
+
+        Some(IgnorePattern {
+            original: original.to_string(),
+            source: source.to_path_buf(),
+            line: line_number,
+            pattern,
+            base: base.to_string(),
+            negated,
+            directory_only,
+            anchored,
+        })
+    }
+
+    pub fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.directory_only && !is_dir {
+            return false
+        }
+        let Some(relative) = path.strip_prefix(&self.base) else {
+            return false
+        };
+
+        let text = if self.anchored {
+            relative
+        } else {
+            relative.rsplit('/').next().unwrap_or(relative)
+        };
+        wildmatch(self.pattern.as_bytes(), text.as_bytes(), false)
+    }
+}
+
+pub fn parse_patterns(content: &str, base: &str, source: &Path) -> Vec<IgnorePattern> {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| IgnorePattern::parse(line, base, source, index + 1))
+        .collect()
+}
+
+fn read_patterns(path: &Path, base: &str, source: &Path) -> Result<Vec<IgnorePattern>, Error> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(parse_patterns(&content, base, source)),
+        Err(err) if matches!(err.kind(), io::ErrorKind::NotFound | io::ErrorKind::NotADirectory) => Ok(Vec::new()),
+        Err(err) => Err(err.into())
+    }
+}
+
+/*
+ * The ignore rules of the working tree. Like git, patterns are looked up
+ * from the highest to the lowest precedence: the .gitignore files from
+ * the path's own directory up to the root, then .git/info/exclude and
+ * finally the file named by core.excludesFile. Within a file the last
+ * matching pattern wins.
+ */
+pub struct Ignore {
+    /* The root of the working tree the .gitignore files are read from */
+    root: PathBuf,
+    excludes: Vec<IgnorePattern>,
+    /* .gitignore patterns by directory ("" is the root), read on demand */
+    directories: HashMap<String, Vec<IgnorePattern>>,
+}
+
+impl Ignore {
+    pub fn new(root: PathBuf, excludes: Vec<IgnorePattern>) -> Ignore {
+        Ignore { root, excludes, directories: HashMap::new() }
+    }
+
+    pub fn load(repository: &Repository) -> Result<Ignore, Error> {
+        let config = repository.config()?;
+        let excludes_file = match config.get("core.excludesFile") {
+            Some(path) => Some(expand_home(path)),
+            None => xdg_config_path("ignore")
+        };
+
+        let mut excludes = Vec::new();
+        if let Some(path) = excludes_file {
+            excludes.extend(read_patterns(&path, "", &path)?);
+        }
+        let info_exclude = repository.git_path("info/exclude");
+        excludes.extend(read_patterns(&info_exclude, "", &info_exclude)?);
+        Ok(Ignore::new(repository.work_tree()?.to_path_buf(), excludes))
+    }
+
+    fn directory_patterns(&mut self, dir: &str) -> Result<&Vec<IgnorePattern>, Error> {
+        if !self.directories.contains_key(dir) {
+            let source = PathBuf::from(format!("{}{}", dir, GITIGNORE));
+            let patterns = read_patterns(&self.root.join(&source), dir, &source)?;
+            self.directories.insert(dir.to_string(), patterns);
+        }
+        Ok(&self.directories[dir])
+    }
+
+    /* The pattern deciding about the path itself, ignoring its parents */
+    fn match_path(&mut self, path: &str, is_dir: bool) -> Result<Option<IgnorePattern>, Error> {
+        let mut dirs = vec![String::new()];
+        for (index, _) in path.match_indices('/') {
+            dirs.push(path[..index + 1].to_string());
+        }
+
+        for dir in dirs.iter().rev() {
+            let patterns = self.directory_patterns(dir)?;
+            if let Some(pattern) = patterns.iter().rev().find(|pattern| pattern.matches(path, is_dir)) {
+                return Ok(Some(pattern.clone()))
+            }
+        }
+        Ok(self.excludes.iter().rev().find(|pattern| pattern.matches(path, is_dir)).cloned())
+    }
+
+    /*
+     * The pattern that decides whether a path (relative to the root of the
+     * working tree, with '/' separators) is ignored, if any. A file in an
+     * ignored directory stays ignored, as git never looks inside it.
+     */
+    pub fn matching(&mut self, path: &str, is_dir: bool) -> Result<Option<IgnorePattern>, Error> {
+        for (index, _) in path.match_indices('/') {
+            if let Some(pattern) = self.match_path(&path[..index], true)?
+                && !pattern.negated {
+                return Ok(Some(pattern))
+            }
+        }
+        self.match_path(path, is_dir)
+    }
+
+    pub fn is_ignored(&mut self, path: &str, is_dir: bool) -> Result<bool, Error> {
+        Ok(self.matching(path, is_dir)?.is_some_and(|pattern| !pattern.negated))
+    }
+}
+
+/*
+ * Prints the paths (relative to the current directory) that are ignored,
+ * and with `verbose` the deciding pattern as "<source>:<line>:<pattern>\t<path>".
+ * Tracked files are never ignored. Returns whether any path was ignored.
+ */
+pub fn check_ignore(
+    repository: &Repository,
+    paths: &[PathBuf],
+    verbose: bool,
+    non_matching: bool,
+) -> Result<bool, Error> {
+    let mut ignore = Ignore::load(repository)?;
+    let tracked: BTreeSet<String> = repository.read_index()?.entries
+        .into_iter()
+        .map(|entry| entry.name)
+        .collect();
+
+    let mut any_ignored = false;
+    for path in paths {
+        let name = repository.relative_path(path)?;
+        let is_dir = path.is_dir() || path.to_string_lossy().ends_with('/');
+
+        let pattern = match tracked.contains(&name) {
+            true => None,
+            false => ignore.matching(&name, is_dir)?
+        };
+        any_ignored |= pattern.as_ref().is_some_and(|pattern| !pattern.negated);
+
+        match pattern {
+            Some(pattern) if verbose => {
+                println!("{}:{}:{}\t{}", pattern.source.display(), pattern.line, pattern.original, path.display());
+            },
+            Some(pattern) if !pattern.negated => println!("{}", path.display()),
+            None if verbose && non_matching => println!("::\t{}", path.display()),
+            None if non_matching => println!("{}", path.display()),
+            _ => {}
+        }
+    }
+    Ok(any_ignored)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ignore(patterns: &str) -> Ignore {
+        let mut ignore = Ignore::new(PathBuf::new(), Vec::new());
+        ignore.directories.insert(String::new(), parse_patterns(patterns, "", Path::new(GITIGNORE)));
+        ignore
+    }
+
+    #[test]
+    fn match_globs_negation_and_directories() {
+        let mut ignore = ignore("# build output\n*.log\n!keep.log\nbuild/\n/TODO\ndocs/**/*.pdf\n\\#notes\n[Tt]mp?\n");
+
+        assert!(ignore.is_ignored("debug.log", false).unwrap());
+        assert!(ignore.is_ignored("src/nested/trace.log", false).unwrap());
+        assert!(!ignore.is_ignored("keep.log", false).unwrap());
+        assert!(ignore.is_ignored("build", true).unwrap());
+        assert!(!ignore.is_ignored("build", false).unwrap());
+        assert!(ignore.is_ignored("src/build/out.o", false).unwrap());
+        assert!(ignore.is_ignored("TODO", false).unwrap());
+        assert!(!ignore.is_ignored("src/TODO", false).unwrap());
+        assert!(ignore.is_ignored("docs/a/b/manual.pdf", false).unwrap());
+        assert!(ignore.is_ignored("docs/manual.pdf", false).unwrap());
+        assert!(ignore.is_ignored("#notes", false).unwrap());
+        assert!(ignore.is_ignored("tmp1", false).unwrap());
+        assert!(!ignore.is_ignored("temp", false).unwrap());
+    }
+
+    #[test]
+    fn file_in_ignored_directory_cannot_be_included() {
+        let mut ignore = ignore("target/\n!target/keep\n");
+
+        let pattern = ignore.matching("target/keep", false).unwrap().unwrap();
+
+        assert_eq!(pattern.original, "target/");
+        assert_eq!(pattern.line, 1);
+    }
+
+    #[test]
+    fn nested_gitignore_takes_precedence() {
+        let mut ignore = ignore("*.txt\n");
+        ignore.directories.insert(String::from("docs/"), parse_patterns("!*.txt\n/local\n", "docs/", Path::new("docs/.gitignore")));
+
+        assert!(ignore.is_ignored("notes.txt", false).unwrap());
+        assert!(!ignore.is_ignored("docs/notes.txt", false).unwrap());
+        assert!(ignore.is_ignored("docs/local", false).unwrap());
+        assert!(!ignore.is_ignored("local", false).unwrap());
+    }
+}
@@ -1,15 +1,39 @@
+use crate::error::*;
 use crate::util::*;
 use crate::hash::*;
+use crate::index_extension::*;
+use crate::object::*;
+use crate::repository::*;
 
 use std::fs;
+use std::io;
 use std::iter;
 
 use std::os::unix::fs::MetadataExt;
-
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use sha1::{Digest, Sha1};
+
+/* The fixed size part of an entry, i.e. stat data, hash and flags */
+const ENTRY_SIZE: usize = 62;
+/* The longest name length the flags can hold, longer names are NUL terminated */
+const NAME_MASK: u16 = 0x0FFF;
+/* Set in the flags when a second, extended flags field follows (version 3 and up) */
+const EXTENDED: u16 = 0x4000;
+/* The extended flags */
+const SKIP_WORKTREE: u16 = 0x4000;
+const INTENT_TO_ADD: u16 = 0x2000;
+
+/*
+ * The index file: a header, the entries sorted by path and stage, the
+ * extensions and a SHA-1 checksum of everything before it.
+ */
 #[derive(Debug)]
 pub struct Index {
     pub header: IndexHeader,
-    pub entries: Vec<IndexEntry>
+    pub entries: Vec<IndexEntry>,
+    pub extensions: Vec<Extension>
 }
 
 #[derive(Debug)]
@@ -29,71 +53,173 @@ pub struct IndexHeader {
 }
 
 impl Index {
-    pub fn new(entries: Vec<IndexEntry>) -> Self {
+    pub fn new(mut entries: Vec<IndexEntry>) -> Self {
         let header = IndexHeader {
             signature: u32::from_be_bytes([ b'D', b'I', b'R', b'C' ]),
             version: 2_u32,
             num_entries: entries.len() as u32,
         };
+        entries.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
 
-        Index { header, entries }
+        Index { header, entries, extensions: Vec::new() }
     }
 
-    pub fn extend(self, new_entries: Vec<IndexEntry>) -> Self {
-        let mut updated_entries = self.entries
-            .into_iter()
-            .filter(|e1| !new_entries.iter().any(|e2| e1.name == e2.name))
-            .collect::<Vec<IndexEntry>>();
+    /*
+     * Adds or replaces entries, keeping the extensions. The cached trees
+     * of the directories containing them are invalidated, and the
+     * untracked cache is dropped for git to rebuild it.
+     */
+    pub fn extend(mut self, new_entries: Vec<IndexEntry>) -> Self {
+        self.entries.retain(|e1| !new_entries.iter().any(|e2| e1.name == e2.name));
+        new_entries.iter().for_each(|entry| self.invalidate_cache_tree(&entry.name));
+        self.extensions.retain(|extension| !matches!(extension, Extension::Untracked(_)));
+
+        self.entries.extend(new_entries);
+        self.entries.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+        self.header.num_entries = self.entries.len() as u32;
+        self
+    }
 
-        for entry in new_entries {
-            updated_entries.push(entry);
+    /* Invalidates the cached trees of the directories containing `path` */
+    pub fn invalidate_cache_tree(&mut self, path: &str) {
+        for extension in self.extensions.iter_mut() {
+            if let Extension::Tree(tree) = extension {
+                tree.invalidate(path);
+            }
         }
+    }
+
+    /* The cached trees, if the index has them */
+    pub fn cache_tree(&self) -> Option<&CacheTree> {
+        self.extensions.iter().find_map(|extension| match extension {
+            Extension::Tree(tree) => Some(tree),
+            _ => None
+        })
+    }
 
-        Self::new(updated_entries)
+    /* Replaces the cached trees, which like git's are written before other extensions */
+    pub fn set_cache_tree(&mut self, tree: CacheTree) {
+        self.extensions.retain(|extension| !matches!(extension, Extension::Tree(_)));
+        self.extensions.insert(0, Extension::Tree(tree));
     }
 
-    pub fn read(index_file: &str) -> Self {
-        let contents = fs::read(index_file).unwrap();
-        let (hbytes, ebytes) = contents.split_at(12);
+    /* Reads the index, a missing index file being an empty index */
+    pub fn read(index_file: impl AsRef<Path>) -> Result<Self, Error> {
+        let index_file = index_file.as_ref();
+        let contents = match fs::read(index_file) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::new(Vec::new())),
+            Err(err) => return Err(err.into())
+        };
+        Self::parse(&contents)
+            .map_err(|err| Error::InvalidIndex(format!("{}: {}", index_file.display(), err)))
+    }
+
+    /*
+     * Parses an index file, checking its trailing checksum. Like git, an
+     * all-zero checksum (written with index.skipHash) is not checked.
+     */
+    pub fn parse(contents: &[u8]) -> Result<Self, Error> {
+        if contents.len() < 12 + 20 {
+            return Err(Error::InvalidIndex(String::from("file is too short")))
+        }
+        let (contents, checksum) = contents.split_at(contents.len() - 20);
+        if checksum != [0; 20] && Sha1::digest(contents)[..] != *checksum {
+            return Err(Error::InvalidIndex(String::from("bad index file sha1 signature")))
+        }
+        let (hbytes, mut ebytes) = contents.split_at(12);
+
+        let header = Self::read_header(hbytes)?;
+        let entries = Self::read_entries(&mut ebytes, header.num_entries as usize, header.version)?;
+        for pair in entries.windows(2) {
+            if pair[0].sort_key() >= pair[1].sort_key() {
+                return Err(Error::InvalidIndex(format!("unordered stage entries for '{}'", pair[1].name)))
+            }
+        }
 
-        let header = Self::read_header(hbytes);
+        let extensions = read_extensions(ebytes, contents.len() - ebytes.len())?;
+        Ok(Self { header, entries, extensions })
+    }
 
-        let entries = Self::read_entries(ebytes, header.num_entries as usize);
-        Self { header, entries }
+    /*
+     * Writes the index. A split index is refused: its entries are only
+     * those missing from or replacing the shared index's, which nit does
+     * not merge, so writing them back would corrupt it.
+     */
+    pub fn write(&self, index_file: impl AsRef<Path>) -> Result<(), Error> {
+        if let Some(Extension::Link { base, .. }) = self.extensions.iter().find(|extension| matches!(extension, Extension::Link { .. })) {
+            return Err(Error::InvalidIndex(format!(
+                "the index is split from the shared index sharedindex.{}, which nit cannot update; \
+                run 'git update-index --no-split-index' first",
+                base
+            )))
+        }
+        Ok(fs::write(index_file, self.to_bytes())?)
     }
 
-    pub fn read_header(mut bytes: &[u8]) -> IndexHeader {
+    pub fn read_header(mut bytes: &[u8]) -> Result<IndexHeader, Error> {
         let signature = take_u32(&mut bytes);
         let version = take_u32(&mut bytes);
         let num_entries = take_u32(&mut bytes);
 
-        IndexHeader { signature, version, num_entries }
+        if signature.to_be_bytes() != *b"DIRC" {
+            return Err(Error::InvalidIndex(String::from("bad signature")))
+        }
+        if !(2..=4).contains(&version) {
+            return Err(Error::InvalidIndex(format!("unsupported version {}", version)))
+        }
+        Ok(IndexHeader { signature, version, num_entries })
     }
 
-    pub fn read_entries(mut bytes: &[u8], num_entries: usize) -> Vec<IndexEntry> {
-        let mut entries = Vec::with_capacity(num_entries);
+    /*
+     * Reads the entries, leaving `bytes` at the first extension. Version 4
+     * names are prefix compressed against the previous entry's name.
+     */
+    pub fn read_entries(bytes: &mut &[u8], num_entries: usize, version: u32) -> Result<Vec<IndexEntry>, Error> {
+        let mut entries: Vec<IndexEntry> = Vec::with_capacity(num_entries.min(bytes.len() / ENTRY_SIZE));
 
         for _ in 0..num_entries {
-            let entry = IndexEntry::read(&mut bytes);
+            let previous = entries.last().map_or("", |entry| entry.name.as_str());
+            let entry = IndexEntry::read(bytes, version, previous)?;
 
             // Pad 1-8 nul bytes as necessary to pad the entry 
             // to a multiple of eight bytes 
-            let padding_len = 8 - ((6 + entry.name_len()) % 8);
-            take_n_bytes(&mut bytes, padding_len);
+            if version < 4 {
+                let padding_len = entry.padding_len();
+                if bytes.len() < padding_len {
+                    return Err(Error::InvalidIndex(format!("entry '{}' is truncated", entry.name)))
+                }
+                take_n_bytes(bytes, padding_len);
+            }
 
             entries.push(entry);
         }
 
-        entries
+        Ok(entries)
+    }
+
+    /*
+     * The version the index is written with. Like git, version 3 is only
+     * used when an entry needs extended flags, so version 2 and 3 indexes
+     * switch to whichever suffices.
+     */
+    pub fn write_version(&self) -> u32 {
+        match self.header.version {
+            2 | 3 if self.entries.iter().any(|entry| entry.extended_flags != 0) => 3,
+            2 | 3 => 2,
+            version => version
+        }
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut index_bytes: Vec<u8> = Vec::new();
+        let version = self.write_version();
 
         index_bytes.extend(self.header.signature.to_be_bytes());
-        index_bytes.extend(self.header.version.to_be_bytes());
-        index_bytes.extend(self.header.num_entries.to_be_bytes());
+        index_bytes.extend(version.to_be_bytes());
+        index_bytes.extend((self.entries.len() as u32).to_be_bytes());
 
+        let mut previous = "";
         for entry in &self.entries {
             index_bytes.extend(entry.ctime_sec.to_be_bytes());
             index_bytes.extend(entry.ctime_nano.to_be_bytes());
@@ -101,21 +227,62 @@ impl Index {
             index_bytes.extend(entry.mtime_nano.to_be_bytes());
             index_bytes.extend(entry.dev.to_be_bytes());
             index_bytes.extend(entry.ino.to_be_bytes());
-            index_bytes.extend(entry.mode.to_be_bytes());
+            index_bytes.extend(entry.mode.bits().to_be_bytes());
             index_bytes.extend(entry.uid.to_be_bytes());
             index_bytes.extend(entry.gid.to_be_bytes());
             index_bytes.extend(entry.size.to_be_bytes());
             index_bytes.extend(entry.key.0);
-            index_bytes.extend(entry.flags.to_be_bytes());
-            index_bytes.extend(entry.name.as_bytes());
-            let padding_len = 8 - ((6 + entry.name_len()) % 8);
-            index_bytes.extend(iter::repeat_n(0, padding_len));
+            match entry.extended_flags {
+                0 => index_bytes.extend((entry.flags & !EXTENDED).to_be_bytes()),
+                extended_flags => {
+                    index_bytes.extend((entry.flags | EXTENDED).to_be_bytes());
+                    index_bytes.extend(extended_flags.to_be_bytes());
+                }
+            }
+
+            if version == 4 {
+                /* The number of bytes to drop from the previous name, then the rest of this one */
+                let common = iter::zip(previous.bytes(), entry.name.bytes()).take_while(|(a, b)| a == b).count();
+                index_bytes.extend(encode_varint(previous.len() - common));
+                index_bytes.extend(&entry.name.as_bytes()[common..]);
+                index_bytes.push(0);
+                previous = &entry.name;
+            } else {
+                index_bytes.extend(entry.name.as_bytes());
+                index_bytes.extend(iter::repeat_n(0, entry.padding_len()));
+            }
         }
 
+        write_extensions(&mut index_bytes, &self.extensions);
+        let checksum = Sha1::digest(&index_bytes);
+        index_bytes.extend_from_slice(&checksum);
         index_bytes
     }
 }
 
+impl Repository {
+    pub fn index_path(&self) -> PathBuf {
+        self.git_path("index")
+    }
+
+    pub fn read_index(&self) -> Result<Index, Error> {
+        Index::read(self.index_path())
+    }
+
+    pub fn write_index(&self, index: &Index) -> Result<(), Error> {
+        index.write(self.index_path())
+    }
+
+    /* When the index was last written, in seconds since the epoch */
+    pub fn index_mtime(&self) -> Result<u32, Error> {
+        Ok(fs::metadata(self.index_path())?
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as u32)
+            .unwrap_or(0))
+    }
+}
+
 pub struct IndexEntry {
     /*
      * The last time a file's metadata changed. 
@@ -142,7 +309,7 @@ pub struct IndexEntry {
      *   Only 0755 and 0644 are valid for regular files.
      *   Symbolic links and gitlinks have value 0 in this field
      */
-    mode: u32,
+    mode: FileMode,
     /* stat(2) data */
     uid: u32,
     /* stat(2) data */
@@ -160,27 +327,39 @@ pub struct IndexEntry {
      *   is stored in this field.
     */
     flags: u16,
+    /*
+     * A 16-bit field in version 3 and up, only present if the extended
+     * flag is set in 'flags' (high to low bits)
+     *   1-bit reserved for future
+     *   1-bit skip-worktree flag (used by sparse checkout)
+     *   1-bit intent-to-add flag (used by "git add -N")
+     *   13-bit unused, must be zero
+     */
+    extended_flags: u16,
 
     /* Variable length name entry (relative unix path)*/
     pub name: String,
 }
 
 impl IndexEntry {
-    pub fn create(key: Hash, filename: &str) -> Self{ let stat =
-        fs::metadata(filename).unwrap();
+    /*
+     * An entry named `filename` with the stat data of the file at `path`.
+     * A symlink is not followed, its own stat data is recorded.
+     */
+    pub fn create(key: Hash, mode: FileMode, filename: &str, path: &Path) -> Result<Self, Error> {
+        let stat = fs::symlink_metadata(path)?;
 
         let ctime_sec  = stat.ctime() as u32; let ctime_nano = stat.ctime_nsec() as u32; let
             mtime_sec  = stat.mtime() as u32; let mtime_nano = stat.mtime_nsec() as u32;
         let dev        = stat.dev() as u32;
         let ino        = stat.ino() as u32;
-        let mode       = (1000 & 0x00F) << 12 | 0o0644 & 0x1FF;
         let uid        = stat.uid() as u32;
         let gid        = stat.gid() as u32;
         let size       = stat.len() as u32;
-        let flags      = filename.len() as u16;
+        let flags      = filename.len().min(NAME_MASK as usize) as u16;
         let name       = filename;
 
-        IndexEntry {
+        Ok(IndexEntry {
             ctime_sec,
             ctime_nano,
             mtime_sec,
@@ -193,29 +372,58 @@ impl IndexEntry {
             size,
             key,
             flags,
+            extended_flags: 0,
             name: name.to_string()
-        }
+        })
     }
 
-    fn read(bytes: &mut &[u8]) -> Self {
+    /* Reads an entry of an index of `version`, following `previous` in it */
+    fn read(bytes: &mut &[u8], version: u32, previous: &str) -> Result<Self, Error> {
+        if bytes.len() < ENTRY_SIZE {
+            return Err(Error::InvalidIndex(String::from("entry is truncated")))
+        }
         let ctime_sec  = take_u32(bytes);
         let ctime_nano = take_u32(bytes);
         let mtime_sec  = take_u32(bytes);
         let mtime_nano = take_u32(bytes);
         let dev        = take_u32(bytes);
         let ino        = take_u32(bytes);
-        let mode       = take_u32(bytes);        
+        let mode       = take_u32(bytes);
+        let mode       = FileMode::from_bits(mode)
+                            .filter(|&mode| mode != FileMode::Tree)
+                            .ok_or_else(|| Error::InvalidIndex(format!("entry has invalid mode {:o}", mode)))?;
         let uid        = take_u32(bytes);
         let gid        = take_u32(bytes);
         let size       = take_u32(bytes);
         let key        = take_hash(bytes);
         let flags      = take_u16(bytes);
-        let name_len   = Self::name_len_from_flags(flags);
-        let name_bytes = take_n_bytes(bytes, name_len);
+        let extended_flags = match flags & EXTENDED != 0 {
+            true if version < 3 => return Err(Error::InvalidIndex(String::from("extended flags in a version 2 index"))),
+            true if bytes.len() < 2 => return Err(Error::InvalidIndex(String::from("entry is truncated"))),
+            true => take_u16(bytes),
+            false => 0
+        };
+        if extended_flags & !(SKIP_WORKTREE | INTENT_TO_ADD) != 0 {
+            return Err(Error::InvalidIndex(format!("unknown extended flags {:x}", extended_flags)))
+        }
+        let name_bytes = match version {
+            4 => Self::read_compressed_name(bytes, previous)?,
+            _ => {
+                /* A name too long for the flags ends at the NUL byte of the padding */
+                let name_len = match Self::name_len_from_flags(flags) {
+                    len if len == NAME_MASK as usize => bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len()),
+                    len => len
+                };
+                if bytes.len() < name_len {
+                    return Err(Error::InvalidIndex(String::from("entry name is truncated")))
+                }
+                take_n_bytes(bytes, name_len)
+            }
+        };
         let name       = String::from_utf8(name_bytes)
-                            .expect("ERROR: Unable to read file name");
+                            .map_err(|_| Error::InvalidIndex(String::from("entry name is not valid UTF-8")))?;
 
-        IndexEntry {
+        Ok(IndexEntry {
             ctime_sec,
             ctime_nano,
             mtime_sec,
@@ -227,27 +435,125 @@ impl IndexEntry {
             gid,
             size,
             key,
-            flags,
+            flags: flags & !EXTENDED,
+            extended_flags,
             name
-        }
+        })
+    }
+
+    /*
+     * A version 4 name: the number of bytes to drop from the end of the
+     * previous name as a varint, followed by the NUL terminated bytes to
+     * append to what remains.
+     */
+    fn read_compressed_name(bytes: &mut &[u8], previous: &str) -> Result<Vec<u8>, Error> {
+        let (strip, consumed) = decode_varint(bytes, 0)
+            .ok_or_else(|| Error::InvalidIndex(String::from("entry name is truncated")))?;
+        let keep = previous.len().checked_sub(strip)
+            .ok_or_else(|| Error::InvalidIndex(format!("entry after '{}' strips too much of its name", previous)))?;
+        *bytes = &bytes[consumed..];
+
+        let end = bytes.iter()
+            .position(|&byte| byte == 0)
+            .ok_or_else(|| Error::InvalidIndex(String::from("entry name is truncated")))?;
+        let mut name = previous.as_bytes()[..keep].to_vec();
+        name.extend_from_slice(&bytes[..end]);
+        *bytes = &bytes[end + 1..];
+        Ok(name)
     }
 
-    fn name_len(&self) -> usize {
-        Self::name_len_from_flags(self.flags)
+    /* The NUL bytes padding a version 2 or 3 entry to a multiple of eight bytes */
+    fn padding_len(&self) -> usize {
+        let extended_len = if self.extended_flags != 0 { 2 } else { 0 };
+        8 - (ENTRY_SIZE + extended_len + self.name.len()) % 8
     }
 
     fn name_len_from_flags(flags: u16) -> usize {
-        (flags & 0x0FFF).into()
+        (flags & NAME_MASK).into()
+    }
+
+    /* The merge stage, 0 unless the path is conflicted */
+    pub fn stage(&self) -> u16 {
+        (self.flags >> 12) & 0x3
+    }
+
+    /* Entries are sorted by the bytes of their path, then by stage */
+    pub fn sort_key(&self) -> (&[u8], u16) {
+        (self.name.as_bytes(), self.stage())
+    }
+
+    /* Whether the file is left out of the working tree, e.g. by a sparse checkout */
+    pub fn skip_worktree(&self) -> bool {
+        self.extended_flags & SKIP_WORKTREE != 0
+    }
+
+    pub fn set_skip_worktree(&mut self, skip: bool) {
+        self.set_extended_flag(SKIP_WORKTREE, skip)
+    }
+
+    /* Whether the path was added with `git add -N`, recording only that it will be */
+    pub fn intent_to_add(&self) -> bool {
+        self.extended_flags & INTENT_TO_ADD != 0
+    }
+
+    pub fn set_intent_to_add(&mut self, intent: bool) {
+        self.set_extended_flag(INTENT_TO_ADD, intent)
+    }
+
+    fn set_extended_flag(&mut self, flag: u16, set: bool) {
+        match set {
+            true => self.extended_flags |= flag,
+            false => self.extended_flags &= !flag
+        }
+    }
+
+    pub fn mode(&self) -> FileMode {
+        self.mode
+    }
+
+    pub fn mtime(&self) -> (u32, u32) {
+        (self.mtime_sec, self.mtime_nano)
+    }
+
+    /*
+     * Whether the file looks unchanged since it was added, judged by the
+     * stat(2) data alone. The caller must still rehash "racily clean"
+     * entries, i.e. files modified in the same second the index was written.
+     */
+    pub fn matches_stat(&self, stat: &fs::Metadata) -> bool {
+        self.mtime_sec == stat.mtime() as u32
+            && self.mtime_nano == stat.mtime_nsec() as u32
+            && self.ctime_sec == stat.ctime() as u32
+            && self.ctime_nano == stat.ctime_nsec() as u32
+            && self.ino == stat.ino() as u32
+            && self.size == stat.len() as u32
+    }
+
+    /*
+     * The stat data and flags as `git ls-files --debug` shows them, the
+     * flags less the name length and with the extended flags above them.
+     */
+    pub fn debug_stat(&self) -> String {
+        let flags = match self.extended_flags {
+            0 => (self.flags & !NAME_MASK) as u32,
+            extended_flags => ((extended_flags as u32) << 16) | ((self.flags & !NAME_MASK) | EXTENDED) as u32
+        };
+        format!("  ctime: {}:{}\n  mtime: {}:{}\n  dev: {}\tino: {}\n  uid: {}\tgid: {}\n  size: {}\tflags: {:x}",
+            self.ctime_sec, self.ctime_nano,
+            self.mtime_sec, self.mtime_nano,
+            self.dev, self.ino,
+            self.uid, self.gid,
+            self.size, flags)
     }
 
     pub fn object_type(&self) -> u32 {
         // First 4 bits
-        (self.mode >> 12) & 0x00F
+        (self.mode.bits() >> 12) & 0x00F
     }
 
     pub fn permission(&self) -> u32 {
         // Final 9 bits
-        self.mode & 0x1FF
+        self.mode.bits() & 0x1FF
     }
 }
 
@@ -257,7 +563,7 @@ impl std::fmt::Display for IndexEntry {
             self.object_type(),
             self.permission(),
             self.key,
-            0,
+            self.stage(),
             self.name)
     }
 }
@@ -269,12 +575,13 @@ impl std::fmt::Debug for IndexEntry {
         writeln!(f, "  mtime {}:{}", self.mtime_sec, self.mtime_nano)?;
         writeln!(f, "  dev   {}", self.dev)?;
         writeln!(f, "  ino   {}", self.ino)?;
-        writeln!(f, "  mode  {}", self.mode)?;
+        writeln!(f, "  mode  {:o}", self.mode)?;
         writeln!(f, "  uid   {}", self.uid)?;
         writeln!(f, "  gid   {}", self.gid)?;
         writeln!(f, "  size  {}", self.size)?;
         writeln!(f, "  key   {}", self.key)?;
         writeln!(f, "  flags {}", self.flags)?;
+        writeln!(f, "  extended flags {}", self.extended_flags)?;
         writeln!(f, "  name  {}", self.name)?;
         writeln!(f, "}}")
     }
@@ -283,13 +590,13 @@ impl std::fmt::Debug for IndexEntry {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::hash_blob;
+    use crate::test_util::*;
 
     #[test]
     fn read_header_from_index() {
         let filename = String::from("examples/index");
 
-        let index = Index::read(&filename);
+        let index = Index::read(&filename).unwrap();
         let bytes: [u8; 4] = index.header.signature.to_be_bytes();
         let actual = str::from_utf8(&bytes).unwrap();
 
@@ -301,7 +608,7 @@ mod test {
     fn read_entry_hash_from_index() {
         let filename = String::from("examples/index");
 
-        let index = Index::read(&filename);
+        let index = Index::read(&filename).unwrap();
         let key = index.entries[0].key.to_string();
 
         let expected = String::from("ea8c4bf7f35f6f77f75d92ad8ce8349f6e81ddba");
@@ -312,7 +619,7 @@ mod test {
     fn parse_mode_from_index() {
         let filename = String::from("examples/index");
 
-        let index = Index::read(&filename);
+        let index = Index::read(&filename).unwrap();
         let object_type = index.entries[0].object_type();
         let permission = index.entries[0].permission();
 
@@ -327,7 +634,7 @@ mod test {
     fn list_entry_from_index() {
         let filename = String::from("examples/index");
 
-        let index = Index::read(&filename);
+        let index = Index::read(&filename).unwrap();
         let output = index.entries[5].to_string();
 
         let expected = 
@@ -340,13 +647,125 @@ mod test {
         let filename = "examples/blob.c";
         let contents = fs::read(filename).unwrap();
 
-        let key = hash_blob(contents);
+        let key = hash_object(ObjectType::Blob, &contents);
 
-        let index_entry = IndexEntry::create(key, filename).to_string();
+        let index_entry = IndexEntry::create(key, FileMode::Regular, filename, Path::new(filename)).unwrap().to_string();
 
         let expected = 
             "100644 d9fa2b8cd651190f6ff5932113491d0a2995b116 0       examples/blob.c";
 
         assert_eq!(index_entry, expected);
     }
+
+    #[test]
+    fn reject_truncated_index() {
+        let mut bytes = fs::read("examples/index").unwrap();
+        bytes.truncate(100);
+
+        let header = Index::read_header(&bytes[..12]).unwrap();
+        let entries = Index::read_entries(&mut &bytes[12..], header.num_entries as usize, header.version);
+
+        assert!(matches!(entries, Err(Error::InvalidIndex(_))));
+        assert!(Index::read_header(b"DIRX\0\0\0\x02\0\0\0\0").is_err());
+    }
+
+    #[test]
+    fn reject_invalid_entry_mode() {
+        let bytes = fs::read("examples/index").unwrap();
+        let header = Index::read_header(&bytes[..12]).unwrap();
+        let mut entries = bytes[12..].to_vec();
+        /* The mode follows ctime, mtime, dev and ino */
+        entries[24..28].copy_from_slice(&0o100664u32.to_be_bytes());
+
+        let result = Index::read_entries(&mut &entries[..], header.num_entries as usize, header.version);
+
+        assert!(matches!(result, Err(Error::InvalidIndex(_))));
+    }
+
+    #[test]
+    fn index_round_trips_with_extensions() {
+        let bytes = fs::read("examples/index").unwrap();
+
+        let index = Index::parse(&bytes).unwrap();
+
+        assert!(index.cache_tree().is_some());
+        assert_eq!(index.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn reject_bad_checksum() {
+        let mut bytes = fs::read("examples/index").unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+
+        assert!(matches!(Index::parse(&bytes), Err(Error::InvalidIndex(_))));
+    }
+
+    #[test]
+    fn extend_keeps_entries_sorted_and_invalidates_trees() {
+        let index = Index::read("examples/index").unwrap();
+        let key = index.entries[0].key.clone();
+        let entry = |name: &str| IndexEntry::create(key.clone(), FileMode::Regular, name, Path::new("examples/blob.c")).unwrap();
+        let subtrees = index.cache_tree().unwrap().subtrees.clone();
+
+        let index = index.extend(vec![entry("examples/a.c"), entry("0")]);
+
+        let names: Vec<&str> = index.entries.iter().map(|entry| entry.name.as_str()).collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted);
+        assert_eq!(index.header.num_entries as usize, names.len());
+
+        let tree = index.cache_tree().unwrap();
+        assert!(!tree.is_valid());
+        for (name, subtree) in &tree.subtrees {
+            let before = subtrees.iter().find(|(before, _)| before == name).unwrap();
+            assert_eq!(subtree.is_valid(), name != "examples" && before.1.is_valid());
+        }
+        assert_eq!(Index::parse(&index.to_bytes()).unwrap().entries.len(), names.len());
+    }
+
+    #[test]
+    fn convert_index_to_version_4_and_back() {
+        let bytes = fs::read("examples/index").unwrap();
+        let mut index = Index::parse(&bytes).unwrap();
+
+        index.header.version = 4;
+        let compressed = index.to_bytes();
+        assert!(compressed.len() < bytes.len());
+
+        let mut index = Index::parse(&compressed).unwrap();
+        assert_eq!(index.entries[5].name, "examples/blob.c");
+        index.header.version = 2;
+        assert_eq!(index.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn extended_flags_need_version_3() {
+        let mut index = Index::read("examples/index").unwrap();
+        index.entries[1].set_intent_to_add(true);
+        let bytes = index.to_bytes();
+        assert_eq!(index.write_version(), 3);
+
+        let index = Index::parse(&bytes).unwrap();
+        assert!(index.entries[1].intent_to_add());
+        assert!(!index.entries[0].intent_to_add() && !index.entries[1].skip_worktree());
+
+        let mut bytes = bytes;
+        bytes[7] = 2;
+        let header = Index::read_header(&bytes[..12]).unwrap();
+        let entries = Index::read_entries(&mut &bytes[12..], header.num_entries as usize, header.version);
+        assert!(matches!(entries, Err(Error::InvalidIndex(_))));
+    }
+
+    #[test]
+    fn refuse_to_write_split_index() {
+        let dir = TempDir::new("split-index");
+        let mut index = Index::read("examples/index").unwrap();
+        let base = index.entries[0].key.clone();
+        index.extensions.push(Extension::Link { base, bitmaps: Vec::new() });
+
+        assert!(matches!(index.write(dir.join("index")), Err(Error::InvalidIndex(_))));
+        assert!(!dir.join("index").exists());
+    }
 }
@@ -0,0 +1,374 @@
+use crate::error::*;
+use crate::hash::*;
+use crate::util::*;
+
+use std::fmt;
+
+use sha1::{Digest, Sha1};
+
+/*
+ * The cached tree extension ("TREE"): the tree object names of the index's
+ * directories, so unchanged directories need not be written again. A
+ * directory whose entries changed since is invalid, with no object name
+ * and an entry count of -1.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheTree {
+    /* The number of index entries below this directory, -1 when invalid */
+    pub entry_count: i32,
+    pub hash: Option<Hash>,
+    /* The sub directories by name, in the order they were written */
+    pub subtrees: Vec<(String, CacheTree)>,
+}
+
+/* A conflict resolved since the last commit, as recorded in "REUC" */
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolveUndo {
+    pub path: String,
+    /* The mode and object name of stages 1 to 3, a mode of 0 meaning absent */
+    pub stages: [Option<(u32, Hash)>; 3],
+}
+
+/*
+ * An index extension. Those nit understands are parsed and written back
+ * as they were read; the end of index entry extension is recomputed on
+ * write, as it depends on what precedes it.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Extension {
+    Tree(CacheTree),
+    ResolveUndo(Vec<ResolveUndo>),
+    /* The untracked cache ("UNTR"), kept as is */
+    Untracked(Vec<u8>),
+    /*
+     * The split index ("link"): the shared index holding the other entries
+     * and the EWAH bitmaps of the entries it deletes and replaces. Such an
+     * index can be read but not written, see Index::write.
+     */
+    Link { base: Hash, bitmaps: Vec<u8> },
+    /* "EOIE", the offset of the first extension for threaded readers */
+    EndOfIndex,
+}
+
+impl Extension {
+    pub fn signature(&self) -> &'static [u8; 4] {
+        match self {
+            Extension::Tree(_) => b"TREE",
+            Extension::ResolveUndo(_) => b"REUC",
+            Extension::Untracked(_) => b"UNTR",
+            Extension::Link { .. } => b"link",
+            Extension::EndOfIndex => b"EOIE",
+        }
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::InvalidIndex(message)
+}
+
+/* Splits off the text before the next NUL byte */
+fn take_until_nul<'a>(input: &mut &'a [u8], what: &str) -> Result<&'a [u8], Error> {
+    let end = input.iter()
+        .position(|&byte| byte == 0)
+        .ok_or_else(|| invalid(format!("{} is not terminated", what)))?;
+    let text = &input[..end];
+    *input = &input[end + 1..];
+    Ok(text)
+}
+
+fn take_checked_hash(input: &mut &[u8], what: &str) -> Result<Hash, Error> {
+    if input.len() < 20 {
+        return Err(invalid(format!("{} is truncated", what)))
+    }
+    Ok(take_hash(input))
+}
+
+impl CacheTree {
+    /* An invalid tree with no sub directories, e.g. for a new index */
+    pub fn invalid() -> Self {
+        CacheTree { entry_count: -1, hash: None, subtrees: Vec::new() }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.entry_count >= 0
+    }
+
+    /*
+     * Each directory is "<path>\0<entry count> <subtree count>\n" followed
+     * by its object name if it is valid, then its sub directories.
+     */
+    fn read(input: &mut &[u8]) -> Result<(String, CacheTree), Error> {
+        let name = String::from_utf8_lossy(take_until_nul(input, "Cached tree path")?).to_string();
+        let end = input.iter()
+            .position(|&byte| byte == b'\n')
+            .ok_or_else(|| invalid(format!("Cached tree '{}' has no counts", name)))?;
+        let counts = String::from_utf8_lossy(&input[..end]).to_string();
+        *input = &input[end + 1..];
+
+        let bad_counts = || invalid(format!("Cached tree '{}' has invalid counts '{}'", name, counts));
+        let (entry_count, subtree_count) = counts.split_once(' ').ok_or_else(bad_counts)?;
+        let entry_count: i32 = entry_count.parse().map_err(|_| bad_counts())?;
+        let subtree_count: usize = subtree_count.parse().map_err(|_| bad_counts())?;
+
+        let hash = match entry_count >= 0 {
+            true => Some(take_checked_hash(input, "Cached tree")?),
+            false => None
+        };
+        let mut subtrees = Vec::new();
+        for _ in 0..subtree_count {
+            subtrees.push(CacheTree::read(input)?);
+        }
+        Ok((name, CacheTree { entry_count, hash, subtrees }))
+    }
+
+    fn write(&self, name: &str, output: &mut Vec<u8>) {
+        output.extend_from_slice(name.as_bytes());
+        output.push(0);
+        output.extend(format!("{} {}\n", self.entry_count, self.subtrees.len()).into_bytes());
+        if let Some(hash) = self.hash.as_ref().filter(|_| self.is_valid()) {
+            output.extend_from_slice(&hash.0);
+        }
+        for (name, subtree) in &self.subtrees {
+            subtree.write(name, output);
+        }
+    }
+
+    pub fn parse(mut data: &[u8]) -> Result<Self, Error> {
+        let (_, tree) = CacheTree::read(&mut data)?;
+        if !data.is_empty() {
+            return Err(invalid("Cached tree has trailing data".into()))
+        }
+        Ok(tree)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        self.write("", &mut output);
+        output
+    }
+
+    /*
+     * Invalidates the directories containing `path`, i.e. the root and
+     * every directory on the way down, after an entry below them changed.
+     */
+    pub fn invalidate(&mut self, path: &str) {
+        self.entry_count = -1;
+        self.hash = None;
+        if let Some((dir, rest)) = path.split_once('/')
+            && let Some((_, subtree)) = self.subtrees.iter_mut().find(|(name, _)| name == dir) {
+            subtree.invalidate(rest);
+        }
+    }
+}
+
+impl ResolveUndo {
+    /*
+     * Each entry is "<path>\0" followed by the three stages' modes as
+     * octal text, each NUL terminated, then the object names of the
+     * stages whose mode is not 0.
+     */
+    fn read(input: &mut &[u8]) -> Result<Self, Error> {
+        let path = String::from_utf8_lossy(take_until_nul(input, "Resolve undo path")?).to_string();
+        let mut modes = [0; 3];
+        for mode in modes.iter_mut() {
+            let text = String::from_utf8_lossy(take_until_nul(input, "Resolve undo mode")?).to_string();
+            *mode = u32::from_str_radix(&text, 8)
+                .map_err(|_| invalid(format!("Resolve undo of '{}' has invalid mode '{}'", path, text)))?;
+        }
+
+        let mut stages = [None, None, None];
+        for (stage, mode) in stages.iter_mut().zip(modes) {
+            if mode != 0 {
+                *stage = Some((mode, take_checked_hash(input, "Resolve undo")?));
+            }
+        }
+        Ok(ResolveUndo { path, stages })
+    }
+
+    fn write(&self, output: &mut Vec<u8>) {
+        output.extend_from_slice(self.path.as_bytes());
+        output.push(0);
+        for stage in &self.stages {
+            output.extend(format!("{:o}", stage.as_ref().map_or(0, |(mode, _)| *mode)).into_bytes());
+            output.push(0);
+        }
+        for (_, hash) in self.stages.iter().flatten() {
+            output.extend_from_slice(&hash.0);
+        }
+    }
+}
+
+/*
+ * Reads the extensions between the last entry, which ends at
+ * `entries_end`, and the trailing checksum. Each extension is a four byte
+ * signature, a 32-bit size and its data. Unknown extensions starting with
+ * an uppercase letter are optional and skipped, like git does, but any
+ * other unknown extension is required to understand the index.
+ */
+pub fn read_extensions(mut input: &[u8], entries_end: usize) -> Result<Vec<Extension>, Error> {
+    let mut extensions = Vec::new();
+    let mut headers = Sha1::new();
+    while !input.is_empty() {
+        if input.len() < 8 {
+            return Err(invalid("extension header is truncated".into()))
+        }
+        let header = &input[..8];
+        let signature: [u8; 4] = input[..4].try_into().unwrap();
+        let mut size_bytes = &input[4..8];
+        let size = take_u32(&mut size_bytes) as usize;
+        let name = String::from_utf8_lossy(&signature).to_string();
+        let data = input.get(8..8 + size)
+            .ok_or_else(|| invalid(format!("extension {} is truncated", name)))?;
+        input = &input[8 + size..];
+
+        let extension = match &signature {
+            b"TREE" => Extension::Tree(CacheTree::parse(data)?),
+            b"REUC" => {
+                let mut data = data;
+                let mut entries = Vec::new();
+                while !data.is_empty() {
+                    entries.push(ResolveUndo::read(&mut data)?);
+                }
+                Extension::ResolveUndo(entries)
+            },
+            b"UNTR" => Extension::Untracked(data.to_vec()),
+            b"link" => {
+                let mut data = data;
+                let base = take_checked_hash(&mut data, "Split index link")?;
+                Extension::Link { base, bitmaps: data.to_vec() }
+            },
+            b"EOIE" => {
+                let mut data = data;
+                if data.len() != 24 {
+                    return Err(invalid("end of index entry extension has an invalid size".into()))
+                }
+                let offset = take_u32(&mut data) as usize;
+                let hash = take_hash(&mut data);
+                if offset != entries_end || hash.0[..] != headers.clone().finalize()[..] {
+                    return Err(invalid("end of index entry extension does not match the index".into()))
+                }
+                Extension::EndOfIndex
+            },
+            signature if signature[0].is_ascii_uppercase() => {
+                headers.update(header);
+                continue
+            },
+            _ => return Err(invalid(format!("index uses the {} extension, which nit does not understand", name)))
+        };
+        headers.update(header);
+        extensions.push(extension);
+    }
+    Ok(extensions)
+}
+
+/*
+ * Appends the extensions to an index whose entries end at the end of
+ * `output`. The end of index entry extension, if present, is written last
+ * with the offset of the first extension and a hash of their headers.
+ */
+pub fn write_extensions(output: &mut Vec<u8>, extensions: &[Extension]) {
+    let entries_end = output.len();
+    let mut headers = Sha1::new();
+    for extension in extensions.iter().filter(|extension| **extension != Extension::EndOfIndex) {
+        let mut data = Vec::new();
+        match extension {
+            Extension::Tree(tree) => data = tree.to_bytes(),
+            Extension::ResolveUndo(entries) => entries.iter().for_each(|entry| entry.write(&mut data)),
+            Extension::Untracked(raw) => data.extend_from_slice(raw),
+            Extension::Link { base, bitmaps } => {
+                data.extend_from_slice(&base.0);
+                data.extend_from_slice(bitmaps);
+            },
+            Extension::EndOfIndex => {}
+        }
+
+        let mut header = extension.signature().to_vec();
+        header.extend((data.len() as u32).to_be_bytes());
+        headers.update(&header);
+        output.append(&mut header);
+        output.append(&mut data);
+    }
+
+    if extensions.contains(&Extension::EndOfIndex) {
+        output.extend_from_slice(b"EOIE");
+        output.extend(24u32.to_be_bytes());
+        output.extend((entries_end as u32).to_be_bytes());
+        output.extend_from_slice(&headers.finalize());
+    }
+}
+
+impl fmt::Display for CacheTree {
+    /*
+     * One line per directory like git's test-tool dump-cache-tree, e.g.
+     * "<hash> src/ (3 entries, 1 subtrees)" or "invalid src/ (1 subtrees)"
+     */
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn show(f: &mut fmt::Formatter<'_>, path: &str, tree: &CacheTree) -> fmt::Result {
+            match &tree.hash {
+                Some(hash) => writeln!(f, "{} {} ({} entries, {} subtrees)",
+                    hash, path, tree.entry_count, tree.subtrees.len())?,
+                None => writeln!(f, "invalid {} ({} subtrees)", path, tree.subtrees.len())?
+            }
+            for (name, subtree) in &tree.subtrees {
+                show(f, &format!("{}{}/", path, name), subtree)?;
+            }
+            Ok(())
+        }
+        show(f, "", self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn extensions_round_trip_with_end_of_index() {
+        let tree = CacheTree {
+            entry_count: 2,
+            hash: Some(Hash([1; 20])),
+            subtrees: vec![(String::from("src"), CacheTree::invalid())]
+        };
+        let resolved = ResolveUndo {
+            path: String::from("file"),
+            stages: [Some((0o100644, Hash([2; 20]))), None, Some((0o100755, Hash([3; 20])))]
+        };
+        let extensions = vec![
+            Extension::Tree(tree),
+            Extension::ResolveUndo(vec![resolved]),
+            Extension::EndOfIndex
+        ];
+
+        let mut output = vec![0; 12];
+        write_extensions(&mut output, &extensions);
+
+        assert_eq!(read_extensions(&output[12..], 12).unwrap(), extensions);
+        assert!(read_extensions(&output[12..], 16).is_err());
+    }
+
+    #[test]
+    fn skip_optional_and_reject_required_extensions() {
+        let mut optional = b"ABCD\0\0\0\x01x".to_vec();
+        optional.extend_from_slice(b"TREE\0\0\0\x06\0-1 0\n");
+
+        let extensions = read_extensions(&optional, 0).unwrap();
+        assert_eq!(extensions, vec![Extension::Tree(CacheTree::invalid())]);
+        assert!(read_extensions(b"abcd\0\0\0\0", 0).is_err());
+    }
+
+    #[test]
+    fn invalidate_directories_on_the_path() {
+        let valid = |subtrees| CacheTree { entry_count: 1, hash: Some(Hash([1; 20])), subtrees };
+        let mut tree = valid(vec![
+            (String::from("a"), valid(vec![(String::from("b"), valid(Vec::new()))])),
+            (String::from("c"), valid(Vec::new()))
+        ]);
+
+        tree.invalidate("a/b/file");
+
+        assert_eq!(tree.to_string(), format!(
+            "invalid  (2 subtrees)\ninvalid a/ (1 subtrees)\ninvalid a/b/ (0 subtrees)\n{} c/ (1 entries, 0 subtrees)\n",
+            Hash([1; 20])
+        ));
+    }
+}
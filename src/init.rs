@@ -0,0 +1,102 @@
+use crate::error::*;
+use crate::index::*;
+
+use std::fs;
+
+use std::path::{Path, PathBuf};
+
+const DIRECTORIES: [&str; 6] = [
+    "objects/info",
+    "objects/pack",
+    "refs/heads",
+    "refs/tags",
+    "info",
+    "hooks",
+];
+
+fn default_config(bare: bool) -> String {
+    let mut config = String::from("[core]\n");
+    config.push_str("\trepositoryformatversion = 0\n");
+    config.push_str("\tfilemode = true\n");
+    config.push_str(&format!("\tbare = {}\n", bare));
+    if !bare {
+        config.push_str("\tlogallrefupdates = true\n");
+    }
+    config
+}
+
+/*
+ * Creates the repository skeleton in `directory`. A bare repository keeps
+ * the git files directly in `directory`, otherwise they live in `.git`.
+ *
+ * Running init in an existing repository is safe: the directories are
+ * (re)created, but HEAD, config and index are left untouched.
+ */
+pub fn init(directory: &Path, initial_branch: &str, bare: bool) -> Result<PathBuf, Error> {
+    let git_dir = if bare {
+        directory.to_path_buf()
+    } else {
+        directory.join(".git")
+    };
+
+    for sub_directory in DIRECTORIES {
+        fs::create_dir_all(git_dir.join(sub_directory))?;
+    }
+
+    let head = git_dir.join("HEAD");
+    if !head.exists() {
+        fs::write(head, format!("ref: refs/heads/{}\n", initial_branch))?;
+    }
+
+    let config = git_dir.join("config");
+    if !config.exists() {
+        fs::write(config, default_config(bare))?;
+    }
+
+    let description = git_dir.join("description");
+    if !description.exists() {
+        fs::write(description, "Unnamed repository; edit this file 'description' to name the repository.\n")?;
+    }
+
+    let index = git_dir.join("index");
+    if !bare && !index.exists() {
+        fs::write(index, Index::new(Vec::new()).to_bytes())?;
+    }
+
+    Ok(git_dir)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test_util::*;
+
+    #[test]
+    fn init_creates_skeleton() {
+        let dir = TempDir::new("init");
+
+        let git_dir = init(dir.path(), "main", false).unwrap();
+
+        assert_eq!(git_dir, dir.join(".git"));
+        assert!(git_dir.join("objects").is_dir());
+        assert!(git_dir.join("refs/heads").is_dir());
+        assert!(git_dir.join("refs/tags").is_dir());
+        assert_eq!(fs::read_to_string(git_dir.join("HEAD")).unwrap(), "ref: refs/heads/main\n");
+
+        let index = Index::read(git_dir.join("index")).unwrap();
+        assert_eq!(index.header.num_entries, 0);
+    }
+
+    #[test]
+    fn init_bare_repository() {
+        let dir = TempDir::new("init-bare");
+
+        let git_dir = init(dir.path(), "master", true).unwrap();
+
+        assert_eq!(git_dir, dir.path());
+        assert!(!git_dir.join("index").exists());
+        let config = fs::read_to_string(git_dir.join("config")).unwrap();
+        assert!(config.contains("bare = true"));
+    }
+}
@@ -0,0 +1,43 @@
+/*
+ * nit as a library: `Repository` opens or creates a repository and gives
+ * access to its objects, refs, index and config. Every command of the nit
+ * binary is a function here taking the repository it works on.
+ */
+
+pub mod add;
+pub mod branch;
+pub mod cat_file;
+pub mod checkout;
+pub mod command;
+pub mod commit;
+pub mod compress;
+pub mod config;
+pub mod date;
+pub mod diff;
+pub mod error;
+pub mod fsck;
+pub mod gc;
+pub mod hash;
+pub mod ident;
+pub mod ignore;
+pub mod index;
+pub mod index_extension;
+pub mod init;
+pub mod log;
+pub mod ls_files;
+pub mod object;
+pub mod pack;
+pub mod refs;
+pub mod repository;
+pub mod status;
+pub mod tag;
+#[cfg(test)]
+mod test_util;
+pub mod tree;
+pub mod update_index;
+pub mod util;
+pub mod verify_pack;
+
+pub use error::Error;
+pub use hash::Hash;
+pub use repository::Repository;
@@ -0,0 +1,491 @@
+use crate::error::*;
+use crate::hash::*;
+use crate::commit::*;
+use crate::command::*;
+use crate::date::*;
+use crate::repository::*;
+use crate::tag::*;
+use crate::tree::*;
+
+use std::path::PathBuf;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+/*
+ * Walks the commit graph from a set of tips through all parents.
+ *
+ * In `LogOrder::Date` the walk is lazy: the newest commit (by committer
+ * date) of the ones discovered so far is returned next. The topological
+ * orders need the whole graph up front to know when all children of a
+ * commit have been shown.
+ */
+pub struct RevWalk<'a> {
+    repository: &'a Repository,
+    queue: BinaryHeap<(i64, Hash)>,
+    pending: HashMap<Hash, Commit>,
+    seen: HashSet<Hash>,
+    sorted: Option<VecDeque<(Hash, Commit)>>,
+}
+
+impl<'a> RevWalk<'a> {
+    pub fn new(repository: &'a Repository, tips: Vec<Hash>, order: &LogOrder) -> Result<Self, Error> {
+        let mut walk = RevWalk {
+            repository,
+            queue: BinaryHeap::new(),
+            pending: HashMap::new(),
+            seen: HashSet::new(),
+            sorted: None,
+        };
+
+        match order {
+            LogOrder::Date => {
+                for tip in tips {
+                    walk.discover(tip)?;
+                }
+            },
+            LogOrder::DateOrder | LogOrder::Topo => {
+                walk.sorted = Some(sort_topologically(repository, tips, order)?);
+            }
+        }
+
+        Ok(walk)
+    }
+
+    fn discover(&mut self, hash: Hash) -> Result<(), Error> {
+        if self.seen.insert(hash.clone()) {
+            let commit = Commit::read(self.repository, &hash)?;
+            self.queue.push((commit.committer.timestamp, hash.clone()));
+            self.pending.insert(hash, commit);
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for RevWalk<'_> {
+    type Item = Result<(Hash, Commit), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(sorted) = &mut self.sorted {
+            return sorted.pop_front().map(Ok)
+        }
+
+        let (_, hash) = self.queue.pop()?;
+        let commit = self.pending.remove(&hash)?;
+        for parent in &commit.parents {
+            if let Err(err) = self.discover(parent.clone()) {
+                return Some(Err(err))
+            }
+        }
+        Some(Ok((hash, commit)))
+    }
+}
+
+struct ReadyQueue {
+    by_date: BinaryHeap<(i64, Hash)>,
+    stack: Vec<Hash>,
+    date_order: bool,
+}
+
+impl ReadyQueue {
+    fn push(&mut self, hash: Hash, timestamp: i64) {
+        if self.date_order {
+            self.by_date.push((timestamp, hash));
+        } else {
+            self.stack.push(hash);
+        }
+    }
+
+    fn pop(&mut self) -> Option<Hash> {
+        if self.date_order {
+            self.by_date.pop().map(|(_, hash)| hash)
+        } else {
+            self.stack.pop()
+        }
+    }
+}
+
+/* Whether `ancestor` can be reached from `descendant` through its parents */
+pub fn is_ancestor(repository: &Repository, ancestor: &Hash, descendant: &Hash) -> Result<bool, Error> {
+    for commit in RevWalk::new(repository, vec![descendant.clone()], &LogOrder::Date)? {
+        let (hash, _) = commit?;
+        if hash == *ancestor {
+            return Ok(true)
+        }
+    }
+    Ok(false)
+}
+
+/*
+ * A best common ancestor of two commits, like `git merge-base`: of the
+ * commits reachable from both, one that no other such commit descends
+ * from. When there are several, the newest by committer date is chosen.
+ */
+pub fn merge_base(repository: &Repository, a: &Hash, b: &Hash) -> Result<Option<Hash>, Error> {
+    let mut from_a = HashSet::new();
+    for commit in RevWalk::new(repository, vec![a.clone()], &LogOrder::Date)? {
+        from_a.insert(commit?.0);
+    }
+
+    /* The walk from `b` stops at common commits, as their ancestors are no better */
+    let mut candidates = Vec::new();
+    let mut seen = HashSet::new();
+    let mut stack = vec![b.clone()];
+    while let Some(hash) = stack.pop() {
+        if !seen.insert(hash.clone()) {
+            continue
+        }
+        let commit = Commit::read(repository, &hash)?;
+        if from_a.contains(&hash) {
+            candidates.push((commit.committer.timestamp, hash));
+        } else {
+            stack.extend(commit.parents);
+        }
+    }
+
+    candidates.sort_by(|a, b| b.cmp(a));
+    for (_, candidate) in &candidates {
+        let mut best = true;
+        for (_, other) in candidates.iter().filter(|(_, other)| other != candidate) {
+            if is_ancestor(repository, candidate, other)? {
+                best = false;
+                break
+            }
+        }
+        if best {
+            return Ok(Some(candidate.clone()))
+        }
+    }
+    Ok(None)
+}
+
+/*
+ * Kahn's algorithm over all commits reachable from `tips`: a commit is
+ * ready once all of its children have been emitted. Like git,
+ * `LogOrder::Topo` takes the most recently readied commit next so that each
+ * line of history is shown in one go (the last parent of a merge first),
+ * while `LogOrder::DateOrder` takes the newest one.
+ */
+fn sort_topologically(
+    repository: &Repository,
+    tips: Vec<Hash>,
+    order: &LogOrder,
+) -> Result<VecDeque<(Hash, Commit)>, Error> {
+    let mut commits: HashMap<Hash, Commit> = HashMap::new();
+    let mut children: HashMap<Hash, usize> = HashMap::new();
+
+    let mut stack = tips.clone();
+    while let Some(hash) = stack.pop() {
+        if commits.contains_key(&hash) {
+            continue
+        }
+        let commit = Commit::read(repository, &hash)?;
+        for parent in &commit.parents {
+            *children.entry(parent.clone()).or_default() += 1;
+            stack.push(parent.clone());
+        }
+        commits.insert(hash, commit);
+    }
+
+    let mut ready = ReadyQueue {
+        by_date: BinaryHeap::new(),
+        stack: Vec::new(),
+        date_order: *order == LogOrder::DateOrder,
+    };
+
+    let mut queued = HashSet::new();
+    for tip in tips.into_iter().rev() {
+        if !children.contains_key(&tip) && queued.insert(tip.clone()) {
+            let timestamp = commits[&tip].committer.timestamp;
+            ready.push(tip, timestamp);
+        }
+    }
+
+    let mut sorted = VecDeque::with_capacity(commits.len());
+    while let Some(hash) = ready.pop() {
+        let commit = commits.remove(&hash).expect("ready commits are loaded");
+
+        for parent in &commit.parents {
+            let count = children.get_mut(parent).expect("parents are counted");
+            *count -= 1;
+            if *count == 0 {
+                ready.push(parent.clone(), commits[parent].committer.timestamp);
+            }
+        }
+        sorted.push_back((hash, commit));
+    }
+
+    Ok(sorted)
+}
+
+fn short(hash: &Hash) -> String {
+    hash.to_string()[..7].to_string()
+}
+
+/* `%ad` follows `--date`, the other date placeholders have a fixed format */
+fn format_stamp_field(field: char, stamp: &Stamp, date: &DateFormat, now: i64) -> Option<String> {
+    let format = |format: &DateFormat| format_date(stamp.timestamp, &stamp.timezone, format, now);
+    Some(match field {
+        'n' => String::from_utf8_lossy(&stamp.name).to_string(),
+        'e' => String::from_utf8_lossy(&stamp.email).to_string(),
+        'd' => format(date),
+        't' => format(&DateFormat::Unix),
+        'r' => format(&DateFormat::Relative),
+        'i' => format(&DateFormat::Iso),
+        'I' => format(&DateFormat::IsoStrict),
+        _ => return None
+    })
+}
+
+/* Expands the placeholders of `--format`, unknown ones are kept as is */
+pub fn expand_format(format: &str, hash: &Hash, commit: &Commit, date: &DateFormat, now: i64) -> String {
+    let mut output = String::new();
+    let mut chars = format.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue
+        }
+
+        let expanded = match chars.peek() {
+            Some('H') => Some(hash.to_string()),
+            Some('h') => Some(short(hash)),
+            Some('T') => Some(commit.tree.to_string()),
+            Some('t') => Some(short(&commit.tree)),
+            Some('P') => Some(commit.parents.iter().map(Hash::to_string).collect::<Vec<_>>().join(" ")),
+            Some('p') => Some(commit.parents.iter().map(short).collect::<Vec<_>>().join(" ")),
+            Some('s') => Some(commit.subject()),
+            Some('b') => Some(commit.body()),
+            Some('B') => Some(commit.message_text()),
+            Some('n') => Some(String::from("\n")),
+            Some('%') => Some(String::from("%")),
+            Some(&kind @ ('a' | 'c')) => {
+                let stamp = if kind == 'a' { &commit.author } else { &commit.committer };
+                let mut lookahead = chars.clone();
+                lookahead.next();
+                match lookahead.peek().and_then(|&field| format_stamp_field(field, stamp, date, now)) {
+                    Some(value) => {
+                        chars.next();
+                        Some(value)
+                    },
+                    None => None
+                }
+            },
+            _ => None
+        };
+
+        match expanded {
+            Some(value) => {
+                chars.next();
+                output.push_str(&value);
+            },
+            None => output.push('%')
+        }
+    }
+
+    output
+}
+
+pub fn format_medium(hash: &Hash, commit: &Commit, date: &DateFormat, now: i64) -> String {
+    let mut output = format!("commit {}\n", hash);
+    if commit.parents.len() > 1 {
+        let parents: Vec<String> = commit.parents.iter().map(short).collect();
+        output.push_str(&format!("Merge: {}\n", parents.join(" ")));
+    }
+    output.push_str(&format!("Author: {} <{}>\n",
+        String::from_utf8_lossy(&commit.author.name),
+        String::from_utf8_lossy(&commit.author.email)));
+    let author_date = format_date(commit.author.timestamp, &commit.author.timezone, date, now);
+    output.push_str(&format!("Date:   {}\n\n", author_date));
+    for line in commit.message_text().trim_end().lines() {
+        output.push_str(&format!("    {}\n", line));
+    }
+    output
+}
+
+fn same_entries(a: &[Option<TreeEntry>], b: &[Option<TreeEntry>]) -> bool {
+    a.iter().zip(b).all(|(a, b)| match (a, b) {
+        (Some(a), Some(b)) => a.key == b.key && a.mode == b.mode,
+        (None, None) => true,
+        _ => false
+    })
+}
+
+fn entries_at(repository: &Repository, tree: &Hash, paths: &[PathBuf]) -> Result<Vec<Option<TreeEntry>>, Error> {
+    let tree = Tree::read(repository, tree)?;
+    paths.iter().map(|path| tree.find_entry(repository, path)).collect()
+}
+
+/*
+ * A commit touches the paths unless it is identical to one of its parents
+ * at every path. A root commit touches the paths that exist in its tree.
+ */
+fn touches_paths(repository: &Repository, commit: &Commit, paths: &[PathBuf]) -> Result<bool, Error> {
+    if paths.is_empty() {
+        return Ok(true)
+    }
+
+    let entries = entries_at(repository, &commit.tree, paths)?;
+    if commit.parents.is_empty() {
+        return Ok(entries.iter().any(Option::is_some))
+    }
+
+    for parent in &commit.parents {
+        let parent = Commit::read(repository, parent)?;
+        if same_entries(&entries, &entries_at(repository, &parent.tree, paths)?) {
+            return Ok(false)
+        }
+    }
+
+    Ok(true)
+}
+
+fn parse_limit(date: Option<String>, now: i64) -> Result<Option<i64>, Error> {
+    match date {
+        Some(date) => parse_date(&date, now)
+            .map(Some)
+            .ok_or(Error::InvalidArgument(format!("Invalid date '{}'", date))),
+        None => Ok(None)
+    }
+}
+
+pub fn log(repository: &Repository, options: LogOptions) -> Result<(), Error> {
+    let now = now();
+    let since = parse_limit(options.since, now)?;
+    let until = parse_limit(options.until, now)?;
+
+    let mut paths = options.paths;
+    let mut revisions = Vec::new();
+    for revision in options.revisions {
+        match repository.resolve_revision(&revision) {
+            Ok(hash) => revisions.push(hash),
+            Err(_) if PathBuf::from(&revision).exists() => paths.push(PathBuf::from(revision)),
+            Err(err) => return Err(err)
+        }
+    }
+    if revisions.is_empty() {
+        revisions.push(repository.resolve_revision("HEAD")?);
+    }
+
+    /* Limiting to the root of the working tree is no limit */
+    let mut paths = repository.relative_paths(&paths)?;
+    if paths.iter().any(|path| path.as_os_str().is_empty()) {
+        paths.clear();
+    }
+
+    let tips = revisions
+        .into_iter()
+        .map(|hash| peel_to_commit(repository, hash))
+        .collect::<Result<Vec<Hash>, Error>>()?;
+
+    let mut shown = 0;
+    for item in RevWalk::new(repository, tips, &options.order)? {
+        if options.max_count.is_some_and(|max| shown >= max) {
+            break
+        }
+
+        let (hash, commit) = item?;
+        let timestamp = commit.committer.timestamp;
+        if since.is_some_and(|since| timestamp < since) || until.is_some_and(|until| timestamp > until) {
+            continue
+        }
+        if let Some(author) = &options.author {
+            let identity = format!("{} <{}>",
+                String::from_utf8_lossy(&commit.author.name),
+                String::from_utf8_lossy(&commit.author.email));
+            if !identity.contains(author.as_str()) {
+                continue
+            }
+        }
+        if !touches_paths(repository, &commit, &paths)? {
+            continue
+        }
+
+        match &options.format {
+            LogFormat::Medium => {
+                if shown > 0 {
+                    println!();
+                }
+                print!("{}", format_medium(&hash, &commit, &options.date, now));
+            },
+            LogFormat::Oneline => println!("{} {}", short(&hash), commit.subject()),
+            LogFormat::Custom(format) => println!("{}", expand_format(format, &hash, &commit, &options.date, now))
+        }
+        shown += 1;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::object::*;
+    use crate::test_util::*;
+
+    fn example_commit() -> (Hash, Commit) {
+        let content = "tree fa1c738cb61be8fe31fa4427b7bb7c5b12fe4151
+parent c631313b6cc3a747eac28cdb26802678a96b870b
+author Nicolay Roness <nicolay.caspersen.roness@sparebank1.no> 1762103153 +0100
+committer Nicolay Roness <nicolay.caspersen.roness@sparebank1.no> 1762103153 +0100
+
+create blob
+from file
+
+Reads the file and writes it as a blob.
+";
+        let hash = Hash::from_hex("2b5b736708c9aa610745de597d49616336557058").unwrap();
+        (hash, Commit::parse(content.as_bytes()).unwrap())
+    }
+
+    #[test]
+    fn expand_format_placeholders() {
+        let (hash, commit) = example_commit();
+
+        let output = expand_format("%h %p %an <%ae> %ad%n%s%%%x", &hash, &commit, &DateFormat::Default, 1762103153);
+
+        let expected = "2b5b736 c631313 Nicolay Roness <nicolay.caspersen.roness@sparebank1.no> \
+            Sun Nov 2 18:05:53 2025 +0100\ncreate blob from file%%x";
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn format_commit_as_medium() {
+        let (hash, commit) = example_commit();
+
+        let output = format_medium(&hash, &commit, &DateFormat::Iso, 1762103153);
+
+        let expected = "commit 2b5b736708c9aa610745de597d49616336557058\n\
+            Author: Nicolay Roness <nicolay.caspersen.roness@sparebank1.no>\n\
+            Date:   2025-11-02 18:05:53 +0100\n\
+            \n    create blob\n    from file\n    \n\
+            \x20   Reads the file and writes it as a blob.\n";
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn merge_base_of_diverged_branches() {
+        let repository = TempRepo::new("merge-base");
+        let tree = repository.write_object(ObjectType::Tree, Vec::new()).unwrap();
+        let mut time = 1764365370;
+        let mut commit = |parents: &[&Hash]| {
+            time += 60;
+            let stamp = Stamp::parse(format!("A <a@b> {} +0000", time).as_bytes()).unwrap();
+            let parents = parents.iter().map(|&parent| parent.clone()).collect();
+            let commit = Commit::create(tree.clone(), parents, stamp.clone(), stamp, String::from("commit"));
+            repository.write_object(ObjectType::Commit, commit.to_bytes()).unwrap()
+        };
+
+        let root = commit(&[]);
+        let base = commit(&[&root]);
+        let ours = commit(&[&base]);
+        let theirs = commit(&[&base]);
+        let merged = commit(&[&theirs, &ours]);
+        let unrelated = commit(&[]);
+
+        assert_eq!(merge_base(&repository, &ours, &theirs).unwrap(), Some(base.clone()));
+        assert_eq!(merge_base(&repository, &merged, &ours).unwrap(), Some(ours.clone()));
+        assert_eq!(merge_base(&repository, &root, &merged).unwrap(), Some(root));
+        assert_eq!(merge_base(&repository, &unrelated, &ours).unwrap(), None);
+    }
+}
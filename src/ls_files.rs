@@ -0,0 +1,62 @@
+use crate::error::*;
+use crate::index::*;
+use crate::index_extension::*;
+use crate::repository::*;
+
+/* The entry as `git ls-files` shows it, "<mode> <hash> <stage>\t<name>" with `stage` */
+fn format_entry(entry: &IndexEntry, stage: bool) -> String {
+    match stage {
+        true => format!("{:06o} {} {}\t{}", entry.mode(), entry.key, entry.stage(), entry.name),
+        false => entry.name.clone()
+    }
+}
+
+/*
+ * Lists the index entries. With `debug` each entry is followed by its stat
+ * data and flags, and the extensions are described after the entries.
+ */
+pub fn ls_files(repository: &Repository, stage: bool, debug: bool) -> Result<(), Error> {
+    let index = repository.read_index()?;
+    for entry in &index.entries {
+        println!("{}", format_entry(entry, stage));
+        if debug {
+            println!("{}", entry.debug_stat());
+        }
+    }
+
+    if debug {
+        for extension in &index.extensions {
+            let signature = String::from_utf8_lossy(extension.signature());
+            match extension {
+                Extension::Tree(tree) => print!("extension {}:\n{}", signature, tree),
+                Extension::ResolveUndo(entries) => {
+                    println!("extension {}:", signature);
+                    for entry in entries {
+                        let stages = entry.stages.iter().enumerate().filter_map(|(stage, recorded)| {
+                            recorded.as_ref().map(|(mode, hash)| format!("{:06o} {} {}", mode, hash, stage + 1))
+                        });
+                        stages.for_each(|stage| println!("{}\t{}", stage, entry.path));
+                    }
+                },
+                Extension::Untracked(data) => println!("extension {}: {} bytes", signature, data.len()),
+                Extension::Link { base, .. } => println!("extension {}: shared index {}", signature, base),
+                Extension::EndOfIndex => println!("extension {}", signature)
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format_staged_entries() {
+        let index = Index::read("examples/index").unwrap();
+        let entry = &index.entries[5];
+
+        assert_eq!(format_entry(entry, false), "examples/blob.c");
+        assert_eq!(format_entry(entry, true), "100644 d9fa2b8cd651190f6ff5932113491d0a2995b116 0\texamples/blob.c");
+    }
+}
@@ -1,210 +1,233 @@
+use nit::add::*;
+use nit::branch::*;
+use nit::cat_file::*;
+use nit::checkout::*;
+use nit::command::*;
+use nit::commit::*;
+use nit::config::*;
+use nit::diff::*;
+use nit::error::*;
+use nit::fsck::*;
+use nit::gc::*;
+use nit::ignore::*;
+use nit::log::*;
+use nit::ls_files::*;
+use nit::repository::*;
+use nit::status::*;
+use nit::tag::*;
+use nit::update_index::*;
+use nit::verify_pack::*;
+
 use std::env;
-use std::fs;
-use std::io;
 
-use std::io::{Write};
-use std::fs::File;
-use std::path::{PathBuf, Component};
 use std::process::exit;
-use std::time::SystemTime;
-
-mod compress;
-mod commit;
-mod command;
-mod object;
-mod hash;
-mod index;
-mod tree;
-mod util;
-
-use commit::*;
-use command::*;
-use hash::*;
-use tree::*;
-use index::*;
-use util::*;
-use object::*;
-
-const ROOT: &str   = ".git";
-const INDEX_FILE: &str = ".git/index";
-const BRANCH: &str = "command";
-const IGNORE: [&str; 3] = [".git", "playground", "target"];
-
-fn get_author() -> Stamp {
-    Stamp {
-        name: "Nicolay Roness".to_string(),
-        email: "nicolay.caspersen.roness@sparebank1.no".to_string(),
-        timestamp: SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as u32 - 5 * 86400
-    }
-}
-
-fn get_parent() -> Hash {
-    let path = format!("{}/refs/heads/{}", ROOT, BRANCH);
-    let parent_hex = fs::read_to_string(path)
-        .expect("ERROR: Unable to read parent hex. Tip: Are you on the correct branch?");
-    Hash::from_hex(&parent_hex[..40])
-}
-
-fn remove_leading_dot_slash(path: PathBuf) -> PathBuf {
-    let components: Vec<_> = path.components().collect();
-
-    if let Some(Component::CurDir) = components.first() {
-        components.iter().skip(1).collect()
-    } else {
-        path.to_path_buf()
-    }
-}
-
-fn add(path: PathBuf) -> Vec<IndexEntry> {
-    let mut entries = Vec::new();
-    if path.is_dir() {
-        let dir = std::fs::read_dir(path).expect("Unable to read directory");
-        for path in dir {
-            let path = path.unwrap().path();
-            if IGNORE.iter().any(|i| path.ends_with(i)) {
-                println!("[INFO] ignoring {}", path.to_string_lossy());
-                continue
-            }
-
-            let sub_directory = add(path);
-            entries.extend(sub_directory);
-        }
-    } else {
-        let hash = write_blob(&path);
-        match hash {
-            Ok(hash) => {
-                let path  = remove_leading_dot_slash(path);
-                let filename = path.to_string_lossy();
-                let entry = IndexEntry::create(hash, &filename);
-                entries.push(entry);
-            },
-            Err(err) => println!("[ERROR]: Unable to write blob {:?}: {}", path, err)
-        }
-    }
-    entries
-}
-
-fn write_blob(file: &PathBuf) -> Result<Hash, io::Error> {
-    let content = fs::read(file)?;
-    write_object(ObjectKind::Blob, content)
-}
-
-fn write_tree(tree: Vec<u8>) -> Result<Hash, io::Error> {
-    write_object(ObjectKind::Tree, tree)
-}
-
-fn write_commit(commit: Commit) -> Result<Hash, io::Error> {
-    let commit_content = format!("{}", commit).into_bytes();
-    write_object(ObjectKind::Commit, commit_content)
-}
-
-fn write_index(index: Index) -> Result<(), io::Error> {
-    let index_bytes = index.to_bytes();
-    let mut index = File::create(String::from(INDEX_FILE))?;
-    index.write_all(&index_bytes)
-}
-
-fn write_cache(cache: TreeCache) -> Result<Hash, io::Error> {
-    let mut trees_as_bytes: Vec<(String, Vec<u8>)> = Vec::new();
-
-    for blob in cache.blobs {
-        let name = format!("{}", blob.name.to_string_lossy());
-        trees_as_bytes.push((name, blob.as_bytes()));
-    }
-
-    for (dir, cache) in cache.trees {
-        let hash = write_cache(cache).unwrap_or_else(|err|
-            panic!("[ERROR]: Unable to create tree for '{}': {}", dir.to_string_lossy(), err)
-        );
-
-        let mut bytes = format!(
-            "{:06} {}\0",
-            ObjectKind::Tree as i32,
-            dir.to_string_lossy(),
-        ).into_bytes();
-        bytes.extend_from_slice(&hash.0);
-
-        let name = format!("{}/", dir.to_string_lossy());
-        trees_as_bytes.push((name, bytes));
-    }
-
-    trees_as_bytes.sort_by_key(|(n,_)| n.clone());
-
-    let tree: Vec<u8> = trees_as_bytes
-        .into_iter()
-        .flat_map(|(_, t)| t)
-        .collect();
-
-    write_tree(tree)
-}
-
-fn commit(key: Hash, message: String) -> Result<Hash, io::Error> {
-    // create commit
-    let parent = get_parent();
-    let author = get_author();
-    let committer = get_author();
-    let commit = Commit::create(key, Some(parent), author, committer, message);
-
-    // write commit
-    write_commit(commit)
-}
-
-fn update_refs(commit: Hash) -> Result<(), io::Error> {
-    let path = format!("{}/refs/heads/{}", ROOT, BRANCH);
-    let content = format!("{}", commit).into_bytes();
-    write_to_file(path, content)
-}
 
 fn usage() {
-    println!("USAGE: nit <command> <args>");
+    println!("USAGE: nit [-C <path>] [--git-dir=<path>] [--work-tree=<path>] <command> <args>");
     println!("command:");
+    println!("   init    [--bare] [--initial-branch=<name>] [<directory>]");
     println!("   add     <file|dir>");
-    println!("   commit  <message>");
+    println!("   commit  [-m] <message> [--author=<name <email>>] [--date=<date>]");
+    println!("   branch  [--list | --show-current] | [-f] <name> [<start>] | (-m | -M) [<old>] <new>");
+    println!("           | (-d | -D) <name>...");
+    println!("   checkout [-f] [-b <new>] <branch|commit> | [<commit>] -- <path>...");
+    println!("   switch  [-f] [-c <new>] [--detach] <branch|commit>");
+    println!("   cat-file (-t | -s | -p | -e) <object>");
+    println!("   log     [--oneline | --format=<format>] [--date=<format>] [-n <count>] [--since=<date>] [--until=<date>]");
+    println!("           [--author=<pattern>] [--date-order | --topo-order] [<revision>...] [-- <path>...]");
+    println!("   config  [--system | --global | --local | --worktree | --file <file>] [--bool | --int]");
+    println!("           [--get | --get-all] <key> | [--set | --add] <key> <value> | --unset[-all] <key> | --list");
+    println!("   diff    [--cached] [-U<n>] [--stat | --numstat | --name-only | --name-status]");
+    println!("           [<commit> [<commit>]] [-- <path>...]");
+    println!("   check-ignore [-v [-n]] <path>...");
+    println!("   fsck");
+    println!("   ls-files [--stage] [--debug]");
+    println!("   update-index [--index-version <n>]");
+    println!("   gc");
+    println!("   repack  [-a | -A] [-d] [--window=<n>] [--depth=<n>]");
+    println!("   status  [--short | --porcelain[=v1|v2]] [--branch]");
+    println!("   verify-pack [-v] <pack>.idx...");
+    println!("   tag     [-l [<pattern>...]] | [-a] [-f] [-m <message>] <name> [<commit>] | -d <name>...");
+}
+
+/* Reports an error and exits with its exit code */
+fn fail(err: Error) -> ! {
+    eprintln!("ERROR: {}", err);
+    exit(err.exit_code())
 }
 
 fn main() {
 
     let args: Vec<String> = env::args().collect();
-    let command = match Command::parse(args) {
-        Ok(command) => {
-            println!("[INFO]: Executing command: '{:?}'", command);
-            command
-        },
+    let parsed = GlobalOptions::parse(args)
+        .and_then(|(global, args)| Ok((global, Command::parse(args)?)));
+    let (global, command) = match parsed {
+        Ok(parsed) => parsed,
         Err(err) => {
             eprintln!("ERROR: {}", err);
             usage();
-            exit(1);
+            exit(129);
         }
     };
 
+    for directory in &global.directories {
+        if let Err(err) = env::set_current_dir(directory) {
+            fail(err.into())
+        }
+    }
+
+    /* The global options take precedence over GIT_DIR and GIT_WORK_TREE */
+    let mut discover = DiscoverOptions::from_env();
+    if global.git_dir.is_some() {
+        discover.git_dir = global.git_dir;
+    }
+    if global.work_tree.is_some() {
+        discover.work_tree = global.work_tree;
+    }
+
+    /* Only init and config work outside of a repository */
+    let repository = Repository::discover_with(".", &discover);
+    let needs_repository = !matches!(command, Command::Init { .. } | Command::Config(_) | Command::VerifyPack { .. });
+    let repository = match repository {
+        Ok(repository) => Some(repository),
+        Err(err) if needs_repository => fail(err),
+        Err(_) => None
+    };
+    let repo = || repository.as_ref().expect("the repository is opened");
+
     match command {
+        Command::Init { directory, initial_branch, bare } => {
+            /* == Git init == */
+            let reinit = directory.join(if bare { "HEAD" } else { ".git/HEAD" }).exists();
+            let repository = Repository::init(&directory, &initial_branch, bare).unwrap_or_else(|err| fail(err));
+            let git_dir = repository.git_dir().canonicalize().unwrap_or(repository.git_dir().to_path_buf());
+            if reinit {
+                println!("Reinitialized existing Git repository in {}/", git_dir.display());
+            } else {
+                println!("Initialized empty Git repository in {}/", git_dir.display());
+            }
+        },
         Command::Add(path_buf) => {
             /* == Git add == */
-            // 1. create objects
-            let new_entries = add(path_buf);
-            let index = Index::read(INDEX_FILE);
-            let updated_index = index.extend(new_entries);
-
-            // 2. write to index
-            write_index(updated_index).unwrap();
+            if let Err(err) = add(repo(), &path_buf) {
+                fail(err)
+            }
         },
-        Command::Commit(message) => {
+        Command::Commit(options) => {
             /* == Git commit == */
-            // 0. read staging area (index)
-            let index = Index::read(INDEX_FILE);
-
-            // 1. write-tree
-            let cache = TreeCache::from_index(index);
-            let tree_hash = write_cache(cache).unwrap();
-
-            // 2. write to commit
-            let commit_hash = commit(tree_hash, message).unwrap();
-
-            // 3. update refs
-            update_refs(commit_hash).unwrap();
+            if let Err(err) = commit(repo(), options) {
+                fail(err)
+            }
+        },
+        Command::CatFile { mode, object } => {
+            /* == Git cat-file == */
+            let exists = matches!(mode, CatFileMode::Exists);
+            if let Err(err) = cat_file(repo(), mode, &object) {
+                if !exists {
+                    fail(err);
+                }
+                exit(1);
+            }
+        },
+        Command::Tag(action) => {
+            /* == Git tag == */
+            if let Err(err) = tag(repo(), action) {
+                fail(err)
+            }
+        },
+        Command::Branch(action) => {
+            /* == Git branch == */
+            if let Err(err) = branch(repo(), action) {
+                fail(err)
+            }
+        },
+        Command::Checkout(options) => {
+            /* == Git checkout == */
+            if let Err(err) = checkout(repo(), options) {
+                fail(err)
+            }
+        },
+        Command::Switch(options) => {
+            /* == Git switch == */
+            if let Err(err) = switch(repo(), options) {
+                fail(err)
+            }
+        },
+        Command::Log(options) => {
+            /* == Git log == */
+            if let Err(err) = log(repo(), options) {
+                fail(err)
+            }
+        },
+        Command::Status { format, branch } => {
+            /* == Git status == */
+            if let Err(err) = print_status(repo(), format, branch) {
+                fail(err)
+            }
+        },
+        Command::Config(options) => {
+            /* == Git config == */
+            match config(repository.as_ref(), options) {
+                Ok(true) => {},
+                /* Like git, a missing variable is only shown by the exit code */
+                Ok(false) => exit(1),
+                Err(err) => fail(err)
+            }
+        },
+        Command::Diff(options) => {
+            /* == Git diff == */
+            if let Err(err) = diff(repo(), options) {
+                fail(err)
+            }
+        },
+        Command::CheckIgnore { verbose, non_matching, paths } => {
+            /* == Git check-ignore == */
+            match check_ignore(repo(), &paths, verbose, non_matching) {
+                Ok(true) => {},
+                /* Like git, exit with 1 when no path is ignored */
+                Ok(false) => exit(1),
+                Err(err) => fail(err)
+            }
+        },
+        Command::Gc => {
+            /* == Git gc == */
+            if let Err(err) = gc(repo()) {
+                fail(err)
+            }
+        },
+        Command::Repack(options) => {
+            /* == Git repack == */
+            if let Err(err) = repack(repo(), &options) {
+                fail(err)
+            }
+        },
+        Command::VerifyPack { verbose, paths } => {
+            /* == Git verify-pack == */
+            for path in paths {
+                if let Err(err) = verify_pack(&path, verbose) {
+                    fail(err)
+                }
+            }
+        },
+        Command::Fsck => {
+            /* == Git fsck == */
+            match fsck(repo()) {
+                Ok(true) => {},
+                Ok(false) => exit(1),
+                Err(err) => fail(err)
+            }
+        },
+        Command::LsFiles { stage, debug } => {
+            /* == Git ls-files == */
+            if let Err(err) = ls_files(repo(), stage, debug) {
+                fail(err)
+            }
+        },
+        Command::UpdateIndex { index_version } => {
+            /* == Git update-index == */
+            if let Err(err) = update_index(repo(), index_version) {
+                fail(err)
+            }
         }
     };
 }
@@ -1,81 +1,313 @@
+use crate::error::*;
 use crate::hash::*;
-use crate::ROOT;
+use crate::repository::*;
 use crate::compress::*;
 
 use std::fs;
 use std::io;
 
 use std::str::FromStr;
-use std::path::Path;
+use std::path::PathBuf;
+use std::collections::BTreeSet;
 
-#[derive(Debug, Copy, Clone)]
-pub enum ObjectKind {
-    Blob = 100644,
-    Tree = 40000,
-    Commit = 0
+/* The type of an object, as named in its header */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ObjectType {
+    Blob,
+    Tree,
+    Commit,
+    Tag
 }
 
-impl FromStr for ObjectKind {
-    type Err = ();
+/*
+ * The mode of a tree or index entry. Git only writes these five modes:
+ * a regular file (100644), an executable (100755), a symlink (120000),
+ * a directory (40000, a tree) and a submodule commit (160000, a gitlink).
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FileMode {
+    Regular,
+    Executable,
+    Symlink,
+    Tree,
+    Gitlink
+}
+
+impl FileMode {
+    /* The mode for the unix mode bits, None unless they are one of the five canonical modes */
+    pub fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            0o100644 => Some(FileMode::Regular),
+            0o100755 => Some(FileMode::Executable),
+            0o120000 => Some(FileMode::Symlink),
+            0o40000 => Some(FileMode::Tree),
+            0o160000 => Some(FileMode::Gitlink),
+            _ => None
+        }
+    }
+
+    pub fn bits(self) -> u32 {
+        match self {
+            FileMode::Regular => 0o100644,
+            FileMode::Executable => 0o100755,
+            FileMode::Symlink => 0o120000,
+            FileMode::Tree => 0o40000,
+            FileMode::Gitlink => 0o160000,
+        }
+    }
+
+    /* The type of the object an entry with this mode points to */
+    pub fn object_type(self) -> ObjectType {
+        match self {
+            FileMode::Tree => ObjectType::Tree,
+            FileMode::Gitlink => ObjectType::Commit,
+            _ => ObjectType::Blob
+        }
+    }
+}
+
+/*
+ * Parses a mode as written in a tree object. Non-canonical modes, such as
+ * the 100664 of very old git versions or a zero-padded "040000", are
+ * rejected rather than silently rewritten.
+ */
+impl FromStr for FileMode {
+    type Err = Error;
     fn from_str(input: &str) -> Result<Self, Self::Err> {
-        match input {
-            "100644" => Ok(ObjectKind::Blob),
-            "40000"  => Ok(ObjectKind::Tree),
-            _ => panic!("ERROR: Invalid object mode: {}", input)
+        let invalid = || corrupt(format!("Invalid tree entry mode '{}'", input));
+        if input.starts_with('0') {
+            return Err(invalid())
         }
+        u32::from_str_radix(input, 8)
+            .ok()
+            .and_then(FileMode::from_bits)
+            .ok_or_else(invalid)
     }
+}
 
+/* Modes are shown in octal, e.g. `{:06o}` for "040000" */
+impl std::fmt::Octal for FileMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Octal::fmt(&self.bits(), f)
+    }
 }
 
-impl std::fmt::Display for ObjectKind {
+impl ObjectType {
+    pub fn from_name(name: &str) -> Result<Self, Error> {
+        match name {
+            "blob"   => Ok(ObjectType::Blob),
+            "tree"   => Ok(ObjectType::Tree),
+            "commit" => Ok(ObjectType::Commit),
+            "tag"    => Ok(ObjectType::Tag),
+            _ => Err(corrupt(format!("Unknown object type '{}'", name)))
+        }
+    }
+}
+
+impl std::fmt::Display for ObjectType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ObjectKind::Blob => write!(f, "blob"),
-            ObjectKind::Tree => write!(f, "tree"),
-            ObjectKind::Commit => write!(f, "commit"),
+            ObjectType::Blob => write!(f, "blob"),
+            ObjectType::Tree => write!(f, "tree"),
+            ObjectType::Commit => write!(f, "commit"),
+            ObjectType::Tag => write!(f, "tag"),
         }
     }
 }
 
-pub fn hash_object(object_type: ObjectKind, content: Vec<u8>) -> Hash {
-    let header = format!("{} {}\0", object_type, content.len());
-    Hash::from_bytes(header, content)
+#[derive(Debug)]
+pub struct Object {
+    pub kind: ObjectType,
+    pub content: Vec<u8>,
 }
 
-pub fn write_object(object_type: ObjectKind, content: Vec<u8>) -> Result<Hash, io::Error> {
-    let hash = hash_object(object_type, content.clone());
+#[derive(Debug, Clone)]
+pub struct Blob {
+    pub content: Vec<u8>,
+}
 
-    let path_str = format!("{}/{}", ROOT, hash.to_object_path());
-    let path = Path::new(&path_str);
-    if path.exists() {
-        println!("[INFO] {} {} already exists", object_type, hash);
-        return Ok(hash)
+impl Blob {
+    pub fn parse(content: &[u8]) -> Blob {
+        Blob { content: content.to_vec() }
     }
 
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)?;
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.content.clone()
     }
+}
 
+pub fn corrupt(message: String) -> Error {
+    Error::CorruptObject(message)
+}
+
+pub fn hash_object(object_type: ObjectType, content: &[u8]) -> Hash {
     let header = format!("{} {}\0", object_type, content.len());
-    let compressed = compress_content(header, content)?;
-    fs::write(path, compressed)?;
+    Hash::from_bytes(header.as_bytes(), content)
+}
+
+pub fn parse_object(bytes: &[u8]) -> Result<Object, Error> {
+    let pos = bytes.iter()
+        .position(|&x| x == 0)
+        .ok_or(corrupt("Missing object header".into()))?;
+    let (header, rest) = bytes.split_at(pos);
+    let header = str::from_utf8(header)
+        .map_err(|_| corrupt("Object header is not valid UTF-8".into()))?;
 
-    println!("[INFO] {} {} created", object_type, hash);
+    let (kind, size) = header.split_once(' ')
+        .ok_or(corrupt(format!("Malformed object header '{}'", header)))?;
+    let kind = ObjectType::from_name(kind)?;
+    let size: usize = size.parse()
+        .map_err(|_| corrupt(format!("Invalid object size '{}'", size)))?;
 
-    Ok(hash)
+    let content = rest[1..].to_vec();
+    if content.len() != size {
+        return Err(corrupt(format!(
+            "Object size {} does not match header size {}", content.len(), size
+        )))
+    }
+
+    Ok(Object { kind, content })
 }
 
+impl Repository {
+    pub fn object_path(&self, hash: &Hash) -> PathBuf {
+        self.git_path(&hash.to_object_path())
+    }
+
+    /* Whether the object is stored, either loose or in a pack */
+    pub fn has_object(&self, hash: &Hash) -> Result<bool, Error> {
+        Ok(self.object_path(hash).exists() || self.packs()?.iter().any(|pack| pack.contains(hash)))
+    }
+
+    /*
+     * Reads the loose object `.git/objects/xx/yyyy...`, where xx is the first
+     * byte of the hash, or else the object from a pack. The inflated content
+     * of a loose object is "<kind> <size>\0<content>".
+     */
+    pub fn read_object(&self, hash: &Hash) -> Result<Object, Error> {
+        let compressed = match fs::read(self.object_path(hash)) {
+            Ok(compressed) => compressed,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return self.read_packed_object(hash)?.ok_or_else(|| Error::MissingObject(hash.clone()))
+            },
+            Err(err) => return Err(err.into())
+        };
+        let bytes = decompress(compressed)
+            .map_err(|err| corrupt(format!("Object {} is corrupt: {}", hash, err)))?;
+        parse_object(&bytes)
+            .map_err(|err| corrupt(format!("Object {} is corrupt: {}", hash, err)))
+    }
+
+    pub fn read_object_of_kind(&self, hash: &Hash, kind: ObjectType) -> Result<Object, Error> {
+        let object = self.read_object(hash)?;
+        if object.kind != kind {
+            return Err(corrupt(format!("Object {} is a {}, not a {}", hash, object.kind, kind)))
+        }
+        Ok(object)
+    }
+
+    /*
+     * Finds the object whose hex encoded name starts with `prefix`.
+     * The prefix must be at least 4 characters and identify a single object.
+     */
+    pub fn find_object(&self, prefix: &str) -> Result<Hash, Error> {
+        let not_found = || Error::BadRef(format!("Not a valid object name {}", prefix));
+
+        let prefix = prefix.to_lowercase();
+        if prefix.len() < 4 || prefix.len() > 40 || !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(not_found())
+        }
+        if prefix.len() == 40 {
+            return Hash::from_hex(&prefix)
+        }
+
+        let (dir, rest) = prefix.split_at(2);
+        let entries = match fs::read_dir(self.git_path("objects").join(dir)) {
+            Ok(entries) => entries.collect::<Result<Vec<_>, io::Error>>()?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into())
+        };
+
+        /* An object may be both loose and packed */
+        let mut matches = BTreeSet::new();
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.len() == 38 && name.starts_with(rest) {
+                matches.insert(Hash::from_hex(&format!("{}{}", dir, name))?);
+            }
+        }
+        for pack in self.packs()?.iter() {
+            matches.extend(pack.index.find_prefix(&prefix));
+        }
+
+        match Vec::from_iter(matches).as_slice() {
+            [hash] => Ok(hash.clone()),
+            [] => Err(not_found()),
+            _ => Err(Error::BadRef(format!("Short object name {} is ambiguous", prefix)))
+        }
+    }
+
+    pub fn write_object(&self, object_type: ObjectType, content: Vec<u8>) -> Result<Hash, Error> {
+        let hash = hash_object(object_type, &content);
+        if !self.has_object(&hash)? {
+            self.write_loose_object(&hash, object_type, content)?;
+        }
+        Ok(hash)
+    }
+
+    /* Writes the object as a loose file, whether or not it is packed */
+    pub fn write_loose_object(&self, hash: &Hash, object_type: ObjectType, content: Vec<u8>) -> Result<(), Error> {
+        let path = self.object_path(hash);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let header = format!("{} {}\0", object_type, content.len());
+        let compressed = compress_content(header, content)?;
+        Ok(fs::write(path, compressed)?)
+    }
+
+    /* The names of all loose objects, i.e. the files in .git/objects/xx/ */
+    pub fn loose_objects(&self) -> Result<Vec<Hash>, Error> {
+        let mut hashes = Vec::new();
+        for dir in fs::read_dir(self.git_path("objects"))? {
+            let dir = dir?;
+            let prefix = dir.file_name().to_string_lossy().to_string();
+            if prefix.len() != 2 || !dir.file_type()?.is_dir() {
+                continue
+            }
+            for entry in fs::read_dir(dir.path())? {
+                let name = entry?.file_name().to_string_lossy().to_string();
+                if let Ok(hash) = Hash::from_hex(&format!("{}{}", prefix, name)) {
+                    hashes.push(hash);
+                }
+            }
+        }
+        hashes.sort();
+        Ok(hashes)
+    }
+
+    /* Removes a loose object, and its directory if that is left empty */
+    pub fn remove_loose_object(&self, hash: &Hash) -> Result<(), Error> {
+        let path = self.object_path(hash);
+        fs::remove_file(&path)?;
+        if let Some(dir) = path.parent() {
+            let _ = fs::remove_dir(dir);
+        }
+        Ok(())
+    }
+}
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::compress::compress_content;
+    use crate::compress::{compress_content, decompress};
 
     #[test]
     fn hash_blob_object() {
         let content = String::from("what is up, doc?").into_bytes();
 
-        let hashed = hash_object(ObjectKind::Blob, content).to_string();
+        let hashed = hash_object(ObjectType::Blob, &content).to_string();
 
         let expected = String::from("bd9dbf5aae1a3862dd1526723246b20206e5fc37");
         assert_eq!(hashed, expected);
@@ -84,11 +316,32 @@ mod test {
 
     #[test]
     fn compress_blob_object() {
-        let content = fs::read("playground/main.c").unwrap();
-        let header = format!("{} {}\0", ObjectKind::Blob, content.len());
+        let content = fs::read("examples/blob.c").unwrap();
+        let header = format!("{} {}\0", ObjectType::Blob, content.len());
         let compressed = compress_content(header, content).unwrap();
 
-        let expected = fs::read("playground/.git/objects/d2/676eb8d33f7a3c4d3b133f0dad9040b81c5082").unwrap();
+        let expected = fs::read("examples/blob").unwrap();
         assert_eq!(compressed, expected);
     }
+
+    #[test]
+    fn read_blob_object() {
+        let content = fs::read("examples/blob").unwrap();
+        let decoded = decompress(content).unwrap();
+
+        let object = parse_object(&decoded).unwrap();
+
+        let expected = fs::read("examples/blob.c").unwrap();
+        assert!(matches!(object.kind, ObjectType::Blob));
+        assert_eq!(object.content, expected);
+    }
+
+    #[test]
+    fn reject_object_with_wrong_size() {
+        let bytes = b"blob 17\0what is up, doc?";
+
+        let object = parse_object(bytes);
+
+        assert!(object.is_err());
+    }
 }
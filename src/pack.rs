@@ -0,0 +1,844 @@
+use crate::error::*;
+use crate::compress::*;
+use crate::hash::*;
+use crate::object::*;
+use crate::repository::*;
+use crate::util::*;
+
+use std::fmt;
+use std::fs;
+use std::io;
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, OnceLock, PoisonError};
+
+use flate2::Crc;
+use flate2::read::ZlibDecoder;
+use sha1::{Digest, Sha1};
+
+const IDX_MAGIC: &[u8; 4] = b"\xfftOc";
+const PACK_MAGIC: &[u8; 4] = b"PACK";
+
+/* The header of an .idx file: magic, version and the 256 entry fanout table */
+const IDX_HEADER_SIZE: usize = 8 + 256 * 4;
+/* An object name, CRC32 and 4 byte offset per object */
+const IDX_ENTRY_SIZE: usize = 20 + 4 + 4;
+
+/* Object types in a pack, 5 is reserved */
+pub const OBJ_COMMIT: u8 = 1;
+pub const OBJ_TREE: u8 = 2;
+pub const OBJ_BLOB: u8 = 3;
+pub const OBJ_TAG: u8 = 4;
+/* A delta against the object a number of bytes before it in the pack */
+pub const OBJ_OFS_DELTA: u8 = 6;
+/* A delta against the object with the given name */
+pub const OBJ_REF_DELTA: u8 = 7;
+
+pub fn pack_object_type(pack_type: u8) -> Result<ObjectType, Error> {
+    match pack_type {
+        OBJ_COMMIT => Ok(ObjectType::Commit),
+        OBJ_TREE => Ok(ObjectType::Tree),
+        OBJ_BLOB => Ok(ObjectType::Blob),
+        OBJ_TAG => Ok(ObjectType::Tag),
+        _ => Err(corrupt(format!("Invalid pack object type {}", pack_type)))
+    }
+}
+
+/*
+ * A version 2 pack index (.idx). The object names are sorted, and the
+ * fanout table holds the number of objects whose first byte is less than
+ * or equal to its position, so a lookup only searches the objects sharing
+ * the first byte.
+ */
+pub struct PackIndex {
+    fanout: Vec<u32>,
+    pub hashes: Vec<Hash>,
+    pub crcs: Vec<u32>,
+    pub offsets: Vec<u64>,
+    /* The trailing checksum of the pack this index belongs to */
+    pub pack_checksum: Hash,
+}
+
+impl PackIndex {
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < IDX_HEADER_SIZE + 40 || &bytes[..4] != IDX_MAGIC {
+            return Err(corrupt("Pack index has no version 2 header".into()))
+        }
+        let mut input = &bytes[4..];
+        let version = take_u32(&mut input);
+        if version != 2 {
+            return Err(corrupt(format!("Unsupported pack index version {}", version)))
+        }
+
+        let fanout: Vec<u32> = (0..256).map(|_| take_u32(&mut input)).collect();
+        if fanout.windows(2).any(|pair| pair[0] > pair[1]) {
+            return Err(corrupt("Pack index fanout table is not sorted".into()))
+        }
+        let count = fanout[255] as usize;
+        if input.len() < count * IDX_ENTRY_SIZE + 40 {
+            return Err(corrupt("Pack index is truncated".into()))
+        }
+
+        let hashes: Vec<Hash> = (0..count).map(|_| take_hash(&mut input)).collect();
+        let crcs = (0..count).map(|_| take_u32(&mut input)).collect();
+        let small_offsets: Vec<u32> = (0..count).map(|_| take_u32(&mut input)).collect();
+
+        /* Offsets above 2^31 are stored in a table of 8 byte offsets */
+        let large_count = small_offsets.iter().filter(|&&offset| offset & 0x8000_0000 != 0).count();
+        if input.len() != large_count * 8 + 40 {
+            return Err(corrupt("Pack index has an invalid size".into()))
+        }
+        let large_offsets: Vec<u64> = (0..large_count)
+            .map(|_| (take_u32(&mut input) as u64) << 32 | take_u32(&mut input) as u64)
+            .collect();
+        let offsets = small_offsets
+            .iter()
+            .map(|&offset| match offset & 0x8000_0000 {
+                0 => Ok(offset as u64),
+                _ => large_offsets.get((offset & 0x7fff_ffff) as usize)
+                    .copied()
+                    .ok_or_else(|| corrupt("Pack index has an invalid large offset".into()))
+            })
+            .collect::<Result<Vec<u64>, Error>>()?;
+
+        if hashes.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(corrupt("Pack index object names are not sorted".into()))
+        }
+        let pack_checksum = take_hash(&mut input);
+
+        Ok(PackIndex { fanout, hashes, crcs, offsets, pack_checksum })
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /* The objects whose name starts with `first_byte` */
+    fn range(&self, first_byte: u8) -> std::ops::Range<usize> {
+        let start = match first_byte {
+            0 => 0,
+            byte => self.fanout[byte as usize - 1] as usize
+        };
+        start..self.fanout[first_byte as usize] as usize
+    }
+
+    pub fn position(&self, hash: &Hash) -> Option<usize> {
+        let range = self.range(hash.0[0]);
+        let start = range.start;
+        self.hashes[range].binary_search(hash).ok().map(|position| start + position)
+    }
+
+    /* The offset of the object in the pack */
+    pub fn find(&self, hash: &Hash) -> Option<u64> {
+        self.position(hash).map(|position| self.offsets[position])
+    }
+
+    /* The names of the objects starting with a hex prefix of at least two characters */
+    pub fn find_prefix(&self, prefix: &str) -> Vec<Hash> {
+        let Some(first_byte) = prefix.get(..2).and_then(|byte| u8::from_str_radix(byte, 16).ok()) else {
+            return Vec::new()
+        };
+        self.hashes[self.range(first_byte)]
+            .iter()
+            .filter(|hash| hash.to_string().starts_with(prefix))
+            .cloned()
+            .collect()
+    }
+}
+
+/*
+ * Reads the type and inflated size of the pack entry at `offset`, returning
+ * where its data starts. The first byte holds the type in bits 4-6 and the
+ * low 4 bits of the size, every following byte 7 more bits of the size
+ * while the high bit is set.
+ */
+pub fn read_entry_header(data: &[u8], offset: usize) -> Result<(u8, usize, usize), Error> {
+    let truncated = || corrupt(format!("Pack entry at {} is truncated", offset));
+
+    let mut position = offset;
+    let mut byte = *data.get(position).ok_or_else(truncated)?;
+    let pack_type = (byte >> 4) & 0x7;
+    let mut size = (byte & 0x0f) as usize;
+    let mut shift = 4;
+    while byte & 0x80 != 0 {
+        position += 1;
+        byte = *data.get(position).ok_or_else(truncated)?;
+        if shift > 60 {
+            return Err(corrupt(format!("Pack entry at {} has an invalid size", offset)))
+        }
+        size |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+    }
+    Ok((pack_type, size, position + 1))
+}
+
+/* Reads the distance back to the base of an OFS_DELTA entry */
+fn read_delta_offset(data: &[u8], position: usize) -> Result<(usize, usize), Error> {
+    decode_varint(data, position)
+        .ok_or_else(|| corrupt(format!("Delta base offset at {} is truncated or overflows", position)))
+}
+
+/* A size in a delta header: 7 bits per byte, least significant first */
+fn read_delta_size(delta: &[u8], position: &mut usize) -> Result<usize, Error> {
+    let mut size = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *delta.get(*position).ok_or_else(|| corrupt("Delta header is truncated".into()))?;
+        *position += 1;
+        if shift > 60 {
+            return Err(corrupt("Delta header has an invalid size".into()))
+        }
+        size |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(size)
+        }
+    }
+}
+
+/*
+ * Rebuilds an object from its delta base. A delta starts with the sizes of
+ * the base and result, followed by instructions that either copy a range
+ * of the base (high bit set, the low bits telling which offset and size
+ * bytes follow) or insert the next 1-127 bytes of the delta.
+ */
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut position = 0;
+    let base_size = read_delta_size(delta, &mut position)?;
+    let result_size = read_delta_size(delta, &mut position)?;
+    if base_size != base.len() {
+        return Err(corrupt(format!("Delta base is {} bytes, expected {}", base.len(), base_size)))
+    }
+
+    let truncated = || corrupt("Delta is truncated".into());
+    let mut result = Vec::with_capacity(result_size);
+    while position < delta.len() {
+        let instruction = delta[position];
+        position += 1;
+
+        if instruction & 0x80 != 0 {
+            let mut argument = |bits: std::ops::Range<u8>| -> Result<usize, Error> {
+                let mut value = 0;
+                for (index, bit) in bits.enumerate() {
+                    if instruction & (1 << bit) != 0 {
+                        let byte = *delta.get(position).ok_or_else(truncated)?;
+                        value |= (byte as usize) << (8 * index);
+                        position += 1;
+                    }
+                }
+                Ok(value)
+            };
+            let offset = argument(0..4)?;
+            let size = match argument(4..7)? {
+                0 => 0x10000,
+                size => size
+            };
+            let copied = offset.checked_add(size)
+                .and_then(|end| base.get(offset..end))
+                .ok_or_else(|| corrupt("Delta copies from outside of its base".into()))?;
+            result.extend_from_slice(copied);
+        } else if instruction != 0 {
+            let end = position + instruction as usize;
+            result.extend_from_slice(delta.get(position..end).ok_or_else(truncated)?);
+            position = end;
+        } else {
+            return Err(corrupt("Delta has a reserved instruction".into()))
+        }
+    }
+
+    if result.len() != result_size {
+        return Err(corrupt(format!("Delta result is {} bytes, expected {}", result.len(), result_size)))
+    }
+    Ok(result)
+}
+
+/* Inflates a zlib stream of `size` bytes starting at `start`, ignoring what follows it */
+fn inflate(data: &[u8], start: usize, size: usize) -> Result<Vec<u8>, Error> {
+    let stream = data.get(start..).ok_or_else(|| corrupt(format!("Pack entry at {} is truncated", start)))?;
+    let mut content = Vec::with_capacity(size);
+    ZlibDecoder::new(stream)
+        .take(size as u64 + 1)
+        .read_to_end(&mut content)
+        .map_err(|err| corrupt(format!("Pack entry at {} is corrupt: {}", start, err)))?;
+    if content.len() != size {
+        return Err(corrupt(format!("Pack entry at {} is {} bytes, expected {}", start, content.len(), size)))
+    }
+    Ok(content)
+}
+
+/*
+ * A packfile and its index. The index is read when the pack is opened, the
+ * pack itself only once an object is read from it.
+ */
+pub struct Pack {
+    pub path: PathBuf,
+    pub index: PackIndex,
+    data: OnceLock<Vec<u8>>,
+}
+
+impl fmt::Debug for Pack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Pack({}, {} objects)", self.path.display(), self.index.len())
+    }
+}
+
+impl Pack {
+    /* Opens the pack belonging to the index at `idx_path`, e.g. "pack-<hash>.idx" */
+    pub fn open(idx_path: &Path) -> Result<Self, Error> {
+        let index = PackIndex::parse(&fs::read(idx_path)?)
+            .map_err(|err| corrupt(format!("{}: {}", idx_path.display(), err)))?;
+        Ok(Pack { path: idx_path.with_extension("pack"), index, data: OnceLock::new() })
+    }
+
+    /*
+     * The content of the .pack file: "PACK", the version, the number of
+     * objects, the entries and a trailing SHA-1 checksum, which must be the
+     * one recorded in the index.
+     */
+    pub fn data(&self) -> Result<&[u8], Error> {
+        if let Some(data) = self.data.get() {
+            return Ok(data)
+        }
+
+        let data = fs::read(&self.path)?;
+        let invalid = |message: &str| corrupt(format!("{}: {}", self.path.display(), message));
+        if data.len() < 32 || &data[..4] != PACK_MAGIC {
+            return Err(invalid("not a pack file"))
+        }
+        let mut header = &data[4..12];
+        let version = take_u32(&mut header);
+        if version != 2 && version != 3 {
+            return Err(invalid(&format!("unsupported pack version {}", version)))
+        }
+        if take_u32(&mut header) as usize != self.index.len() {
+            return Err(invalid("object count does not match its index"))
+        }
+        if data[data.len() - 20..] != self.index.pack_checksum.0 {
+            return Err(invalid("checksum does not match its index"))
+        }
+
+        Ok(self.data.get_or_init(|| data))
+    }
+
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.index.position(hash).is_some()
+    }
+
+    pub fn read_object(&self, hash: &Hash) -> Result<Option<Object>, Error> {
+        match self.index.find(hash) {
+            Some(offset) => self.read_at(offset).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    /*
+     * Reads the object at `offset`, following its chain of deltas down to
+     * a whole object and applying them back up again.
+     */
+    pub fn read_at(&self, offset: u64) -> Result<Object, Error> {
+        let data = self.data()?;
+        let mut deltas = Vec::new();
+        let mut offset = offset as usize;
+
+        let (kind, mut content) = loop {
+            if deltas.len() > self.index.len() {
+                return Err(corrupt(format!("{}: delta chain loops", self.path.display())))
+            }
+
+            let (pack_type, size, start) = read_entry_header(data, offset)?;
+            match pack_type {
+                OBJ_OFS_DELTA => {
+                    let (distance, start) = read_delta_offset(data, start)?;
+                    deltas.push(inflate(data, start, size)?);
+                    offset = offset.checked_sub(distance)
+                        .filter(|_| distance > 0)
+                        .ok_or_else(|| corrupt(format!("Delta at {} has an invalid base offset", offset)))?;
+                },
+                OBJ_REF_DELTA => {
+                    let base = data.get(start..start + 20)
+                        .map(|mut bytes| take_hash(&mut bytes))
+                        .ok_or_else(|| corrupt(format!("Delta at {} is truncated", offset)))?;
+                    deltas.push(inflate(data, start + 20, size)?);
+                    /* Packs on disk are never thin, the base is in the same pack */
+                    offset = self.index.find(&base).ok_or(Error::MissingObject(base))? as usize;
+                },
+                pack_type => break (pack_object_type(pack_type)?, inflate(data, start, size)?)
+            }
+        };
+
+        for delta in deltas.iter().rev() {
+            content = apply_delta(&content, delta)?;
+        }
+        Ok(Object { kind, content })
+    }
+}
+
+/* An entry of a pack as stored, before resolving deltas */
+#[derive(Debug)]
+pub struct PackEntry {
+    pub pack_type: u8,
+    /* The inflated size of the object, or of the delta for a delta */
+    pub size: usize,
+    /* The offset of the delta base */
+    pub base: Option<u64>,
+}
+
+impl Pack {
+    /* Reads the header of the entry at `offset` and finds its delta base */
+    pub fn read_entry(&self, offset: u64) -> Result<PackEntry, Error> {
+        let data = self.data()?;
+        let (pack_type, size, start) = read_entry_header(data, offset as usize)?;
+        let base = match pack_type {
+            OBJ_OFS_DELTA => {
+                let (distance, _) = read_delta_offset(data, start)?;
+                let base = (offset as usize).checked_sub(distance)
+                    .filter(|_| distance > 0)
+                    .ok_or_else(|| corrupt(format!("Delta at {} has an invalid base offset", offset)))?;
+                Some(base as u64)
+            },
+            OBJ_REF_DELTA => {
+                let base = data.get(start..start + 20)
+                    .map(|mut bytes| take_hash(&mut bytes))
+                    .ok_or_else(|| corrupt(format!("Delta at {} is truncated", offset)))?;
+                Some(self.index.find(&base).ok_or(Error::MissingObject(base))?)
+            },
+            _ => None
+        };
+        Ok(PackEntry { pack_type, size, base })
+    }
+
+    /*
+     * Checks the trailing checksums of the pack and its index, and the
+     * CRC32 the index records for every entry. An entry runs up to the
+     * next one, the last one up to the pack's checksum.
+     */
+    pub fn verify(&self) -> Result<(), Error> {
+        let invalid = |path: &Path, message: &str| corrupt(format!("{}: {}", path.display(), message));
+
+        let idx_path = self.path.with_extension("idx");
+        let idx = fs::read(&idx_path)?;
+        let (content, checksum) = idx.split_at(idx.len() - 20);
+        if Sha1::digest(content)[..] != *checksum {
+            return Err(invalid(&idx_path, "index checksum mismatch"))
+        }
+
+        let data = self.data()?;
+        let end = data.len() - 20;
+        if Sha1::digest(&data[..end])[..] != data[end..] {
+            return Err(invalid(&self.path, "pack checksum mismatch"))
+        }
+
+        let mut entries: Vec<(u64, usize)> = self.index.offsets.iter().copied().zip(0..).collect();
+        entries.sort();
+        for (position, &(offset, index)) in entries.iter().enumerate() {
+            let next = entries.get(position + 1).map_or(end, |&(offset, _)| offset as usize);
+            let entry = data.get(offset as usize..next)
+                .filter(|entry| !entry.is_empty())
+                .ok_or_else(|| invalid(&self.path, &format!("invalid offset {} in index", offset)))?;
+            let mut crc = Crc::new();
+            crc.update(entry);
+            if crc.sum() != self.index.crcs[index] {
+                return Err(invalid(&self.path, &format!("CRC mismatch for object {}", self.index.hashes[index])))
+            }
+        }
+        Ok(())
+    }
+}
+
+/* The length of the blocks of a delta base that are looked up in the target */
+const DELTA_BLOCK: usize = 16;
+/* The most a single copy instruction copies, as understood by every git version */
+const MAX_COPY: usize = 0x10000;
+
+/* The number of objects of the same type a delta base is searched among */
+pub const DEFAULT_WINDOW: usize = 10;
+/* The longest chain of deltas to reach an object */
+pub const DEFAULT_DEPTH: usize = 50;
+
+fn push_delta_size(delta: &mut Vec<u8>, mut size: usize) {
+    while size >= 0x80 {
+        delta.push(0x80 | (size & 0x7f) as u8);
+        size >>= 7;
+    }
+    delta.push(size as u8);
+}
+
+fn push_insert(delta: &mut Vec<u8>, bytes: &[u8]) {
+    for chunk in bytes.chunks(0x7f) {
+        delta.push(chunk.len() as u8);
+        delta.extend_from_slice(chunk);
+    }
+}
+
+/* A copy instruction only carries the non-zero bytes of its offset and size */
+fn push_copy(delta: &mut Vec<u8>, mut offset: usize, mut size: usize) {
+    while size > 0 {
+        let length = size.min(MAX_COPY);
+        let mut instruction = 0x80;
+        let mut arguments = Vec::new();
+        for (bit, byte) in (0..4).map(|index| (index, (offset >> (8 * index)) as u8)) {
+            if byte != 0 {
+                instruction |= 1 << bit;
+                arguments.push(byte);
+            }
+        }
+        /* A size of 0x10000 is written as 0 */
+        let encoded = if length == MAX_COPY { 0 } else { length };
+        for (bit, byte) in (0..3).map(|index| (index + 4, (encoded >> (8 * index)) as u8)) {
+            if byte != 0 {
+                instruction |= 1 << bit;
+                arguments.push(byte);
+            }
+        }
+        delta.push(instruction);
+        delta.extend(arguments);
+        offset += length;
+        size -= length;
+    }
+}
+
+/*
+ * Encodes `target` as a delta against `base`, or None if the delta would
+ * be larger than `max_size`. Every block of the base is indexed, then the
+ * target is scanned for blocks found in the base, which are extended in
+ * both directions and copied, while the bytes in between are inserted.
+ */
+pub fn create_delta(base: &[u8], target: &[u8], max_size: usize) -> Option<Vec<u8>> {
+    let mut blocks: HashMap<&[u8], usize> = HashMap::new();
+    for (index, block) in base.chunks_exact(DELTA_BLOCK).enumerate() {
+        blocks.entry(block).or_insert(index * DELTA_BLOCK);
+    }
+
+    let mut delta = Vec::new();
+    push_delta_size(&mut delta, base.len());
+    push_delta_size(&mut delta, target.len());
+
+    let mut inserted = 0;
+    let mut position = 0;
+    while position + DELTA_BLOCK <= target.len() {
+        let Some(&start) = blocks.get(&target[position..position + DELTA_BLOCK]) else {
+            position += 1;
+            continue
+        };
+
+        let mut length = DELTA_BLOCK;
+        while start + length < base.len()
+            && position + length < target.len()
+            && base[start + length] == target[position + length] {
+            length += 1;
+        }
+        let mut before = 0;
+        while before < position - inserted
+            && before < start
+            && base[start - before - 1] == target[position - before - 1] {
+            before += 1;
+        }
+
+        push_insert(&mut delta, &target[inserted..position - before]);
+        push_copy(&mut delta, start - before, length + before);
+        position += length;
+        inserted = position;
+        if delta.len() > max_size {
+            return None
+        }
+    }
+    push_insert(&mut delta, &target[inserted..]);
+
+    (delta.len() <= max_size).then_some(delta)
+}
+
+/*
+ * Git's hash of the path an object was found at. The last characters
+ * count the most, so files with the same name or extension sort next to
+ * each other and are tried as delta bases for one another.
+ */
+pub fn name_hash(name: &str) -> u32 {
+    name.bytes()
+        .filter(|byte| !byte.is_ascii_whitespace())
+        .fold(0u32, |hash, byte| (hash >> 2).wrapping_add((byte as u32) << 24))
+}
+
+/* An object to pack, with its size and the hash of the path it was reached by */
+pub struct PackObject {
+    pub hash: Hash,
+    pub kind: ObjectType,
+    pub size: usize,
+    pub name_hash: u32,
+}
+
+fn entry_header(pack_type: u8, mut size: usize) -> Vec<u8> {
+    let mut header = Vec::new();
+    let mut byte = (pack_type << 4) | (size & 0x0f) as u8;
+    size >>= 4;
+    while size > 0 {
+        header.push(byte | 0x80);
+        byte = (size & 0x7f) as u8;
+        size >>= 7;
+    }
+    header.push(byte);
+    header
+}
+
+fn pack_type(kind: ObjectType) -> u8 {
+    match kind {
+        ObjectType::Commit => OBJ_COMMIT,
+        ObjectType::Tree => OBJ_TREE,
+        ObjectType::Blob => OBJ_BLOB,
+        ObjectType::Tag => OBJ_TAG,
+    }
+}
+
+/*
+ * Writes the objects as a pack and its v2 index to `dir`, named after the
+ * pack's checksum ("pack-<checksum>.pack"), and returns the checksum and
+ * the number of deltas. Deltas refer to their base by offset.
+ *
+ * Contents are read with `read` as the objects are written, so only the
+ * last `window` of them are held at once. Like git, objects are sorted by
+ * type, name hash and then size, largest first, and each object is
+ * compared with the `window` objects of the same type before it.
+ */
+pub fn write_pack<F>(dir: &Path, objects: &[PackObject], mut read: F, window: usize, depth: usize) -> Result<(Hash, usize), Error>
+    where F: FnMut(&Hash) -> Result<Vec<u8>, Error> {
+    let mut order: Vec<usize> = (0..objects.len()).collect();
+    order.sort_by_key(|&index| {
+        let object = &objects[index];
+        (pack_type(object.kind), object.name_hash, std::cmp::Reverse(object.size))
+    });
+
+    let mut pack = PACK_MAGIC.to_vec();
+    pack.extend_from_slice(&2u32.to_be_bytes());
+    pack.extend_from_slice(&(objects.len() as u32).to_be_bytes());
+
+    let mut offsets = vec![0; objects.len()];
+    let mut crcs = vec![0; objects.len()];
+    let mut depths = vec![0; objects.len()];
+    let mut recent: VecDeque<(usize, Vec<u8>)> = VecDeque::with_capacity(window + 1);
+    let mut deltas = 0;
+    for index in order {
+        let object = &objects[index];
+        let content = read(&object.hash)?;
+
+        /* Like git, a delta must at least halve the object to be worth it */
+        let mut best: Option<(usize, Vec<u8>)> = None;
+        if content.len() >= 50 {
+            for (candidate, base) in recent.iter().rev() {
+                if objects[*candidate].kind != object.kind || depths[*candidate] >= depth {
+                    continue
+                }
+                let max_size = best.as_ref().map_or(content.len() / 2 - 20, |(_, delta)| delta.len() - 1);
+                if let Some(delta) = create_delta(base, &content, max_size) {
+                    best = Some((*candidate, delta));
+                }
+            }
+        }
+
+        let offset = pack.len();
+        let mut entry = match &best {
+            Some((base, delta)) => {
+                depths[index] = depths[*base] + 1;
+                deltas += 1;
+                let mut entry = entry_header(OBJ_OFS_DELTA, delta.len());
+                entry.extend(encode_varint(offset - offsets[*base]));
+                entry.extend(compress(delta)?);
+                entry
+            },
+            None => {
+                let mut entry = entry_header(pack_type(object.kind), content.len());
+                entry.extend(compress(&content)?);
+                entry
+            }
+        };
+
+        let mut crc = Crc::new();
+        crc.update(&entry);
+        crcs[index] = crc.sum();
+        offsets[index] = offset;
+        pack.append(&mut entry);
+
+        recent.push_back((index, content));
+        if recent.len() > window {
+            recent.pop_front();
+        }
+    }
+    let checksum = Hash(Sha1::digest(&pack).into());
+    pack.extend_from_slice(&checksum.0);
+
+    /* The index lists the objects sorted by name */
+    let mut sorted: Vec<usize> = (0..objects.len()).collect();
+    sorted.sort_by(|&a, &b| objects[a].hash.cmp(&objects[b].hash));
+
+    let mut idx = IDX_MAGIC.to_vec();
+    idx.extend_from_slice(&2u32.to_be_bytes());
+    for byte in 0..=255u8 {
+        let count = sorted.iter().filter(|&&index| objects[index].hash.0[0] <= byte).count();
+        idx.extend_from_slice(&(count as u32).to_be_bytes());
+    }
+    for &index in &sorted {
+        idx.extend_from_slice(&objects[index].hash.0);
+    }
+    for &index in &sorted {
+        idx.extend_from_slice(&crcs[index].to_be_bytes());
+    }
+    let mut large_offsets = Vec::new();
+    for &index in &sorted {
+        let offset = offsets[index] as u64;
+        if offset < 0x8000_0000 {
+            idx.extend_from_slice(&(offset as u32).to_be_bytes());
+        } else {
+            idx.extend_from_slice(&(0x8000_0000 | large_offsets.len() as u32).to_be_bytes());
+            large_offsets.push(offset);
+        }
+    }
+    for offset in large_offsets {
+        idx.extend_from_slice(&offset.to_be_bytes());
+    }
+    idx.extend_from_slice(&checksum.0);
+    let idx_checksum: [u8; 20] = Sha1::digest(&idx).into();
+    idx.extend_from_slice(&idx_checksum);
+
+    /* The index is written last, as a pack without one is never read */
+    fs::create_dir_all(dir)?;
+    let name = format!("pack-{}", checksum);
+    let temporary = dir.join(format!("tmp_{}", name));
+    fs::write(&temporary, &pack)?;
+    fs::rename(&temporary, dir.join(format!("{}.pack", name)))?;
+    fs::write(&temporary, &idx)?;
+    fs::rename(&temporary, dir.join(format!("{}.idx", name)))?;
+
+    Ok((checksum, deltas))
+}
+
+impl Repository {
+    /* The packs in .git/objects/pack, read once and kept until `reload_packs` */
+    pub fn packs(&self) -> Result<Arc<Vec<Pack>>, Error> {
+        if let Some(packs) = self.packs.read().unwrap_or_else(PoisonError::into_inner).as_ref() {
+            return Ok(packs.clone())
+        }
+
+        let mut packs = Vec::new();
+        let entries = match fs::read_dir(self.git_path("objects/pack")) {
+            Ok(entries) => entries.collect::<Result<Vec<_>, io::Error>>()?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into())
+        };
+        for entry in entries {
+            let path = entry.path();
+            if path.extension().is_some_and(|extension| extension == "idx") && path.with_extension("pack").is_file() {
+                packs.push(Pack::open(&path)?);
+            }
+        }
+        packs.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let packs = Arc::new(packs);
+        *self.packs.write().unwrap_or_else(PoisonError::into_inner) = Some(packs.clone());
+        Ok(packs)
+    }
+
+    /* Forgets the packs read so far, e.g. after packs were added or removed */
+    pub fn reload_packs(&self) {
+        *self.packs.write().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+
+    pub fn read_packed_object(&self, hash: &Hash) -> Result<Option<Object>, Error> {
+        for pack in self.packs()?.iter() {
+            if let Some(object) = pack.read_object(hash)? {
+                return Ok(Some(object))
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test_util::*;
+
+    fn open_example(name: &str) -> Pack {
+        Pack::open(Path::new(&format!("examples/{}.idx", name))).unwrap()
+    }
+
+    #[test]
+    fn read_objects_through_offset_deltas() {
+        let pack = open_example("pack-ofs-delta");
+        assert_eq!(pack.index.len(), 9);
+
+        for hash in &pack.index.hashes {
+            let object = pack.read_object(hash).unwrap().unwrap();
+            assert_eq!(hash_object(object.kind, &object.content), *hash);
+        }
+
+        /* The oldest version of the file is at the end of a chain of two deltas */
+        let oldest = Hash::from_hex("e2a8f2e4ef9f1ca5b22787e3817adc6803f4f6f2").unwrap();
+        let object = pack.read_object(&oldest).unwrap().unwrap();
+        assert_eq!(object.kind, ObjectType::Blob);
+        assert!(object.content.starts_with(b"line 1 of the packed file"));
+    }
+
+    #[test]
+    fn read_objects_through_ref_deltas() {
+        let pack = open_example("pack-ref-delta");
+
+        for hash in &pack.index.hashes {
+            let object = pack.read_object(hash).unwrap().unwrap();
+            assert_eq!(hash_object(object.kind, &object.content), *hash);
+        }
+        assert_eq!(pack.index.find_prefix("75bdd7").len(), 1);
+        assert!(pack.read_object(&Hash([0; 20])).unwrap().is_none());
+    }
+
+    #[test]
+    fn apply_copy_and_insert_instructions() {
+        let base = b"hello, world";
+        /* base size 12, result size 13, copy 7 bytes at 0, insert "nit!!!" */
+        let delta = [12, 13, 0x90, 7, 6, b'n', b'i', b't', b'!', b'!', b'!'];
+
+        let result = apply_delta(base, &delta).unwrap();
+
+        assert_eq!(result, b"hello, nit!!!");
+        assert!(apply_delta(b"short", &delta).is_err());
+    }
+
+    #[test]
+    fn create_delta_round_trips() {
+        let base: Vec<u8> = (0..200).flat_map(|line| format!("line {}\n", line).into_bytes()).collect();
+        let mut target = base.clone();
+        target.splice(100..110, b"changed".iter().copied());
+        target.extend_from_slice(b"appended\n");
+
+        let delta = create_delta(&base, &target, target.len() / 2).unwrap();
+
+        assert_eq!(apply_delta(&base, &delta).unwrap(), target);
+        assert!(create_delta(&base, b"nothing in common", 4).is_none());
+    }
+
+    #[test]
+    fn written_pack_can_be_read_back() {
+        let dir = TempDir::new("pack");
+        let example = open_example("pack-ofs-delta");
+        let objects: Vec<PackObject> = example.index.hashes.iter().map(|hash| {
+            let object = example.read_object(hash).unwrap().unwrap();
+            PackObject { hash: hash.clone(), kind: object.kind, size: object.content.len(), name_hash: 0 }
+        }).collect();
+
+        let read = |hash: &Hash| Ok(example.read_object(hash)?.unwrap().content);
+        let (checksum, deltas) = write_pack(dir.path(), &objects, read, DEFAULT_WINDOW, DEFAULT_DEPTH).unwrap();
+        let pack = Pack::open(&dir.join(format!("pack-{}.idx", checksum))).unwrap();
+
+        assert!(deltas > 0);
+        assert_eq!(pack.index.pack_checksum, checksum);
+        assert_eq!(pack.index.hashes, example.index.hashes);
+        for object in &objects {
+            let content = example.read_object(&object.hash).unwrap().unwrap().content;
+            assert_eq!(pack.read_object(&object.hash).unwrap().unwrap().content, content);
+        }
+    }
+}
@@ -0,0 +1,400 @@
+use crate::error::*;
+use crate::hash::*;
+use crate::object::*;
+use crate::commit::*;
+use crate::tag::*;
+use crate::repository::*;
+
+use std::fs;
+use std::io;
+
+use std::path::Path;
+
+const PACKED_REFS: &str = "packed-refs";
+/* How many symbolic references git follows before giving up */
+const MAX_SYMREF_DEPTH: usize = 5;
+
+/*
+ * Reference names follow the rules of git-check-ref-format, e.g. no
+ * "..", no control characters, and no component starting with '.'
+ * or ending with ".lock".
+ */
+pub fn is_valid_ref_name(name: &str) -> bool {
+    if name.is_empty()
+        || name == "@"
+        || name.starts_with('-')
+        || name.starts_with('/')
+        || name.ends_with('/')
+        || name.ends_with('.')
+        || name.contains("..")
+        || name.contains("@{")
+        || name.contains("//") {
+        return false
+    }
+
+    let forbidden = |c: char| c.is_ascii_control() || " ~^:?*[\\".contains(c);
+    if name.chars().any(forbidden) {
+        return false
+    }
+
+    name.split('/').all(|component| !component.starts_with('.') && !component.ends_with(".lock"))
+}
+
+/*
+ * packed-refs contains one "<hash> <name>" line per reference. A line
+ * starting with '^' holds the peeled value of the annotated tag above it.
+ */
+pub fn parse_packed_refs(content: &str) -> Vec<(String, Hash)> {
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.starts_with('^'))
+        .filter_map(|line| {
+            let (hex, name) = line.split_once(' ')?;
+            let hash = Hash::from_hex(hex).ok()?;
+            Some((name.trim().to_string(), hash))
+        })
+        .collect()
+}
+
+/* What HEAD points to: a branch (e.g. "refs/heads/master") or a commit */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Head {
+    Branch(String),
+    Detached(Hash),
+}
+
+pub fn parse_head(content: &str) -> Result<Head, Error> {
+    let head = content.trim();
+    if let Some(target) = head.strip_prefix("ref: ") {
+        return Ok(Head::Branch(target.to_string()))
+    }
+    Hash::from_hex(head)
+        .map(Head::Detached)
+        .map_err(|_| Error::BadRef(String::from("HEAD is corrupt")))
+}
+
+impl Repository {
+    pub fn read_packed_refs(&self) -> Result<Vec<(String, Hash)>, Error> {
+        match fs::read_to_string(self.git_path(PACKED_REFS)) {
+            Ok(content) => Ok(parse_packed_refs(&content)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err.into())
+        }
+    }
+
+    pub fn read_head(&self) -> Result<Head, Error> {
+        parse_head(&fs::read_to_string(self.git_path("HEAD"))?)
+    }
+
+    pub fn write_head(&self, head: &Head) -> Result<(), Error> {
+        let content = match head {
+            Head::Branch(target) => format!("ref: {}\n", target),
+            Head::Detached(hash) => format!("{}\n", hash),
+        };
+        Ok(fs::write(self.git_path("HEAD"), content)?)
+    }
+
+    /* The branch HEAD points to, or None when HEAD is detached */
+    pub fn current_branch(&self) -> Result<Option<String>, Error> {
+        Ok(match self.read_head()? {
+            Head::Branch(target) => target.strip_prefix("refs/heads/").map(str::to_string),
+            Head::Detached(_) => None
+        })
+    }
+
+    /*
+     * Moves HEAD to a new commit: the checked-out branch is updated (and
+     * created when it is unborn), a detached HEAD is rewritten in place.
+     */
+    pub fn update_head(&self, hash: &Hash) -> Result<(), Error> {
+        match self.read_head()? {
+            Head::Branch(target) => self.write_ref(&target, hash),
+            Head::Detached(_) => self.write_head(&Head::Detached(hash.clone()))
+        }
+    }
+
+    /*
+     * Reads a reference, preferring the loose file over packed-refs. Like
+     * git, symbolic references are followed at most MAX_SYMREF_DEPTH deep,
+     * so that a cycle is reported instead of looping.
+     */
+    pub fn read_ref(&self, name: &str) -> Result<Option<Hash>, Error> {
+        let mut target = name.to_string();
+        for _ in 0..=MAX_SYMREF_DEPTH {
+            let path = self.git_path(&target);
+            if !path.is_file() {
+                return Ok(self.read_packed_refs()?
+                    .into_iter()
+                    .find(|(packed, _)| *packed == target)
+                    .map(|(_, hash)| hash))
+            }
+
+            let content = fs::read_to_string(&path)?;
+            let content = content.trim();
+            match content.strip_prefix("ref: ") {
+                Some(next) => target = next.to_string(),
+                None => return Hash::from_hex(content)
+                    .map(Some)
+                    .map_err(|_| Error::BadRef(format!("Reference {} is corrupt", target)))
+            }
+        }
+        Err(Error::BadRef(format!("Reference {} is nested too deeply", name)))
+    }
+
+    pub fn write_ref(&self, name: &str, hash: &Hash) -> Result<(), Error> {
+        let path = self.git_path(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(fs::write(path, format!("{}\n", hash))?)
+    }
+
+    /* Deletes both the loose and the packed copy of a reference */
+    pub fn delete_ref(&self, name: &str) -> Result<bool, Error> {
+        let path = self.git_path(name);
+        let mut deleted = false;
+        if path.is_file() {
+            fs::remove_file(path)?;
+            deleted = true;
+        }
+
+        let packed_path = self.git_path(PACKED_REFS);
+        let content = match fs::read_to_string(&packed_path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(deleted),
+            Err(err) => return Err(err.into())
+        };
+
+        let mut kept = Vec::new();
+        let mut skipping = false;
+        for line in content.lines() {
+            if line.starts_with('^') && skipping {
+                continue
+            }
+            skipping = line.split_once(' ').is_some_and(|(_, packed)| packed == name);
+            if skipping {
+                deleted = true;
+            } else {
+                kept.push(line);
+            }
+        }
+
+        if deleted {
+            let mut content = kept.join("\n");
+            content.push('\n');
+            fs::write(packed_path, content)?;
+        }
+
+        Ok(deleted)
+    }
+
+    /* The loose references below `dir` that hold an object name, not a symbolic ref */
+    fn collect_packable_refs(&self, dir: &Path, name: &str, refs: &mut Vec<(String, Hash)>) -> Result<(), Error> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let child = format!("{}/{}", name, entry.file_name().to_string_lossy());
+            if entry.file_type()?.is_dir() {
+                self.collect_packable_refs(&entry.path(), &child, refs)?;
+            } else if let Ok(hash) = Hash::from_hex(fs::read_to_string(entry.path())?.trim()) {
+                refs.push((child, hash));
+            }
+        }
+        Ok(())
+    }
+
+    /*
+     * Moves all loose references into packed-refs, like `git pack-refs
+     * --all`, and returns how many were packed. Annotated tags are followed
+     * by a "^<hash>" line with the object they peel to. Symbolic references
+     * stay loose.
+     */
+    pub fn pack_refs(&self) -> Result<usize, Error> {
+        let mut loose = Vec::new();
+        self.collect_packable_refs(&self.git_path("refs"), "refs", &mut loose)?;
+
+        let mut refs = self.read_packed_refs()?;
+        refs.retain(|(packed, _)| !loose.iter().any(|(name, _)| name == packed));
+        refs.extend(loose.iter().cloned());
+        refs.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut content = String::from("# pack-refs with: peeled fully-peeled sorted \n");
+        for (name, hash) in &refs {
+            content.push_str(&format!("{} {}\n", hash, name));
+            if let Ok((peeled, _)) = peel(self, hash.clone())
+                && peeled != *hash {
+                content.push_str(&format!("^{}\n", peeled));
+            }
+        }
+        fs::write(self.git_path(PACKED_REFS), content)?;
+
+        /* Directories such as refs/heads/feature/ are removed once empty */
+        let refs_dir = self.git_path("refs");
+        for (name, _) in &loose {
+            let path = self.git_path(name);
+            fs::remove_file(&path)?;
+            let mut dir = path.parent();
+            while let Some(parent) = dir.filter(|parent| parent.parent() != Some(refs_dir.as_path()) && *parent != refs_dir) {
+                if fs::remove_dir(parent).is_err() {
+                    break
+                }
+                dir = parent.parent();
+            }
+        }
+        Ok(loose.len())
+    }
+
+    fn collect_loose_refs(&self, dir: &Path, name: &str, refs: &mut Vec<(String, Hash)>) -> Result<(), Error> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let child = format!("{}/{}", name, entry.file_name().to_string_lossy());
+            if entry.file_type()?.is_dir() {
+                self.collect_loose_refs(&entry.path(), &child, refs)?;
+            } else if let Some(hash) = self.read_ref(&child)? {
+                refs.push((child, hash));
+            }
+        }
+        Ok(())
+    }
+
+    /* Lists all references below `prefix` (e.g. "refs/tags"), sorted by name */
+    pub fn list_refs(&self, prefix: &str) -> Result<Vec<(String, Hash)>, Error> {
+        let mut refs = Vec::new();
+        let dir = self.git_path(prefix);
+        if dir.is_dir() {
+            self.collect_loose_refs(&dir, prefix, &mut refs)?;
+        }
+
+        let prefix_dir = format!("{}/", prefix);
+        for (name, hash) in self.read_packed_refs()? {
+            if name.starts_with(&prefix_dir) && !refs.iter().any(|(loose, _)| *loose == name) {
+                refs.push((name, hash));
+            }
+        }
+
+        refs.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(refs)
+    }
+
+    /*
+     * Resolves a revision to an object name. Supported are full or abbreviated
+     * hashes, HEAD and reference names looked up in the same order as git
+     * (`<rev>`, `refs/<rev>`, `refs/tags/<rev>`, `refs/heads/<rev>` and
+     * `refs/remotes/<rev>`), optionally followed by `^{}`, `^{commit}` or `^{tree}`.
+     */
+    pub fn resolve_revision(&self, revision: &str) -> Result<Hash, Error> {
+        if let Some(revision) = revision.strip_suffix("^{}") {
+            let (hash, _) = peel(self, self.resolve_revision(revision)?)?;
+            return Ok(hash)
+        }
+        if let Some(revision) = revision.strip_suffix("^{commit}") {
+            return peel_to_commit(self, self.resolve_revision(revision)?)
+        }
+        if let Some(revision) = revision.strip_suffix("^{tree}") {
+            let (hash, kind) = peel(self, self.resolve_revision(revision)?)?;
+            if kind == ObjectType::Tree {
+                return Ok(hash)
+            }
+            let commit = self.read_object(&peel_to_commit(self, hash)?)?;
+            return Ok(Commit::parse(&commit.content)?.tree)
+        }
+
+        if let Ok(hash) = Hash::from_hex(revision) {
+            return Ok(hash)
+        }
+
+        if is_valid_ref_name(revision) {
+            /*
+             * Like git, the git directory itself is only looked at for names
+             * like HEAD or FETCH_HEAD, so a branch named e.g. "config" is not
+             * mistaken for the config file.
+             */
+            let pseudo_ref = revision.bytes().all(|byte| byte.is_ascii_uppercase() || byte == b'_')
+                || revision.starts_with("refs/");
+            let candidates = [
+                pseudo_ref.then(|| revision.to_string()),
+                Some(format!("refs/{}", revision)),
+                Some(format!("refs/tags/{}", revision)),
+                Some(format!("refs/heads/{}", revision)),
+                Some(format!("refs/remotes/{}", revision)),
+            ];
+            for candidate in candidates.into_iter().flatten() {
+                /* A file that is not a reference is no match */
+                match self.read_ref(&candidate) {
+                    Ok(Some(hash)) => return Ok(hash),
+                    Ok(None) | Err(Error::BadRef(_)) => {},
+                    Err(err) => return Err(err)
+                }
+            }
+        }
+
+        self.find_object(revision).map_err(|_| Error::BadRef(format!("Unknown revision '{}'", revision)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test_util::*;
+
+    #[test]
+    fn validate_ref_names() {
+        assert!(is_valid_ref_name("v1.0.0"));
+        assert!(is_valid_ref_name("refs/heads/feature/login"));
+        assert!(!is_valid_ref_name("release..1"));
+        assert!(!is_valid_ref_name("bad name"));
+        assert!(!is_valid_ref_name("topic.lock"));
+        assert!(!is_valid_ref_name(".hidden"));
+        assert!(!is_valid_ref_name("-v1"));
+    }
+
+    #[test]
+    fn read_packed_refs_with_peeled_tags() {
+        let content = "# pack-refs with: peeled fully-peeled sorted
+c631313b6cc3a747eac28cdb26802678a96b870b refs/heads/master
+f60b322c7351b08514fceed6f69102138ab420e7 refs/tags/v1.0.0
+^c631313b6cc3a747eac28cdb26802678a96b870b
+";
+
+        let refs = parse_packed_refs(content);
+
+        assert_eq!(refs.len(), 2);
+        assert_eq!(refs[1].0, "refs/tags/v1.0.0");
+        assert_eq!(refs[1].1.to_string(), "f60b322c7351b08514fceed6f69102138ab420e7");
+    }
+
+    #[test]
+    fn parse_symbolic_and_detached_head() {
+        let branch = parse_head("ref: refs/heads/main\n").unwrap();
+        let detached = parse_head("c631313b6cc3a747eac28cdb26802678a96b870b\n").unwrap();
+
+        assert_eq!(branch, Head::Branch(String::from("refs/heads/main")));
+        assert_eq!(detached, Head::Detached(Hash::from_hex("c631313b6cc3a747eac28cdb26802678a96b870b").unwrap()));
+        assert!(parse_head("garbage").is_err());
+    }
+
+    #[test]
+    fn branches_named_like_git_files_resolve_to_refs() {
+        let repository = TempRepo::new("refs-resolve");
+        let hash = repository.write_object(ObjectType::Blob, b"content".to_vec()).unwrap();
+        repository.write_index(&crate::index::Index::new(Vec::new())).unwrap();
+        fs::write(repository.git_path("description"), "Unnamed repository\n").unwrap();
+
+        for name in ["config", "index", "description", "packed-refs"] {
+            repository.write_ref(&format!("refs/heads/{}", name), &hash).unwrap();
+            assert_eq!(repository.resolve_revision(name).unwrap(), hash);
+        }
+        repository.write_ref("FETCH_HEAD", &hash).unwrap();
+        assert_eq!(repository.resolve_revision("FETCH_HEAD").unwrap(), hash);
+        assert_eq!(repository.resolve_revision("refs/heads/config").unwrap(), hash);
+    }
+
+    #[test]
+    fn symbolic_ref_cycle_is_an_error() {
+        let repository = TempRepo::new("refs-cycle");
+        fs::write(repository.git_path("HEAD"), "ref: refs/heads/a\n").unwrap();
+        fs::write(repository.git_path("refs/heads/a"), "ref: HEAD\n").unwrap();
+
+        assert!(matches!(repository.read_ref("HEAD"), Err(Error::BadRef(_))));
+    }
+}
@@ -0,0 +1,294 @@
+use crate::error::*;
+use crate::init::*;
+use crate::pack::*;
+
+use std::env;
+use std::fs;
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, PoisonError, RwLock};
+
+/* The name of the git directory in a working tree */
+pub const DOT_GIT: &str = ".git";
+
+/*
+ * A git repository: the git directory holding objects, refs, index and
+ * config, and the working tree it belongs to. Bare repositories have no
+ * working tree.
+ */
+#[derive(Debug)]
+pub struct Repository {
+    git_dir: PathBuf,
+    work_tree: Option<PathBuf>,
+    /*
+     * The packs in objects/pack, read on the first lookup of an object and
+     * shared by the threads using the repository
+     */
+    pub(crate) packs: RwLock<Option<Arc<Vec<Pack>>>>,
+}
+
+/* A repository can be shared across threads, which caches must not prevent */
+fn _assert_send_sync<T: Send + Sync>() {}
+const _: fn() = _assert_send_sync::<Repository>;
+
+impl Clone for Repository {
+    fn clone(&self) -> Self {
+        let packs = self.packs.read().unwrap_or_else(PoisonError::into_inner).clone();
+        Repository { git_dir: self.git_dir.clone(), work_tree: self.work_tree.clone(), packs: RwLock::new(packs) }
+    }
+}
+
+/*
+ * Where to look for the repository. The git directory and working tree
+ * override discovery, while discovery never looks at the ceiling
+ * directories or above them.
+ */
+#[derive(Debug, Default, Clone)]
+pub struct DiscoverOptions {
+    pub git_dir: Option<PathBuf>,
+    pub work_tree: Option<PathBuf>,
+    pub ceilings: Vec<PathBuf>,
+}
+
+impl DiscoverOptions {
+    /* Reads GIT_DIR, GIT_WORK_TREE and the ':' separated GIT_CEILING_DIRECTORIES */
+    pub fn from_env() -> Self {
+        let ceilings = env::var_os("GIT_CEILING_DIRECTORIES")
+            .map(|ceilings| env::split_paths(&ceilings).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        DiscoverOptions {
+            git_dir: env::var_os("GIT_DIR").filter(|dir| !dir.is_empty()).map(PathBuf::from),
+            work_tree: env::var_os("GIT_WORK_TREE").filter(|dir| !dir.is_empty()).map(PathBuf::from),
+            /* Like git, relative ceilings are ignored */
+            ceilings: ceilings.into_iter().filter(|ceiling| ceiling.is_absolute()).collect(),
+        }
+    }
+}
+
+/* Whether `path` looks like a git directory, i.e. has HEAD, objects and refs */
+pub fn is_git_dir(path: &Path) -> bool {
+    path.join("HEAD").is_file() && path.join("objects").is_dir() && path.join("refs").is_dir()
+}
+
+/*
+ * Reads a .git file, which points to the git directory elsewhere with
+ * "gitdir: <path>", a relative path being relative to the file.
+ */
+fn read_git_file(path: &Path) -> Result<PathBuf, Error> {
+    let content = fs::read_to_string(path)?;
+    let git_dir = content
+        .strip_prefix("gitdir:")
+        .map(str::trim)
+        .filter(|git_dir| !git_dir.is_empty())
+        .ok_or_else(|| Error::NotARepository(path.to_path_buf()))?;
+
+    let git_dir = path.parent().unwrap_or(Path::new("")).join(git_dir);
+    if !is_git_dir(&git_dir) {
+        return Err(Error::NotARepository(git_dir))
+    }
+    Ok(git_dir)
+}
+
+/* Resolves "." and ".." without touching the file system */
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                normalized.pop();
+            },
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+impl Repository {
+    pub fn new(git_dir: PathBuf, work_tree: Option<PathBuf>) -> Self {
+        Repository { git_dir, work_tree, packs: RwLock::new(None) }
+    }
+
+    /*
+     * Opens the repository at `path`, which is either a working tree with
+     * a .git directory (or a .git file pointing to one) or a bare repository.
+     */
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let dot_git = path.join(DOT_GIT);
+        if dot_git.is_file() {
+            return Ok(Repository::new(read_git_file(&dot_git)?, Some(path.to_path_buf())))
+        }
+        if is_git_dir(&dot_git) {
+            return Ok(Repository::new(dot_git, Some(path.to_path_buf())))
+        }
+        if is_git_dir(path) {
+            return Ok(Repository::new(path.to_path_buf(), None))
+        }
+        Err(Error::NotARepository(path.to_path_buf()))
+    }
+
+    /* Creates (or reinitializes) a repository and opens it */
+    pub fn init(path: impl AsRef<Path>, initial_branch: &str, bare: bool) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let git_dir = init(path, initial_branch, bare)?;
+        let work_tree = (!bare).then(|| path.to_path_buf());
+        Ok(Repository::new(git_dir, work_tree))
+    }
+
+    /* Opens the repository at `path` or the closest of its parent directories */
+    pub fn discover(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::discover_with(path, &DiscoverOptions::default())
+    }
+
+    /*
+     * Like `discover`, but an explicit git directory is used as is, with
+     * `path` as its working tree unless one is given or the repository is
+     * bare. Relative option paths are relative to `path`.
+     */
+    pub fn discover_with(path: impl AsRef<Path>, options: &DiscoverOptions) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let start = path.canonicalize()?;
+        let work_tree = options.work_tree.as_ref().map(|work_tree| start.join(work_tree));
+
+        if let Some(git_dir) = &options.git_dir {
+            let git_dir = start.join(git_dir);
+            if !is_git_dir(&git_dir) {
+                return Err(Error::NotARepository(git_dir))
+            }
+            let mut repository = Repository::new(git_dir, None);
+            let bare = repository.config()?.get_bool("core.bare")?.unwrap_or(false);
+            repository.work_tree = match work_tree {
+                Some(work_tree) => Some(work_tree),
+                None => (!bare).then_some(start)
+            };
+            return Ok(repository)
+        }
+
+        /* Only ceilings above the starting directory stop the search */
+        let ceilings: Vec<PathBuf> = options.ceilings
+            .iter()
+            .filter_map(|ceiling| ceiling.canonicalize().ok())
+            .filter(|ceiling| start.starts_with(ceiling) && *ceiling != start)
+            .collect();
+
+        for dir in start.ancestors() {
+            if ceilings.iter().any(|ceiling| ceiling.starts_with(dir)) {
+                break
+            }
+            match Repository::open(dir) {
+                Ok(mut repository) => {
+                    if work_tree.is_some() {
+                        repository.work_tree = work_tree;
+                    }
+                    return Ok(repository)
+                },
+                Err(Error::NotARepository(_)) => continue,
+                Err(err) => return Err(err)
+            }
+        }
+        Err(Error::NotARepository(path.to_path_buf()))
+    }
+
+    pub fn git_dir(&self) -> &Path {
+        &self.git_dir
+    }
+
+    pub fn is_bare(&self) -> bool {
+        self.work_tree.is_none()
+    }
+
+    pub fn work_tree(&self) -> Result<&Path, Error> {
+        self.work_tree.as_deref().ok_or_else(|| {
+            Error::InvalidArgument(String::from("this operation must be run in a work tree"))
+        })
+    }
+
+    /* A file in the git directory, e.g. "HEAD" or "refs/heads/master" */
+    pub fn git_path(&self, name: &str) -> PathBuf {
+        self.git_dir.join(name)
+    }
+
+    /* A file in the working tree by its path relative to the root */
+    pub fn worktree_path(&self, name: &str) -> Result<PathBuf, Error> {
+        Ok(self.work_tree()?.join(name))
+    }
+
+    /*
+     * The path relative to the root of the working tree, with '/'
+     * separators, of a path relative to the current directory. E.g.
+     * "../README" in "src/" is "README" and the root itself is "".
+     */
+    pub fn relative_path(&self, path: &Path) -> Result<String, Error> {
+        let root = self.work_tree()?.canonicalize()?;
+        let absolute = normalize(&env::current_dir()?.join(path));
+        match absolute.strip_prefix(&root) {
+            Ok(relative) => Ok(relative.to_string_lossy().to_string()),
+            Err(_) => Err(Error::InvalidArgument(format!(
+                "'{}' is outside repository at '{}'",
+                path.display(),
+                root.display()
+            )))
+        }
+    }
+
+    /* Pathspecs given relative to the current directory, see `relative_path` */
+    pub fn relative_paths(&self, paths: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+        paths.iter().map(|path| self.relative_path(path).map(PathBuf::from)).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test_util::*;
+
+    use std::fs;
+
+    #[test]
+    fn open_and_discover_repository() {
+        let created = TempRepo::new("repository");
+        let dir = created.path();
+        fs::create_dir_all(dir.join("src/nested")).unwrap();
+
+        let opened = Repository::open(dir).unwrap();
+        let discovered = Repository::discover(dir.join("src/nested")).unwrap();
+
+        assert_eq!(opened.git_dir(), created.git_dir());
+        assert_eq!(discovered.work_tree().unwrap(), dir.canonicalize().unwrap());
+        assert!(matches!(Repository::open(dir.join("src")), Err(Error::NotARepository(_))));
+    }
+
+    #[test]
+    fn bare_repository_has_no_work_tree() {
+        let dir = TempDir::new("repository-bare");
+        Repository::init(dir.path(), "main", true).unwrap();
+
+        let repository = Repository::open(dir.path()).unwrap();
+
+        assert!(repository.is_bare());
+        assert!(repository.work_tree().is_err());
+    }
+
+    #[test]
+    fn discover_follows_git_file_and_stops_at_ceiling() {
+        let scratch = TempDir::new("repository-discover");
+        let created = Repository::init(scratch.join("repo"), "main", false).unwrap();
+        fs::create_dir_all(scratch.join("linked/src")).unwrap();
+        fs::write(scratch.join("linked/.git"), "gitdir: ../repo/.git\n").unwrap();
+        let dir = scratch.path().canonicalize().unwrap();
+
+        let linked = Repository::discover(dir.join("linked/src")).unwrap();
+        let ceiling = DiscoverOptions { ceilings: vec![dir.join("linked")], ..Default::default() };
+        let explicit = DiscoverOptions { git_dir: Some(PathBuf::from("repo/.git")), ..Default::default() };
+        let opened = Repository::discover_with(&dir, &explicit).unwrap();
+
+        assert_eq!(linked.git_dir().canonicalize().unwrap(), created.git_dir().canonicalize().unwrap());
+        assert_eq!(linked.work_tree().unwrap(), dir.join("linked"));
+        assert!(matches!(Repository::discover_with(dir.join("linked/src"), &ceiling), Err(Error::NotARepository(_))));
+        assert!(Repository::discover_with(dir.join("linked"), &ceiling).is_ok());
+        assert_eq!(opened.work_tree().unwrap(), dir);
+    }
+}
@@ -0,0 +1,441 @@
+use crate::error::*;
+use crate::hash::*;
+use crate::command::*;
+use crate::ignore::*;
+use crate::index::*;
+use crate::object::*;
+use crate::commit::*;
+use crate::tree::*;
+use crate::repository::*;
+
+use std::fs;
+use std::io;
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Change {
+    Added,
+    Modified,
+    Deleted,
+}
+
+impl Change {
+    fn code(change: Option<Change>) -> char {
+        match change {
+            Some(Change::Added) => 'A',
+            Some(Change::Modified) => 'M',
+            Some(Change::Deleted) => 'D',
+            None => ' ',
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Change::Added => "new file:   ",
+            Change::Modified => "modified:   ",
+            Change::Deleted => "deleted:    ",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FileStatus {
+    pub path: String,
+    /* HEAD compared to the index */
+    pub staged: Option<Change>,
+    /* The index compared to the working tree */
+    pub unstaged: Option<Change>,
+    pub head: Option<(FileMode, Hash)>,
+    pub index: Option<(FileMode, Hash)>,
+    pub worktree_mode: Option<FileMode>,
+}
+
+#[derive(Debug)]
+pub struct Status {
+    pub branch: Option<String>,
+    pub head: Option<Hash>,
+    pub files: Vec<FileStatus>,
+    pub untracked: Vec<String>,
+}
+
+/* The mode git would record for the file: symlink, executable or regular file */
+pub fn worktree_mode(stat: &fs::Metadata) -> FileMode {
+    if stat.file_type().is_symlink() {
+        FileMode::Symlink
+    } else if stat.permissions().mode() & 0o111 != 0 {
+        FileMode::Executable
+    } else {
+        FileMode::Regular
+    }
+}
+
+/* The content git would store for the file, i.e. the target of a symlink */
+pub fn read_worktree_file(path: &Path) -> Result<Vec<u8>, Error> {
+    if fs::symlink_metadata(path)?.file_type().is_symlink() {
+        Ok(fs::read_link(path)?.into_os_string().into_encoded_bytes())
+    } else {
+        Ok(fs::read(path)?)
+    }
+}
+
+/*
+ * The mode and object name of the working tree file of an index entry, or
+ * None if it was deleted. The file is only rehashed if its stat(2) data
+ * differs from the index, or if it was modified in the same second as the
+ * index was written (and so could have changed without it showing).
+ */
+pub fn worktree_entry(
+    repository: &Repository,
+    entry: &IndexEntry,
+    index_mtime: u32,
+) -> Result<Option<(FileMode, Hash)>, Error> {
+    /* A sparse checkout leaves the file out, it is taken to be unchanged */
+    if entry.skip_worktree() {
+        return Ok(Some((entry.mode(), entry.key.clone())))
+    }
+
+    let path = repository.worktree_path(&entry.name)?;
+    let stat = match fs::symlink_metadata(&path) {
+        Ok(stat) if stat.is_dir() => return Ok(None),
+        Ok(stat) => stat,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into())
+    };
+
+    let mode = worktree_mode(&stat);
+    let racily_clean = entry.mtime().0 >= index_mtime;
+    if entry.matches_stat(&stat) && !racily_clean {
+        return Ok(Some((mode, entry.key.clone())))
+    }
+
+    let content = read_worktree_file(&path)?;
+    Ok(Some((mode, hash_object(ObjectType::Blob, &content))))
+}
+
+/*
+ * The files below `dir` by their path relative to `root`, skipping the git
+ * directory and ignored files and directories.
+ */
+fn collect_worktree_files(
+    root: &Path,
+    dir: &Path,
+    ignore: &mut Ignore,
+    files: &mut Vec<String>,
+) -> Result<(), Error> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.ends_with(DOT_GIT) {
+            continue
+        }
+
+        let stat = fs::symlink_metadata(&path)?;
+        let name = path.strip_prefix(root).unwrap_or(&path).to_string_lossy().to_string();
+        if ignore.is_ignored(&name, stat.is_dir())? {
+            continue
+        }
+        if stat.is_dir() {
+            collect_worktree_files(root, &path, ignore, files)?;
+        } else {
+            files.push(name);
+        }
+    }
+    Ok(())
+}
+
+/*
+ * Like git, an untracked directory is shown as "dir/" instead of listing
+ * every file in it, as long as nothing inside it is tracked.
+ */
+pub fn collapse_untracked(files: Vec<String>, tracked: &BTreeSet<String>) -> Vec<String> {
+    let mut collapsed = BTreeSet::new();
+    for file in files {
+        let mut shown = file.clone();
+        let mut end = 0;
+        while let Some(pos) = file[end..].find('/') {
+            let dir = &file[..end + pos + 1];
+            if !tracked.iter().any(|path| path.starts_with(dir)) {
+                shown = dir.to_string();
+                break
+            }
+            end += pos + 1;
+        }
+        collapsed.insert(shown);
+    }
+    collapsed.into_iter().collect()
+}
+
+pub fn status(repository: &Repository) -> Result<Status, Error> {
+    let branch = repository.current_branch()?;
+    let head = repository.read_ref("HEAD")?;
+    let head_entries = match &head {
+        Some(commit) => {
+            let commit = Commit::read(repository, commit)?;
+            Tree::read(repository, &commit.tree)?.flatten(repository, "")?
+        },
+        None => BTreeMap::new()
+    };
+
+    let index = repository.read_index()?;
+    let index_mtime = repository.index_mtime()?;
+
+    let mut files: BTreeMap<String, FileStatus> = BTreeMap::new();
+    let file_status = |path: &str| FileStatus {
+        path: path.to_string(),
+        staged: None,
+        unstaged: None,
+        head: head_entries.get(path).map(|entry| (entry.mode, entry.key.clone())),
+        index: None,
+        worktree_mode: None,
+    };
+
+    for entry in &index.entries {
+        let mut status = file_status(&entry.name);
+        status.index = Some((entry.mode(), entry.key.clone()));
+        status.staged = match &status.head {
+            None => Some(Change::Added),
+            Some((mode, key)) if *mode != entry.mode() || *key != entry.key => Some(Change::Modified),
+            Some(_) => None
+        };
+
+        /* An intent to add is only staged once the file is added */
+        if entry.intent_to_add() {
+            status.staged = None;
+            status.unstaged = Some(Change::Added);
+            files.insert(entry.name.clone(), status);
+            continue
+        }
+
+        match worktree_entry(repository, entry, index_mtime)? {
+            Some((mode, key)) => {
+                status.worktree_mode = Some(mode);
+                if mode != entry.mode() || key != entry.key {
+                    status.unstaged = Some(Change::Modified);
+                }
+            },
+            None => status.unstaged = Some(Change::Deleted)
+        }
+
+        files.insert(entry.name.clone(), status);
+    }
+
+    for path in head_entries.keys() {
+        if !files.contains_key(path) {
+            let mut status = file_status(path);
+            status.staged = Some(Change::Deleted);
+            files.insert(path.clone(), status);
+        }
+    }
+
+    let tracked: BTreeSet<String> = index.entries.into_iter().map(|entry| entry.name).collect();
+    let mut worktree = Vec::new();
+    let mut ignore = Ignore::load(repository)?;
+    let root = repository.work_tree()?;
+    collect_worktree_files(root, root, &mut ignore, &mut worktree)?;
+    let untracked = worktree
+        .into_iter()
+        .filter(|path| !tracked.contains(path))
+        .collect();
+
+    Ok(Status {
+        branch,
+        head,
+        files: files
+            .into_values()
+            .filter(|status| status.staged.is_some() || status.unstaged.is_some())
+            .collect(),
+        untracked: collapse_untracked(untracked, &tracked),
+    })
+}
+
+fn format_branch_header(status: &Status) -> String {
+    match (&status.branch, &status.head) {
+        (Some(branch), Some(_)) => format!("## {}\n", branch),
+        (Some(branch), None) => format!("## No commits yet on {}\n", branch),
+        (None, _) => String::from("## HEAD (no branch)\n"),
+    }
+}
+
+pub fn format_short(status: &Status, branch: bool) -> String {
+    let mut output = String::new();
+    if branch {
+        output.push_str(&format_branch_header(status));
+    }
+    for file in &status.files {
+        output.push_str(&format!("{}{} {}\n",
+            Change::code(file.staged),
+            Change::code(file.unstaged),
+            file.path));
+    }
+    for path in &status.untracked {
+        output.push_str(&format!("?? {}\n", path));
+    }
+    output
+}
+
+pub fn format_porcelain_v2(status: &Status, branch: bool) -> String {
+    let mut output = String::new();
+    if branch {
+        match &status.head {
+            Some(head) => output.push_str(&format!("# branch.oid {}\n", head)),
+            None => output.push_str("# branch.oid (initial)\n"),
+        }
+        match &status.branch {
+            Some(name) => output.push_str(&format!("# branch.head {}\n", name)),
+            None => output.push_str("# branch.head (detached)\n"),
+        }
+    }
+
+    let zero = Hash([0; 20]);
+    for file in &status.files {
+        let code = |change| match Change::code(change) {
+            ' ' => '.',
+            code => code
+        };
+        /* A missing side has mode 000000 and the null object name */
+        let mode = |mode: Option<FileMode>| mode.map_or(0, FileMode::bits);
+        let head_key = file.head.as_ref().map_or(&zero, |(_, key)| key);
+        let index_key = file.index.as_ref().map_or(&zero, |(_, key)| key);
+        output.push_str(&format!("1 {}{} N... {:06o} {:06o} {:06o} {} {} {}\n",
+            code(file.staged),
+            code(file.unstaged),
+            mode(file.head.as_ref().map(|(mode, _)| *mode)),
+            mode(file.index.as_ref().map(|(mode, _)| *mode)),
+            mode(file.worktree_mode),
+            head_key,
+            index_key,
+            file.path));
+    }
+    for path in &status.untracked {
+        output.push_str(&format!("? {}\n", path));
+    }
+    output
+}
+
+pub fn format_long(status: &Status) -> String {
+    let mut output = match (&status.branch, &status.head) {
+        (Some(branch), _) => format!("On branch {}\n", branch),
+        (None, Some(head)) => format!("HEAD detached at {}\n", &head.to_string()[..7]),
+        (None, None) => String::from("Not currently on any branch.\n"),
+    };
+    if status.head.is_none() {
+        output.push_str("\nNo commits yet\n\n");
+    }
+
+    let staged: Vec<_> = status.files.iter().filter_map(|f| Some((f.staged?, &f.path))).collect();
+    let unstaged: Vec<_> = status.files.iter().filter_map(|f| Some((f.unstaged?, &f.path))).collect();
+
+    if !staged.is_empty() {
+        output.push_str("Changes to be committed:\n");
+        for (change, path) in &staged {
+            output.push_str(&format!("\t{}{}\n", change.describe(), path));
+        }
+        output.push('\n');
+    }
+    if !unstaged.is_empty() {
+        output.push_str("Changes not staged for commit:\n");
+        output.push_str("  (use \"nit add <file>...\" to update what will be committed)\n");
+        for (change, path) in &unstaged {
+            output.push_str(&format!("\t{}{}\n", change.describe(), path));
+        }
+        output.push('\n');
+    }
+    if !status.untracked.is_empty() {
+        output.push_str("Untracked files:\n");
+        output.push_str("  (use \"nit add <file>...\" to include in what will be committed)\n");
+        for path in &status.untracked {
+            output.push_str(&format!("\t{}\n", path));
+        }
+        output.push('\n');
+    }
+
+    if !staged.is_empty() {
+        output.pop();
+        return output
+    }
+
+    let summary = match (unstaged.is_empty(), status.untracked.is_empty()) {
+        (false, _) => "no changes added to commit (use \"nit add\")\n",
+        (true, false) => "nothing added to commit but untracked files present (use \"nit add\" to track)\n",
+        (true, true) if status.head.is_none() => "nothing to commit (create/copy files and use \"nit add\" to track)\n",
+        (true, true) => "nothing to commit, working tree clean\n",
+    };
+    output.push_str(summary);
+    output
+}
+
+pub fn print_status(repository: &Repository, format: StatusFormat, branch: bool) -> Result<(), Error> {
+    let status = status(repository)?;
+    let output = match format {
+        StatusFormat::Long => format_long(&status),
+        StatusFormat::Short | StatusFormat::PorcelainV1 => format_short(&status, branch),
+        StatusFormat::PorcelainV2 => format_porcelain_v2(&status, branch),
+    };
+    print!("{}", output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn example_status() -> Status {
+        let key = Hash::from_hex("d9fa2b8cd651190f6ff5932113491d0a2995b116").unwrap();
+        let file = |path: &str, staged, unstaged| FileStatus {
+            path: path.to_string(),
+            staged,
+            unstaged,
+            head: (staged != Some(Change::Added)).then(|| (FileMode::Regular, key.clone())),
+            index: (staged != Some(Change::Deleted)).then(|| (FileMode::Regular, key.clone())),
+            worktree_mode: (unstaged != Some(Change::Deleted)).then_some(FileMode::Regular),
+        };
+
+        Status {
+            branch: Some(String::from("master")),
+            head: Some(key.clone()),
+            files: vec![
+                file("Cargo.toml", Some(Change::Modified), Some(Change::Modified)),
+                file("examples/blob.c", Some(Change::Added), None),
+                file("src/main.rs", None, Some(Change::Deleted)),
+            ],
+            untracked: vec![String::from("playground/")],
+        }
+    }
+
+    #[test]
+    fn format_status_short() {
+        let output = format_short(&example_status(), true);
+
+        let expected = "## master\nMM Cargo.toml\nA  examples/blob.c\n D src/main.rs\n?? playground/\n";
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn format_status_porcelain_v2() {
+        let output = format_porcelain_v2(&example_status(), false);
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines[1], "1 A. N... 000000 100644 100644 \
+            0000000000000000000000000000000000000000 d9fa2b8cd651190f6ff5932113491d0a2995b116 examples/blob.c");
+        assert_eq!(lines[2], "1 .D N... 100644 100644 000000 \
+            d9fa2b8cd651190f6ff5932113491d0a2995b116 d9fa2b8cd651190f6ff5932113491d0a2995b116 src/main.rs");
+        assert_eq!(lines[3], "? playground/");
+    }
+
+    #[test]
+    fn collapse_untracked_directories() {
+        let tracked = BTreeSet::from([String::from("src/main.rs")]);
+        let files = vec![
+            String::from("src/new.rs"),
+            String::from("docs/a.md"),
+            String::from("docs/b/c.md"),
+            String::from("notes.txt"),
+        ];
+
+        let untracked = collapse_untracked(files, &tracked);
+
+        assert_eq!(untracked, ["docs/", "notes.txt", "src/new.rs"]);
+    }
+}
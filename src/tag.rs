@@ -0,0 +1,212 @@
+use crate::error::*;
+use crate::hash::*;
+use crate::commit::*;
+use crate::refs::*;
+use crate::object::*;
+use crate::util::*;
+use crate::command::*;
+use crate::ident::*;
+use crate::repository::*;
+
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub struct Tag {
+    pub object: Hash,
+    pub kind: ObjectType,
+    pub name: Vec<u8>,
+    /* Very old tags were written without a tagger */
+    pub tagger: Option<Stamp>,
+    pub headers: Headers,
+    /* None for a tag without the empty line that starts the message */
+    pub message: Option<Vec<u8>>,
+}
+
+impl Tag {
+    /* Parses the content of a tag object, i.e. without the object header */
+    pub fn parse(content: &[u8]) -> Result<Tag, Error> {
+        let (headers, message) = split_headers(content);
+        let mut headers = headers.into_iter().peekable();
+
+        let object = match headers.next() {
+            Some((key, Some(value))) if key == "object" => Hash::from_hex(&String::from_utf8_lossy(&value))?,
+            _ => return Err(corrupt("Tag is missing object".into()))
+        };
+
+        let kind = match headers.next() {
+            Some((key, Some(value))) if key == "type" => ObjectType::from_name(&String::from_utf8_lossy(&value))?,
+            _ => return Err(corrupt("Tag is missing type".into()))
+        };
+
+        let name = match headers.next() {
+            Some((key, Some(value))) if key == "tag" => value,
+            _ => return Err(corrupt("Tag is missing tag name".into()))
+        };
+
+        let tagger = match headers.next_if(|(key, _)| key == "tagger") {
+            Some((_, value)) => Some(Stamp::parse(&value.unwrap_or_default())?),
+            None => None
+        };
+
+        Ok(Tag {
+            object,
+            kind,
+            name,
+            tagger,
+            headers: headers.collect(),
+            message
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = format!("object {}\ntype {}\n", self.object, self.kind).into_bytes();
+        write_header(&mut bytes, "tag", &self.name);
+        if let Some(tagger) = &self.tagger {
+            write_header(&mut bytes, "tagger", &tagger.to_bytes());
+        }
+        write_headers_and_message(&mut bytes, &self.headers, self.message.as_deref());
+        bytes
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.to_bytes()))
+    }
+}
+
+/* Follows annotated tags until reaching an object that is not a tag */
+pub fn peel(repository: &Repository, mut hash: Hash) -> Result<(Hash, ObjectType), Error> {
+    loop {
+        let object = repository.read_object(&hash)?;
+        if object.kind != ObjectType::Tag {
+            return Ok((hash, object.kind))
+        }
+        hash = Tag::parse(&object.content)?.object;
+    }
+}
+
+pub fn peel_to_commit(repository: &Repository, hash: Hash) -> Result<Hash, Error> {
+    match peel(repository, hash)? {
+        (hash, ObjectType::Commit) => Ok(hash),
+        (hash, kind) => Err(Error::InvalidArgument(format!("{} is a {}, not a commit", hash, kind)))
+    }
+}
+
+fn create_tag(
+    repository: &Repository,
+    name: &str,
+    target: Option<String>,
+    message: Option<String>,
+    force: bool,
+) -> Result<(), Error> {
+    let ref_name = format!("refs/tags/{}", name);
+    if !is_valid_ref_name(&ref_name) {
+        return Err(Error::BadRef(format!("'{}' is not a valid tag name", name)))
+    }
+    if !force && repository.read_ref(&ref_name)?.is_some() {
+        return Err(Error::Conflict(format!("Tag '{}' already exists", name)))
+    }
+
+    let target = repository.resolve_revision(target.as_deref().unwrap_or("HEAD"))?;
+
+    /* An annotated tag is a tag object the reference points to */
+    let hash = match message {
+        Some(mut message) => {
+            if !message.ends_with('\n') {
+                message.push('\n');
+            }
+            let tag = Tag {
+                kind: repository.read_object(&target)?.kind,
+                object: target,
+                name: name.as_bytes().to_vec(),
+                tagger: Some(committer(repository)?),
+                headers: Vec::new(),
+                message: Some(message.into_bytes())
+            };
+            repository.write_object(ObjectType::Tag, tag.to_bytes())?
+        },
+        None => target
+    };
+
+    repository.write_ref(&ref_name, &hash)
+}
+
+/* The names of the tags matching any of the patterns, or of all tags without patterns */
+fn list_tags(repository: &Repository, patterns: &[String]) -> Result<Vec<String>, Error> {
+    Ok(repository.list_refs("refs/tags")?
+        .into_iter()
+        .map(|(name, _)| name["refs/tags/".len()..].to_string())
+        .filter(|name| {
+            patterns.is_empty()
+                || patterns.iter().any(|pattern| wildmatch(pattern.as_bytes(), name.as_bytes(), false))
+        })
+        .collect())
+}
+
+pub fn tag(repository: &Repository, action: TagAction) -> Result<(), Error> {
+    match action {
+        TagAction::List(patterns) => {
+            for name in list_tags(repository, &patterns)? {
+                println!("{}", name);
+            }
+        },
+        TagAction::Create { name, target, message, force } => {
+            create_tag(repository, &name, target, message, force)?;
+        },
+        TagAction::Delete(names) => {
+            for name in names {
+                let ref_name = format!("refs/tags/{}", name);
+                let hash = repository.read_ref(&ref_name)?;
+                match hash {
+                    Some(hash) if repository.delete_ref(&ref_name)? => {
+                        println!("Deleted tag '{}' (was {})", name, &hash.to_string()[..7]);
+                    },
+                    _ => return Err(Error::BadRef(format!("Tag '{}' not found", name)))
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test_util::*;
+
+    #[test]
+    fn tag_round_trip() {
+        let content = "object c631313b6cc3a747eac28cdb26802678a96b870b
+type commit
+tag v1.0.0
+tagger Nicolay Roness <nicolay.caspersen.roness@sparebank1.no> 1764365370 +0100
+
+Release 1.0.0
+-----BEGIN PGP SIGNATURE-----
+-----END PGP SIGNATURE-----
+";
+
+        let tag = Tag::parse(content.as_bytes()).unwrap();
+
+        assert_eq!(tag.name, b"v1.0.0");
+        assert_eq!(tag.kind, ObjectType::Commit);
+        assert_eq!(tag.tagger.as_ref().unwrap().name, b"Nicolay Roness");
+        assert_eq!(tag.to_string(), content);
+    }
+
+    #[test]
+    fn list_tags_matching_patterns() {
+        let repository = TempRepo::new("tag-list");
+        let blob = repository.write_object(ObjectType::Blob, b"tagged".to_vec()).unwrap();
+        for name in ["v1.0", "v1.1", "v2.0", "release"] {
+            repository.write_ref(&format!("refs/tags/{}", name), &blob).unwrap();
+        }
+
+        assert_eq!(list_tags(&repository, &[]).unwrap().len(), 4);
+        assert_eq!(list_tags(&repository, &["v1.*".to_string()]).unwrap(), ["v1.0", "v1.1"]);
+        assert_eq!(list_tags(&repository, &["v2*".to_string(), "rel*".to_string()]).unwrap(), ["release", "v2.0"]);
+        assert!(list_tags(&repository, &["v3*".to_string()]).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,68 @@
+use crate::repository::*;
+
+use std::fs;
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/*
+ * A scratch directory for a test, unique within the test run and removed
+ * when dropped, also when the test panics.
+ */
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("nit-{}-{}-{}", name, std::process::id(), id));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.path.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/* A repository with a working tree and "main" checked out, in a scratch directory */
+pub struct TempRepo {
+    pub repository: Repository,
+    pub dir: TempDir,
+}
+
+impl TempRepo {
+    pub fn new(name: &str) -> Self {
+        let dir = TempDir::new(name);
+        let repository = Repository::init(dir.path(), "main", false).unwrap();
+        TempRepo { repository, dir }
+    }
+
+    /* The root of the working tree */
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+}
+
+impl Deref for TempRepo {
+    type Target = Repository;
+
+    fn deref(&self) -> &Repository {
+        &self.repository
+    }
+}
@@ -1,77 +1,164 @@
+use crate::error::*;
 use crate::hash::*;
 use crate::object::*;
 use crate::index::*;
-use crate::take_hash;
+use crate::index_extension::*;
+use crate::repository::*;
+use crate::util::*;
 
 use std::fmt;
 
-use std::str::FromStr;
-use std::path::{PathBuf, Component};
-use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf, Component};
+use std::collections::{BTreeMap, HashMap};
 
-
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct TreeCache {
     pub blobs: Vec<TreeEntry>,
     pub trees: HashMap<PathBuf, TreeCache>
 }
 
-#[derive(Debug)]
-pub struct TreeEntry {
-    pub key: Hash,
-    pub mode: ObjectKind,
-    pub name: PathBuf,
+#[derive(Debug, Clone)]
+pub struct Tree {
+    pub entries: Vec<TreeEntry>
 }
-impl TreeEntry {
-    pub fn new(key: Hash, mode: ObjectKind, name: PathBuf) -> Self {
-        TreeEntry { key, mode, name }
+
+impl Tree {
+    pub fn new(entries: Vec<TreeEntry>) -> Self {
+        Self { entries }
     }
 
-    pub fn _read(bytes: &mut &[u8]) -> Option<Self> {
-        if let Some(pos) = bytes.iter().position(|&x| x == 0) {
-            let (content, rest) = bytes.split_at(pos);
-            let data: Vec<&str> = str::from_utf8(content).ok()?
-                .split(" ")
-                .collect();
-            for (i, s) in data.clone().into_iter().enumerate() {
-                println!("{} {}", i, s);
-            }
+    pub fn read(repository: &Repository, hash: &Hash) -> Result<Self, Error> {
+        let object = repository.read_object_of_kind(hash, ObjectType::Tree)?;
+        Tree::parse(&object.content)
+    }
 
-            let mode: ObjectKind = ObjectKind::from_str(data[0]).unwrap();
-            let name = data[1].parse().unwrap();
+    /* Parses the content of a tree object, i.e. without the object header */
+    pub fn parse(mut bytes: &[u8]) -> Result<Self, Error> {
+        let mut entries = Vec::new();
+        while !bytes.is_empty() {
+            entries.push(TreeEntry::read(&mut bytes)?);
+        }
 
-            *bytes = &rest[1..];
+        Ok(Tree { entries })
+    }
 
-            let key = take_hash(bytes);
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.entries
+            .iter()
+            .flat_map(|entry| entry.as_bytes())
+            .collect()
+    }
 
-            Some(
-                TreeEntry {
-                    mode,
-                    name,
-                    key
-                }
-            )
+    /* Looks up the entry at `path` by walking down through the sub trees */
+    pub fn find_entry(&self, repository: &Repository, path: &Path) -> Result<Option<TreeEntry>, Error> {
+        let mut components = path.components();
+        let Some(first) = components.next() else {
+            return Ok(None)
+        };
+        let rest = components.as_path();
+
+        let Some(entry) = self.entries.iter().find(|e| e.name.as_os_str() == first.as_os_str()) else {
+            return Ok(None)
+        };
+
+        if rest.as_os_str().is_empty() {
+            Ok(Some(entry.clone()))
+        } else if entry.kind() == ObjectType::Tree {
+            Tree::read(repository, &entry.key)?.find_entry(repository, rest)
         } else {
-            None
+            Ok(None)
+        }
+    }
+
+    /*
+     * Lists every non-tree entry below this tree by its full path,
+     * e.g. "src/main.rs", descending into the sub trees.
+     */
+    pub fn flatten(&self, repository: &Repository, prefix: &str) -> Result<BTreeMap<String, TreeEntry>, Error> {
+        let mut entries = BTreeMap::new();
+        for entry in &self.entries {
+            let path = format!("{}{}", prefix, entry.name.to_string_lossy());
+            if entry.kind() == ObjectType::Tree {
+                let sub_tree = Tree::read(repository, &entry.key)?;
+                entries.extend(sub_tree.flatten(repository, &format!("{}/", path))?);
+            } else {
+                entries.insert(path, entry.clone());
+            }
         }
+        Ok(entries)
+    }
+
+    /*
+     * Git sorts tree entries by name, comparing directories as if
+     * their name had a trailing '/'.
+     */
+    pub fn sort(&mut self) {
+        self.entries.sort_by_key(|entry| entry.sort_key());
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TreeEntry {
+    pub key: Hash,
+    pub mode: FileMode,
+    pub name: PathBuf,
+}
+
+impl TreeEntry {
+    pub fn new(key: Hash, mode: FileMode, name: PathBuf) -> Self {
+        TreeEntry { key, mode, name }
+    }
+
+    pub fn read(bytes: &mut &[u8]) -> Result<Self, Error> {
+        let pos = bytes.iter()
+            .position(|&x| x == 0)
+            .ok_or(corrupt("Tree entry is missing its name terminator".into()))?;
+        let (content, rest) = bytes.split_at(pos);
+
+        let space = content.iter()
+            .position(|&x| x == b' ')
+            .ok_or(corrupt("Tree entry is missing its mode".into()))?;
+        let (mode, name) = content.split_at(space);
+        let mode: FileMode = String::from_utf8_lossy(mode).parse()?;
+        let name = PathBuf::from(OsStr::from_bytes(&name[1..]));
+
+        if rest.len() < 21 {
+            return Err(corrupt(format!("Tree entry {:?} is truncated", name)))
+        }
+        *bytes = &rest[1..];
+        let key = take_hash(bytes);
+
+        Ok(TreeEntry { mode, name, key })
     }
 
     pub fn as_bytes(&self) -> Vec<u8> {
-        let mut bytes = format!(
-            "{:06} {}\0", 
-            self.mode as i32,
-            self.name.to_string_lossy(),
-        ).into_bytes();
+        let mut bytes = format!("{:o} ", self.mode).into_bytes();
+        bytes.extend_from_slice(self.name.as_os_str().as_bytes());
+        bytes.push(0);
         bytes.extend_from_slice(&self.key.0);
         bytes
     }
+
+    pub fn kind(&self) -> ObjectType {
+        self.mode.object_type()
+    }
+
+    pub fn sort_key(&self) -> Vec<u8> {
+        let mut key = self.name.as_os_str().as_bytes().to_vec();
+        if self.kind() == ObjectType::Tree {
+            key.push(b'/');
+        }
+        key
+    }
 }
 
 impl fmt::Display for TreeEntry {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:06} {} {}    {}", 
-            self.mode as i32,
+        write!(f, "{:06o} {} {}    {}", 
             self.mode,
+            self.kind(),
             self.key,
             self.name.to_string_lossy())
     }
@@ -88,20 +175,21 @@ impl TreeCache {
     pub fn from_index(index: Index) -> Self {
         let mut cache = TreeCache::new();
 
-        for entry in index.entries {
+        /* Paths only intended to be added are not part of the tree yet */
+        for entry in index.entries.into_iter().filter(|entry| !entry.intent_to_add()) {
+            let mode = entry.mode();
             let path = PathBuf::from(&entry.name);
             let components: Vec<Component> = path.components().collect();
-            if components.len() > 1 {
-                let (base, rest) = components.split_first().expect("ERROR: Split first should always work in len > 1");
+            if let Some((base, rest)) = components.split_first().filter(|(_, rest)| !rest.is_empty()) {
                 let base: PathBuf = base.into();
                 let rest: PathBuf = rest.iter().collect();
 
                 let sub_cache = cache.get_or_create_tree_mut(base);
 
-                let entry = TreeEntry::new(entry.key, ObjectKind::Blob, rest);
+                let entry = TreeEntry::new(entry.key, mode, rest);
                 sub_cache.add_tree(entry);
             } else {
-                let blob = TreeEntry::new(entry.key, ObjectKind::Blob, entry.name.into());
+                let blob = TreeEntry::new(entry.key, mode, entry.name.into());
                 cache.add_blob(blob);
             }
         }
@@ -110,7 +198,7 @@ impl TreeCache {
     }
 
     pub fn get_or_create_tree_mut(&mut self, tree_name: PathBuf) -> &mut TreeCache {
-        self.trees.entry(tree_name).or_insert(TreeCache::new())
+        self.trees.entry(tree_name).or_default()
     }
 
     pub fn add_blob(&mut self, entry: TreeEntry) {
@@ -120,63 +208,121 @@ impl TreeCache {
     pub fn add_tree(&mut self, entry: TreeEntry) {
         let path = PathBuf::from(&entry.name);
         let components: Vec<Component> = path.components().collect();
-        if components.len() > 1 {
-            let (base, rest) = components.split_first().expect("ERROR: Split first should always work in len > 1");
+        if let Some((base, rest)) = components.split_first().filter(|(_, rest)| !rest.is_empty()) {
             let base: PathBuf = base.into();
             let rest: PathBuf = rest.iter().collect();
 
             let entry = TreeEntry::new(entry.key, entry.mode, rest);
-            let sub_cache = self.trees.entry(base).or_insert(TreeCache::new());
+            let sub_cache = self.trees.entry(base).or_default();
             sub_cache.add_tree(entry);
         } else {
-            let blob = TreeEntry::new(entry.key, ObjectKind::Blob, entry.name);
+            let blob = TreeEntry::new(entry.key, entry.mode, entry.name);
             self.add_blob(blob);
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    struct Tree {
-        pub entries: Vec<TreeEntry>
-    }
+    /* Writes the tree and its sub trees as objects, returning the root tree */
+    pub fn write(self, repository: &Repository) -> Result<Hash, Error> {
+        let mut tree = Tree::new(self.blobs);
+
+        for (dir, cache) in self.trees {
+            let hash = cache.write(repository)?;
 
-    impl Tree {
-        pub fn _new() -> Self {
-            let entries = Vec::new();
-            Self { entries }
+            tree.entries.push(TreeEntry::new(hash, FileMode::Tree, dir));
         }
 
-        pub fn _read(bytes: &mut &[u8]) -> Self{
-            Tree::_read_header(bytes);
+        tree.sort();
 
-            let mut entries = Vec::new();
-            while let Some(entry) = TreeEntry::_read(bytes) {
-                entries.push(entry);
-            };
+        repository.write_object(ObjectType::Tree, tree.to_bytes())
+    }
+}
 
-            Tree { entries }
-        }
+/*
+ * Writes the tree of the directory whose index entries are `entries`, each
+ * name starting with the directory's path of `prefix_len` bytes, unless
+ * `cached` already holds it. Invalid directories are written again and
+ * become valid, except those with paths only intended to be added, which
+ * git leaves invalid too. Returns the tree, or None for a directory with
+ * nothing to record, which is not written.
+ */
+fn update_cache_tree(
+    repository: &Repository,
+    cached: &mut CacheTree,
+    entries: &[IndexEntry],
+    prefix_len: usize,
+) -> Result<Option<Hash>, Error> {
+    if let Some(hash) = cached.hash.as_ref().filter(|_| cached.entry_count as usize == entries.len()) {
+        return Ok(Some(hash.clone()))
+    }
 
-        pub fn _read_header(bytes: &mut &[u8]) {
-            if let Some(pos) = bytes.iter().position(|&x| x == 0) {
-                let (content, rest) = bytes.split_at(pos);
-                let data: &str = str::from_utf8(content).unwrap();
-                *bytes = &rest[1..];
-                println!("{} |", data);
+    let mut tree = Tree::new(Vec::new());
+    let mut subtrees = Vec::new();
+    let mut complete = true;
+    let mut position = 0;
+    while let Some(entry) = entries.get(position) {
+        let name = &entry.name[prefix_len..];
+        let Some((dir, _)) = name.split_once('/') else {
+            match entry.intent_to_add() {
+                true => complete = false,
+                false => tree.entries.push(TreeEntry::new(entry.key.clone(), entry.mode(), name.into()))
             }
+            position += 1;
+            continue
+        };
+
+        /* The entries below a directory are next to each other in the index */
+        let dir_prefix = &entry.name[..prefix_len + dir.len() + 1];
+        let end = position + entries[position..].iter().take_while(|entry| entry.name.starts_with(dir_prefix)).count();
+        let mut subtree = cached.subtrees.iter()
+            .position(|(name, _)| name == dir)
+            .map_or_else(CacheTree::invalid, |index| cached.subtrees.swap_remove(index).1);
+
+        let hash = update_cache_tree(repository, &mut subtree, &entries[position..end], dir_prefix.len())?;
+        complete &= subtree.is_valid();
+        if let Some(hash) = hash {
+            tree.entries.push(TreeEntry::new(hash, FileMode::Tree, dir.into()));
         }
+        subtrees.push((dir.to_string(), subtree));
+        position = end;
+    }
 
-        pub fn _to_bytes(&self) -> Vec<u8> {
-            self.entries
-                .iter()
-                .map(|entry| entry.as_bytes())
-                .flatten()
-                .collect()
-        }
+    /* Sub directories are kept in git's order, shorter names first */
+    subtrees.sort_by(|(a, _), (b, _)| a.len().cmp(&b.len()).then(a.cmp(b)));
+    if tree.entries.is_empty() {
+        *cached = CacheTree { subtrees, ..CacheTree::invalid() };
+        return Ok(None)
     }
 
+    tree.sort();
+    let hash = repository.write_object(ObjectType::Tree, tree.to_bytes())?;
+    *cached = match complete {
+        true => CacheTree { entry_count: entries.len() as i32, hash: Some(hash.clone()), subtrees },
+        false => CacheTree { subtrees, ..CacheTree::invalid() }
+    };
+    Ok(Some(hash))
+}
+
+/*
+ * Writes the tree of the index like `git write-tree`. Only the directories
+ * invalidated in the index's cached trees since they were last written
+ * are written again, and the cached trees are updated for the next time.
+ */
+pub fn write_tree(repository: &Repository, index: &mut Index) -> Result<Hash, Error> {
+    let mut cached = index.cache_tree().cloned().unwrap_or_else(CacheTree::invalid);
+    let hash = match update_cache_tree(repository, &mut cached, &index.entries, 0)? {
+        Some(hash) => hash,
+        /* Unlike other directories, the root is written when empty */
+        None => repository.write_object(ObjectType::Tree, Vec::new())?
+    };
+    index.set_cache_tree(cached);
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
     use super::*;
+
+    use crate::test_util::*;
     use std::fs;
     use crate::compress::*;
 
@@ -184,25 +330,101 @@ mod tests {
     fn parse_entry_hash_from_staging_area() {
         let filename = String::from("examples/tree");
         let content = fs::read(&filename).unwrap();
-        let mut decoded = &_decompress(content).unwrap()[..];
+        let object = parse_object(&decompress(content).unwrap()).unwrap();
 
-        let index = Tree::_read(&mut decoded);
-        let index_entry = index.entries[4].to_string();
+        let tree = Tree::parse(&object.content).unwrap();
+        let tree_entry = tree.entries[4].to_string();
 
         let expected = "040000 tree f37ef49b903a6db9fa814b04f8226569f6d0f592    examples";
-        assert_eq!(index_entry, expected);
+        assert_eq!(tree_entry, expected);
     }
 
     #[test]
     fn create_tree_hash_from_index() {
         let filename = String::from("examples/index_with_tree");
         let content = fs::read(&filename).unwrap();
-        let mut decoded = &_decompress(content).unwrap()[..];
+        let object = parse_object(&decompress(content).unwrap()).unwrap();
 
-        let tree = Tree::_read(&mut decoded)._to_bytes();
-        let key = hash_object(ObjectKind::Tree, tree).to_string();
+        let tree = Tree::parse(&object.content).unwrap().to_bytes();
+        let key = hash_object(ObjectType::Tree, &tree).to_string();
 
         let expected = String::from("f37ef49b903a6db9fa814b04f8226569f6d0f592");
         assert_eq!(key, expected);
     }
+
+    #[test]
+    fn tree_round_trip_with_arbitrary_modes() {
+        let key = Hash::from_hex("d9fa2b8cd651190f6ff5932113491d0a2995b116").unwrap();
+        let mut tree = Tree::new(vec![
+            TreeEntry::new(key.clone(), FileMode::Executable, "run.sh".into()),
+            TreeEntry::new(key.clone(), FileMode::Symlink, "link".into()),
+            TreeEntry::new(key.clone(), FileMode::Gitlink, "vendor".into()),
+            TreeEntry::new(key.clone(), FileMode::Tree, "src".into()),
+            TreeEntry::new(key.clone(), FileMode::Regular, "src.rs".into()),
+        ]);
+        tree.sort();
+
+        let bytes = tree.to_bytes();
+        let parsed = Tree::parse(&bytes).unwrap();
+
+        let names: Vec<_> = parsed.entries.iter().map(|e| e.name.to_string_lossy().to_string()).collect();
+        assert_eq!(names, ["link", "run.sh", "src.rs", "src", "vendor"]);
+        assert_eq!(parsed.entries[1].mode, FileMode::Executable);
+        assert_eq!(parsed.entries[3].kind(), ObjectType::Tree);
+        assert_eq!(parsed.to_bytes(), bytes);
+        assert!(bytes.starts_with(b"120000 link\0"));
+    }
+
+    #[test]
+    fn reject_non_canonical_modes() {
+        let entry = |mode: &str| {
+            let mut bytes = format!("{} file\0", mode).into_bytes();
+            bytes.extend_from_slice(&[1; 20]);
+            Tree::parse(&bytes)
+        };
+
+        assert_eq!(entry("100644").unwrap().entries[0].mode, FileMode::Regular);
+        assert_eq!(entry("40000").unwrap().entries[0].mode, FileMode::Tree);
+        assert!(entry("100664").is_err());
+        assert!(entry("040000").is_err());
+        assert!(entry("644").is_err());
+    }
+
+    #[test]
+    fn write_tree_reuses_cached_trees() {
+        let repository = TempRepo::new("write-tree");
+        let entry = |name: &str| {
+            let key = repository.write_object(ObjectType::Blob, name.as_bytes().to_vec()).unwrap();
+            IndexEntry::create(key, FileMode::Regular, name, Path::new("examples/blob.c")).unwrap()
+        };
+        let mut index = Index::new(vec![entry("a/b/file"), entry("a/c"), entry("d/e"), entry("top"), entry("only/new")]);
+        index.entries[3].set_intent_to_add(true);
+
+        let tree = write_tree(&repository, &mut index).unwrap();
+        let expected = TreeCache::from_index(Index::new(vec![entry("a/b/file"), entry("a/c"), entry("d/e"), entry("top")]))
+            .write(&repository)
+            .unwrap();
+        assert_eq!(tree, expected);
+        let cached = index.cache_tree().unwrap().clone();
+        assert!(!cached.is_valid());
+        let names: Vec<&str> = cached.subtrees.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["a", "d", "only"]);
+        assert_eq!(cached.subtrees[0].1.entry_count, 2);
+        assert!(!cached.subtrees[2].1.is_valid());
+        assert!(!repository.has_object(&hash_object(ObjectType::Tree, &[])).unwrap());
+
+        /* A cached tree is trusted as is, until an entry below it changes */
+        let mut index = index.extend(vec![entry("top")]);
+        let bogus = Hash([7; 20]);
+        if let Some(Extension::Tree(cached)) = index.extensions.first_mut() {
+            cached.subtrees[1].1.hash = Some(bogus.clone());
+        }
+        let tree = write_tree(&repository, &mut index).unwrap();
+        let tree = Tree::read(&repository, &tree).unwrap();
+        assert_eq!(tree.entries[1].key, bogus);
+
+        let mut index = index.extend(vec![entry("d/e")]);
+        let tree = write_tree(&repository, &mut index).unwrap();
+        assert_eq!(tree, expected);
+    }
 }
@@ -0,0 +1,53 @@
+use crate::error::*;
+use crate::repository::*;
+
+/*
+ * Rewrites the index, in `index_version` if given. Version 3 is only kept
+ * while an entry has extended flags, otherwise version 2 is written.
+ */
+pub fn update_index(repository: &Repository, index_version: Option<u32>) -> Result<(), Error> {
+    let mut index = repository.read_index()?;
+    if let Some(version) = index_version {
+        index.header.version = version;
+    }
+    repository.write_index(&index)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test_util::*;
+
+    use crate::index::*;
+    use crate::object::*;
+
+    use std::fs;
+
+    #[test]
+    fn convert_between_index_versions() {
+        let repository = TempRepo::new("update-index");
+        let dir = repository.path();
+        let mut entries = Vec::new();
+        for name in ["dir/file", "dir/file2", "dir/other", "top"] {
+            fs::create_dir_all(dir.join("dir")).unwrap();
+            fs::write(dir.join(name), name).unwrap();
+            let key = repository.write_object(ObjectType::Blob, name.as_bytes().to_vec()).unwrap();
+            entries.push(IndexEntry::create(key, FileMode::Regular, name, &dir.join(name)).unwrap());
+        }
+        entries[1].set_skip_worktree(true);
+        repository.write_index(&Index::new(entries)).unwrap();
+        let version = || fs::read(repository.index_path()).unwrap()[7];
+        assert_eq!(version(), 3);
+
+        update_index(&repository, Some(4)).unwrap();
+        assert_eq!(version(), 4);
+        let index = repository.read_index().unwrap();
+        let names: Vec<&str> = index.entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["dir/file", "dir/file2", "dir/other", "top"]);
+        assert!(index.entries[1].skip_worktree());
+
+        update_index(&repository, Some(2)).unwrap();
+        assert_eq!(version(), 3);
+    }
+}
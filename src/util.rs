@@ -1,10 +1,5 @@
 use crate::hash::*;
 
-use std::fs;
-use std::io;
-
-use std::path::Path;
-
 pub fn take_u16(input: &mut &[u8]) -> u16 {
     let (int_bytes, rest) = input.split_at(size_of::<u16>());
     *input = rest;
@@ -29,10 +24,183 @@ pub fn take_n_bytes(input: &mut &[u8], n: usize) -> Vec<u8> {
     bytes.to_vec()
 }
 
-pub fn write_to_file(path_str: String, content: Vec<u8>) -> Result<(), io::Error> {
-    let path = Path::new(&path_str);
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)?;
+/*
+ * Reads a variable-length integer, as used for the base distances of
+ * OFS_DELTA pack entries and version 4 index names, returning it and the
+ * position after it. Every byte holds 7 bits, most significant first, and
+ * each continuation adds one so that no number has two encodings. None
+ * if it is truncated or does not fit.
+ */
+pub fn decode_varint(data: &[u8], position: usize) -> Option<(usize, usize)> {
+    let mut position = position;
+    let mut byte = *data.get(position)?;
+    let mut value = (byte & 0x7f) as usize;
+    while byte & 0x80 != 0 {
+        position += 1;
+        byte = *data.get(position)?;
+        value = value.checked_add(1)?.checked_mul(128)? | (byte & 0x7f) as usize;
+    }
+    Some((value, position + 1))
+}
+
+/* Encodes a number as read by `decode_varint` */
+pub fn encode_varint(mut value: usize) -> Vec<u8> {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    value >>= 7;
+    while value > 0 {
+        value -= 1;
+        bytes.push(0x80 | (value & 0x7f) as u8);
+        value >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
+/* Matches one character against a bracket expression such as "[a-z]" or "[!0-9]" */
+fn match_bracket(pattern: &[u8], c: u8, ignore_case: bool) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some(b'!' | b'^'));
+    if negated {
+        i += 1;
+    }
+
+    let same = |a: u8, b: u8| a == b || (ignore_case && a.eq_ignore_ascii_case(&b));
+    let mut matched = false;
+    let mut first = true;
+    while let Some(&p) = pattern.get(i) {
+        if p == b']' && !first {
+            return Some((matched != negated, i + 1))
+        }
+        first = false;
+
+        let p = if p == b'\\' {
+            i += 1;
+            *pattern.get(i)?
+        } else {
+            p
+        };
+        if pattern.get(i + 1) == Some(&b'-') && pattern.get(i + 2).is_some_and(|&end| end != b']') {
+            let in_range = |c: u8| (p..=pattern[i + 2]).contains(&c);
+            matched |= in_range(c)
+                || (ignore_case && (in_range(c.to_ascii_lowercase()) || in_range(c.to_ascii_uppercase())));
+            i += 3;
+        } else {
+            matched |= same(p, c);
+            i += 1;
+        }
+    }
+    None
+}
+
+/*
+ * Matches `text` against a glob pattern: `?` and `*` match any character
+ * and any run of characters except '/', "[...]" matches a set of characters
+ * and a backslash escapes the next character. A double star also matches
+ * across '/', and followed by a slash it matches zero or more directories.
+ */
+pub fn wildmatch(pattern: &[u8], text: &[u8], ignore_case: bool) -> bool {
+    if let Some(rest) = pattern.strip_prefix(b"**") {
+        if let Some(after_slash) = rest.strip_prefix(b"/")
+            && wildmatch(after_slash, text, ignore_case) {
+            return true
+        }
+        return (0..=text.len()).any(|i| wildmatch(rest, &text[i..], ignore_case))
+    }
+
+    match (pattern.first(), text.first()) {
+        (None, _) => text.is_empty(),
+        (Some(b'*'), _) => {
+            let run = text.iter().position(|&c| c == b'/').unwrap_or(text.len());
+            (0..=run).any(|i| wildmatch(&pattern[1..], &text[i..], ignore_case))
+        },
+        (Some(b'?'), Some(&c)) if c != b'/' => wildmatch(&pattern[1..], &text[1..], ignore_case),
+        (Some(b'['), Some(&c)) if c != b'/' => match match_bracket(pattern, c, ignore_case) {
+            Some((true, length)) => wildmatch(&pattern[length..], &text[1..], ignore_case),
+            Some((false, _)) => false,
+            /* An unterminated bracket is a literal '[' */
+            None => c == b'[' && wildmatch(&pattern[1..], &text[1..], ignore_case)
+        },
+        (Some(b'\\'), Some(&c)) if pattern.len() > 1 => {
+            pattern[1] == c && wildmatch(&pattern[2..], &text[1..], ignore_case)
+        },
+        (Some(&p), Some(&c)) if p == c || (ignore_case && p.eq_ignore_ascii_case(&c)) => {
+            wildmatch(&pattern[1..], &text[1..], ignore_case)
+        },
+        _ => false
+    }
+}
+
+/*
+ * The headers of a commit or tag object in order. A header line without a
+ * space has no value, which is kept apart from an empty one to write the
+ * object back as it was.
+ */
+pub type Headers = Vec<(String, Option<Vec<u8>>)>;
+
+/*
+ * Splits the content of a commit or tag object into its headers and message.
+ * Headers are "<key> <value>" lines, and a value continues on the following
+ * lines that start with a single space (e.g. `gpgsig` and `mergetag`).
+ * The message starts after the first empty line, and is None if there is
+ * no such line.
+ */
+pub fn split_headers(content: &[u8]) -> (Headers, Option<Vec<u8>>) {
+    let mut headers: Headers = Vec::new();
+    let mut rest = content;
+
+    while !rest.is_empty() {
+        if let Some(message) = rest.strip_prefix(b"\n") {
+            return (headers, Some(message.to_vec()))
+        }
+
+        let end = rest.iter().position(|&byte| byte == b'\n').unwrap_or(rest.len());
+        let line = &rest[..end];
+        rest = rest.get(end + 1..).unwrap_or_default();
+
+        match (line.strip_prefix(b" "), headers.last_mut()) {
+            (Some(continuation), Some((_, Some(value)))) => {
+                value.push(b'\n');
+                value.extend_from_slice(continuation);
+            },
+            _ => {
+                let (key, value) = match line.iter().position(|&byte| byte == b' ') {
+                    Some(space) => (&line[..space], Some(line[space + 1..].to_vec())),
+                    None => (line, None)
+                };
+                headers.push((String::from_utf8_lossy(key).to_string(), value));
+            }
+        }
+    }
+
+    (headers, None)
+}
+
+/* Writes a header line, continuing a multi-line value on lines starting with a space */
+pub fn write_header(output: &mut Vec<u8>, key: &str, value: &[u8]) {
+    output.extend_from_slice(key.as_bytes());
+    output.push(b' ');
+    for &byte in value {
+        output.push(byte);
+        if byte == b'\n' {
+            output.push(b' ');
+        }
+    }
+    output.push(b'\n');
+}
+
+/* Writes the headers, then the message after an empty line if there is one */
+pub fn write_headers_and_message(output: &mut Vec<u8>, headers: &Headers, message: Option<&[u8]>) {
+    for (key, value) in headers {
+        match value {
+            Some(value) => write_header(output, key, value),
+            None => {
+                output.extend_from_slice(key.as_bytes());
+                output.push(b'\n');
+            }
+        }
+    }
+    if let Some(message) = message {
+        output.push(b'\n');
+        output.extend_from_slice(message);
     }
-    fs::write(path, content)
 }
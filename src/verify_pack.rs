@@ -0,0 +1,102 @@
+use crate::error::*;
+use crate::object::*;
+use crate::pack::*;
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/* The number of deltas to apply to reach the object at `offset` */
+fn delta_depth(pack: &Pack, offset: u64) -> Result<usize, Error> {
+    let mut depth = 0;
+    let mut entry = pack.read_entry(offset)?;
+    while let Some(base) = entry.base {
+        depth += 1;
+        if depth > pack.index.len() {
+            return Err(corrupt(format!("{}: delta chain loops", pack.path.display())))
+        }
+        entry = pack.read_entry(base)?;
+    }
+    Ok(depth)
+}
+
+/*
+ * Verifies the pack of the index at `path` (either the .idx or the .pack):
+ * the checksums, the CRC of every entry and that every object hashes to
+ * its name. With `verbose` it lists the objects like `git verify-pack -v`,
+ * "<hash> <type> <size> <size in pack> <offset> [<depth> <base>]" in pack
+ * order, followed by a histogram of delta chain lengths.
+ */
+pub fn verify_pack(path: &Path, verbose: bool) -> Result<(), Error> {
+    let pack = Pack::open(&path.with_extension("idx"))?;
+    pack.verify()?;
+
+    let mut entries: Vec<(u64, usize)> = pack.index.offsets.iter().copied().zip(0..).collect();
+    entries.sort();
+    let by_offset: HashMap<u64, usize> = entries.iter().copied().collect();
+    let end = pack.data()?.len() as u64 - 20;
+
+    let mut chains: BTreeMap<usize, usize> = BTreeMap::new();
+    for (position, &(offset, index)) in entries.iter().enumerate() {
+        let hash = &pack.index.hashes[index];
+        let object = pack.read_at(offset)?;
+        if hash_object(object.kind, &object.content) != *hash {
+            return Err(corrupt(format!("{}: object {} does not match its hash", pack.path.display(), hash)))
+        }
+        if !verbose {
+            continue
+        }
+
+        let entry = pack.read_entry(offset)?;
+        let kind = object.kind.to_string();
+        let stored = entries.get(position + 1).map_or(end, |&(next, _)| next) - offset;
+        match entry.base {
+            Some(base) => {
+                let depth = delta_depth(&pack, offset)?;
+                let base = by_offset.get(&base)
+                    .map(|&index| &pack.index.hashes[index])
+                    .ok_or_else(|| corrupt(format!("Delta base at {} is not in the index", base)))?;
+                println!("{} {:<6} {} {} {} {} {}", hash, kind, entry.size, stored, offset, depth, base);
+                *chains.entry(depth).or_default() += 1;
+            },
+            None => {
+                println!("{} {:<6} {} {} {}", hash, kind, entry.size, stored, offset);
+                *chains.entry(0).or_default() += 1;
+            }
+        }
+    }
+
+    if verbose {
+        for (depth, count) in chains {
+            let objects = if count == 1 { "object" } else { "objects" };
+            match depth {
+                0 => println!("non delta: {} {}", count, objects),
+                depth => println!("chain length = {}: {} {}", depth, count, objects)
+            }
+        }
+        println!("{}: ok", pack.path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test_util::*;
+
+    use std::fs;
+
+    #[test]
+    fn verify_example_packs_and_detect_corruption() {
+        verify_pack(Path::new("examples/pack-ofs-delta.idx"), true).unwrap();
+        verify_pack(Path::new("examples/pack-ref-delta.pack"), false).unwrap();
+
+        let dir = TempDir::new("verify-pack");
+        let mut data = fs::read("examples/pack-ofs-delta.pack").unwrap();
+        data[20] ^= 0xff;
+        fs::write(dir.join("pack.pack"), data).unwrap();
+        fs::copy("examples/pack-ofs-delta.idx", dir.join("pack.idx")).unwrap();
+
+        assert!(matches!(verify_pack(&dir.join("pack.idx"), false), Err(Error::CorruptObject(_))));
+    }
+}